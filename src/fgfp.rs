@@ -0,0 +1,283 @@
+//! Reading the route of a .fgfp file back, like FlightGear's route manager does.
+//!
+//! Only the properties this crate writes are read, so a route can be checked after it's written.
+
+use std::{error::Error, io::Read};
+
+use xml::{reader::XmlEvent, EventReader};
+
+use crate::{AltRestrict, SpeedRestrict};
+
+/// A waypoint of the route of a .fgfp file.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct FgfpWaypoint {
+    /// The type of the waypoint, like `basic`, `navaid`, `via`, `hold` or `runway`. The legacy
+    /// format doesn't have one.
+    pub kind: Option<String>,
+    /// The ident of the waypoint, the runway of a `runway` waypoint or the exit fix of a `via`.
+    pub ident: Option<String>,
+    pub lon: Option<f64>,
+    pub lat: Option<f64>,
+    /// The altitude in feet, written in feet or as a flight level.
+    pub altitude_ft: Option<f64>,
+    pub alt_restrict: Option<AltRestrict>,
+    pub speed_restrict: Option<SpeedRestrict>,
+    pub airway: Option<String>,
+}
+
+/// Reads the waypoints of the route of a .fgfp file.
+///
+/// # Errors
+/// This function fails if the contents aren't valid xml, or if a property of a waypoint can't be
+/// read.
+///
+/// # Example
+///
+/// ```
+/// # use kml_to_fgfp::{fgfp, AltRestrict, EventReader, SpeedRestrict};
+/// let xml = r#"<PropertyList><version type="int">2</version><route>
+///     <wp>
+///         <type type="string">basic</type>
+///         <alt-restrict type="string">below</alt-restrict>
+///         <flight-level type="int">120</flight-level>
+///         <speed-restrict type="string">mach</speed-restrict>
+///         <speed type="double">0.78</speed>
+///         <ident type="string">ARSOT</ident>
+///         <lon type="double">-56.7</lon>
+///         <lat type="double">-34.4</lat>
+///     </wp>
+/// </route></PropertyList>"#;
+///
+/// let route = fgfp::read_route(EventReader::new(xml.as_bytes())).unwrap();
+/// assert_eq!(route[0].ident.as_deref(), Some("ARSOT"));
+/// assert_eq!(route[0].altitude_ft, Some(12000.0));
+/// assert_eq!(route[0].alt_restrict, Some(AltRestrict::Below));
+/// assert_eq!(route[0].speed_restrict, Some(SpeedRestrict::Mach(0.78)));
+/// ```
+pub fn read_route<R: Read>(parser: EventReader<R>) -> Result<Vec<FgfpWaypoint>, Box<dyn Error>> {
+    let mut route = Vec::new();
+    let mut in_route = false;
+    let mut waypoint: Option<(FgfpWaypoint, Option<String>, Option<String>)> = None;
+    let mut element = String::new();
+
+    for event in parser {
+        match event? {
+            XmlEvent::StartElement { name, .. } => {
+                element = name.local_name;
+                match element.as_str() {
+                    "route" => in_route = true,
+                    "wp" if in_route => waypoint = Some((FgfpWaypoint::default(), None, None)),
+                    _ => (),
+                }
+            }
+            XmlEvent::Characters(value) => {
+                if let Some((waypoint, speed_restrict, speed)) = waypoint.as_mut() {
+                    let value = value.trim().to_string();
+                    set(waypoint, &element, &value)?;
+
+                    match element.as_str() {
+                        "speed-restrict" => *speed_restrict = Some(value),
+                        "speed" => *speed = Some(value),
+                        _ => (),
+                    }
+                }
+            }
+            XmlEvent::EndElement { name } => {
+                element.clear();
+                match name.local_name.as_str() {
+                    "route" => in_route = false,
+                    "wp" => {
+                        if let Some((mut waypoint, speed_restrict, speed)) = waypoint.take() {
+                            waypoint.speed_restrict = read_speed(speed_restrict, speed)?;
+                            route.push(waypoint);
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Ok(route)
+}
+
+/// Internal function that sets the property `element` of a waypoint to its `value`.
+fn set(waypoint: &mut FgfpWaypoint, element: &str, value: &str) -> Result<(), Box<dyn Error>> {
+    let number = |value: &str| -> Result<f64, String> {
+        value
+            .parse()
+            .map_err(|_| format!("Invalid {element} `{value}` in the route"))
+    };
+
+    match element {
+        "type" => waypoint.kind = Some(value.to_string()),
+        "ident" | "to" => waypoint.ident = Some(value.to_string()),
+        "airway" => waypoint.airway = Some(value.to_string()),
+        "lon" | "longitude-deg" => waypoint.lon = Some(number(value)?),
+        "lat" | "latitude-deg" => waypoint.lat = Some(number(value)?),
+        "altitude-ft" => waypoint.altitude_ft = Some(number(value)?),
+        "flight-level" => waypoint.altitude_ft = Some(number(value)? * 100.0),
+        "alt-restrict" => waypoint.alt_restrict = Some(value.parse()?),
+        _ => (),
+    }
+
+    Ok(())
+}
+
+/// Internal function that builds a speed restriction from the `speed-restrict` and `speed`
+/// properties of a waypoint.
+fn read_speed(
+    speed_restrict: Option<String>,
+    speed: Option<String>,
+) -> Result<Option<SpeedRestrict>, Box<dyn Error>> {
+    let (kind, speed) = match (speed_restrict, speed) {
+        (Some(kind), Some(speed)) => (kind, speed),
+        (None, None) => return Ok(None),
+        _ => return Err("A speed restriction needs both speed-restrict and speed".into()),
+    };
+
+    let restriction = match kind.as_str() {
+        "mach" => format!("M{speed}"),
+        _ => format!("{kind} {speed}"),
+    };
+
+    Ok(Some(restriction.parse()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{transform_route, EmitterConfig, RouteOptions};
+
+    /// Converts a .kml file and reads the route that was written.
+    fn round_trip(kml: &str, options: &RouteOptions) -> Vec<FgfpWaypoint> {
+        let mut output = Vec::new();
+        let mut writer = EmitterConfig::new().create_writer(&mut output);
+        crate::write_start_of_tree(&mut writer, options.target).unwrap();
+        transform_route(
+            EventReader::new(kml.as_bytes()),
+            &mut writer,
+            &None,
+            &None,
+            options,
+        )
+        .unwrap();
+        crate::close_tree(&mut writer).unwrap();
+
+        read_route(EventReader::new(output.as_slice())).unwrap()
+    }
+
+    /// Builds a fix of a .kml file with the `speed` field in its `ExtendedData`.
+    fn fix(name: &str, speed: Option<&str>, coordinates: &str) -> String {
+        let data = speed
+            .map(|s| {
+                format!(
+                    "<ExtendedData><Data name=\"speed\"><value>{s}</value></Data></ExtendedData>"
+                )
+            })
+            .unwrap_or_default();
+
+        format!(
+            "<Placemark><name>{name}</name><styleUrl>#FixMark</styleUrl>{data}\
+            <Point><coordinates>{coordinates}</coordinates></Point></Placemark>"
+        )
+    }
+
+    fn kml(placemarks: &[String]) -> String {
+        format!(
+            "<?xml version=\"1.0\"?><kml><Document>{}</Document></kml>",
+            placemarks.concat()
+        )
+    }
+
+    #[test]
+    fn reads_back_the_written_restrictions() {
+        let kml = kml(&[
+            fix("ALPHA", None, "-58.0,-34.6,914.4"),
+            fix("BRAVO", Some("above 210"), "-57.5,-34.4,3048"),
+            fix("CHARL", Some("M0.78"), "-57.0,-34.2,10668"),
+            fix("DELTA", None, "-56.5,-34.0,10668"),
+            fix("ECHO", None, "-56.2,-34.3,1524"),
+        ]);
+        let options = RouteOptions {
+            speed_limit: Some((SpeedRestrict::Below(250), 10000.0)),
+            speed_rules: vec![(String::from("DELTA"), SpeedRestrict::At(300))],
+            transition_altitude: Some(18000.0),
+            ..Default::default()
+        };
+
+        let route = round_trip(&kml, &options);
+        let read: Vec<_> = route
+            .iter()
+            .map(|w| {
+                (
+                    w.ident.as_deref().unwrap(),
+                    w.altitude_ft.unwrap(),
+                    w.alt_restrict.unwrap(),
+                    w.speed_restrict,
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            read,
+            [
+                (
+                    "ALPHA",
+                    3000.0,
+                    AltRestrict::Above,
+                    Some(SpeedRestrict::Below(250))
+                ),
+                // Exactly at the limit's altitude, and with its own restriction.
+                (
+                    "BRAVO",
+                    10000.0,
+                    AltRestrict::Above,
+                    Some(SpeedRestrict::Above(210))
+                ),
+                (
+                    "CHARL",
+                    35000.0,
+                    AltRestrict::At,
+                    Some(SpeedRestrict::Mach(0.78))
+                ),
+                (
+                    "DELTA",
+                    35000.0,
+                    AltRestrict::At,
+                    Some(SpeedRestrict::At(300))
+                ),
+                (
+                    "ECHO",
+                    5000.0,
+                    AltRestrict::Below,
+                    Some(SpeedRestrict::Below(250))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_back_waypoints_without_speed() {
+        let kml = kml(&[
+            fix("ALPHA", None, "-58.0,-34.6,3048"),
+            fix("BRAVO", None, "-57.5,-34.4,3048"),
+        ]);
+
+        let route = round_trip(&kml, &RouteOptions::default());
+        assert_eq!(route.len(), 2);
+        assert!(route.iter().all(|w| w.speed_restrict.is_none()));
+        assert!(route.iter().all(|w| w.kind.as_deref() == Some("basic")));
+    }
+
+    #[test]
+    fn rejects_half_a_speed_restriction() {
+        let xml = "<PropertyList><route><wp>\
+            <speed-restrict type=\"string\">at</speed-restrict>\
+            <ident type=\"string\">ALPHA</ident>\
+            </wp></route></PropertyList>";
+
+        assert!(read_route(EventReader::new(xml.as_bytes())).is_err());
+    }
+}
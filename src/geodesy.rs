@@ -0,0 +1,313 @@
+//! Great-circle geometry on a spherical Earth, used to measure the legs of the route.
+//!
+//! Every function works with longitudes on either side of the antimeridian, so legs crossing ±180°
+//! are measured along the short way around the Earth.
+
+/// The mean radius of the Earth in nautical miles.
+pub const EARTH_RADIUS_NM: f64 = 3440.065;
+
+/// A position on the Earth, in degrees.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Coordinates {
+    pub lon: f64,
+    pub lat: f64,
+}
+
+impl Coordinates {
+    /// Checks that the latitude is within ±90°, and brings a wrapped longitude, like 190° or
+    /// -540°, back within ±180°.
+    ///
+    /// # Errors
+    /// This function fails if the latitude is out of range or any coordinate isn't a number.
+    pub fn validate(self) -> Result<Coordinates, String> {
+        if !self.lat.is_finite() || !self.lon.is_finite() {
+            return Err(String::from("coordinates aren't finite numbers"));
+        }
+
+        if !(-90.0..=90.0).contains(&self.lat) {
+            return Err(format!("latitude {} is beyond ±90°", self.lat));
+        }
+
+        Ok(Coordinates {
+            lon: normalize_longitude(self.lon),
+            lat: self.lat,
+        })
+    }
+}
+
+/// Brings a longitude within -180° (exclusive) and 180° (inclusive).
+pub fn normalize_longitude(lon: f64) -> f64 {
+    let lon = (lon + 180.0).rem_euclid(360.0) - 180.0;
+
+    if lon == -180.0 {
+        180.0
+    } else {
+        lon
+    }
+}
+
+/// Checks if the great circle from `from` to `to` crosses the antimeridian. Legs over a pole are
+/// taken as crossing it, since their longitude jumps by 180° there, as it does at ±180°.
+///
+/// # Example
+///
+/// ```
+/// # use kml_to_fgfp::geodesy::{crosses_antimeridian, Coordinates};
+/// let point = |lon, lat| Coordinates { lon, lat };
+/// assert!(crosses_antimeridian(point(179.0, 0.0), point(-179.0, 0.0)));
+/// assert!(!crosses_antimeridian(point(1.0, 0.0), point(-1.0, 0.0)));
+/// assert!(crosses_antimeridian(point(0.0, 80.0), point(180.0, 80.0)));
+/// ```
+pub fn crosses_antimeridian(from: Coordinates, to: Coordinates) -> bool {
+    // The leg crosses the plane of the prime meridian and the antimeridian where the `y` of its
+    // ends has different signs, or it lies in that plane when both ends are on it.
+    const EPSILON: f64 = 1e-12;
+    let ([x1, y1, z1], [x2, y2, z2]) = (to_vector(from), to_vector(to));
+
+    if y1.abs() < EPSILON && y2.abs() < EPSILON {
+        // Along a meridian, it's over a pole when the ends are on opposite sides of the axis.
+        let t = x1 / (x1 - x2);
+        return x1 * x2 < 0.0 && (z1 + t * (z2 - z1)).abs() > EPSILON;
+    }
+    // Ending on the plane isn't crossing it.
+    if y1 * y2 >= 0.0 || y1.abs() < EPSILON || y2.abs() < EPSILON {
+        return false;
+    }
+
+    // The great circle meets the plane on the antimeridian's side, or over a pole.
+    let t = y1 / (y1 - y2);
+    x1 + t * (x2 - x1) < EPSILON
+}
+
+/// Internal function that converts a position to a unit vector from the center of the Earth, with
+/// `x` towards the prime meridian at the equator and `z` towards the north pole.
+fn to_vector(position: Coordinates) -> [f64; 3] {
+    let (lon, lat) = (position.lon.to_radians(), position.lat.to_radians());
+
+    [lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin()]
+}
+
+/// A leg of the route, between two consecutive waypoints.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Leg {
+    pub from: String,
+    pub to: String,
+    /// The great-circle distance of the leg in nautical miles.
+    pub distance_nm: f64,
+    /// The true course when leaving `from`, in degrees.
+    pub initial_course: f64,
+    /// The true course when arriving at `to`, in degrees.
+    pub final_course: f64,
+    /// The magnetic course when leaving `from`, in degrees.
+    pub initial_magnetic_course: f64,
+    /// The magnetic course when arriving at `to`, in degrees.
+    pub final_magnetic_course: f64,
+    /// The distance from the start of the route to `to`, in nautical miles.
+    pub cumulative_nm: f64,
+    /// Whether the leg crosses the antimeridian.
+    pub crosses_antimeridian: bool,
+}
+
+/// Calculates the great-circle distance between two positions in nautical miles, using the
+/// haversine formula.
+pub fn distance_nm(from: Coordinates, to: Coordinates) -> f64 {
+    let (lat1, lat2) = (from.lat.to_radians(), to.lat.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (to.lon - from.lon).to_radians();
+
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    // Rounding can take `a` out of range for antipodal points.
+    let a = a.clamp(0.0, 1.0);
+
+    2.0 * EARTH_RADIUS_NM * a.sqrt().atan2((1.0 - a).sqrt())
+}
+
+/// Calculates the true course to fly when leaving `from` along the great circle to `to`, in
+/// degrees from 0 to 360.
+pub fn initial_course(from: Coordinates, to: Coordinates) -> f64 {
+    let (lat1, lat2) = (from.lat.to_radians(), to.lat.to_radians());
+    let d_lon = (to.lon - from.lon).to_radians();
+
+    let y = d_lon.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * d_lon.cos();
+
+    y.atan2(x).to_degrees().rem_euclid(360.0)
+}
+
+/// Calculates the true course when arriving at `to` along the great circle from `from`, in degrees
+/// from 0 to 360.
+pub fn final_course(from: Coordinates, to: Coordinates) -> f64 {
+    (initial_course(to, from) + 180.0).rem_euclid(360.0)
+}
+
+/// Calculates the distance in nautical miles from `point` to the great circle through `from` and
+/// `to`. It's positive when the point is to the right of the great circle.
+pub fn cross_track_nm(from: Coordinates, to: Coordinates, point: Coordinates) -> f64 {
+    let angle = distance_nm(from, point) / EARTH_RADIUS_NM;
+    let course_difference = (initial_course(from, point) - initial_course(from, to)).to_radians();
+
+    (angle.sin() * course_difference.sin()).asin() * EARTH_RADIUS_NM
+}
+
+/// Calculates the distance in nautical miles from `from`, along the great circle to `to`, to the
+/// point of the great circle closest to `point`.
+pub fn along_track_nm(from: Coordinates, to: Coordinates, point: Coordinates) -> f64 {
+    let angle = distance_nm(from, point) / EARTH_RADIUS_NM;
+    let cross_track = cross_track_nm(from, to, point) / EARTH_RADIUS_NM;
+    let course_difference = (initial_course(from, point) - initial_course(from, to)).to_radians();
+
+    (angle.cos() / cross_track.cos()).clamp(-1.0, 1.0).acos()
+        * course_difference.cos().signum()
+        * EARTH_RADIUS_NM
+}
+
+/// Calculates the position at a `fraction` of the great circle from `from` to `to`, where 0 is
+/// `from` and 1 is `to`.
+pub fn intermediate(from: Coordinates, to: Coordinates, fraction: f64) -> Coordinates {
+    let (lat1, lon1) = (from.lat.to_radians(), from.lon.to_radians());
+    let (lat2, lon2) = (to.lat.to_radians(), to.lon.to_radians());
+
+    // There's no single great circle between the same or antipodal points.
+    let angle = distance_nm(from, to) / EARTH_RADIUS_NM;
+    if angle.sin().abs() < 1e-12 {
+        return from;
+    }
+
+    let a = ((1.0 - fraction) * angle).sin() / angle.sin();
+    let b = (fraction * angle).sin() / angle.sin();
+
+    let x = a * lat1.cos() * lon1.cos() + b * lat2.cos() * lon2.cos();
+    let y = a * lat1.cos() * lon1.sin() + b * lat2.cos() * lon2.sin();
+    let z = a * lat1.sin() + b * lat2.sin();
+
+    Coordinates {
+        lon: y.atan2(x).to_degrees(),
+        lat: z.atan2((x * x + y * y).sqrt()).to_degrees(),
+    }
+}
+
+/// Calculates the position reached by flying `distance_nm` nautical miles from `from`, leaving it
+/// on the true `course` in degrees.
+pub fn destination(from: Coordinates, course: f64, distance_nm: f64) -> Coordinates {
+    let (lat1, lon1) = (from.lat.to_radians(), from.lon.to_radians());
+    let angle = distance_nm / EARTH_RADIUS_NM;
+    let course = course.to_radians();
+
+    let lat2 = (lat1.sin() * angle.cos() + lat1.cos() * angle.sin() * course.cos()).asin();
+    let lon2 = lon1
+        + (course.sin() * angle.sin() * lat1.cos()).atan2(angle.cos() - lat1.sin() * lat2.sin());
+
+    Coordinates {
+        lon: normalize_longitude(lon2.to_degrees()),
+        lat: lat2.to_degrees(),
+    }
+}
+
+/// Measures every leg between consecutive named positions. The `variation` function gives the
+/// magnetic declination in degrees at a position, positive when it's east.
+pub fn legs(points: &[(String, Coordinates)], variation: impl Fn(Coordinates) -> f64) -> Vec<Leg> {
+    let mut cumulative_nm = 0.0;
+
+    points
+        .windows(2)
+        .map(|pair| {
+            let ((from, a), (to, b)) = (&pair[0], &pair[1]);
+            let distance_nm = distance_nm(*a, *b);
+            cumulative_nm += distance_nm;
+
+            let initial_course = initial_course(*a, *b);
+            let final_course = final_course(*a, *b);
+
+            Leg {
+                from: from.clone(),
+                to: to.clone(),
+                distance_nm,
+                initial_course,
+                final_course,
+                initial_magnetic_course: (initial_course - variation(*a)).rem_euclid(360.0),
+                final_magnetic_course: (final_course - variation(*b)).rem_euclid(360.0),
+                cumulative_nm,
+                crosses_antimeridian: crosses_antimeridian(*a, *b),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NZAA: Coordinates = Coordinates {
+        lon: 174.791667,
+        lat: -37.008056,
+    };
+    const PHNL: Coordinates = Coordinates {
+        lon: -157.922428,
+        lat: 21.318681,
+    };
+
+    fn point(lon: f64, lat: f64) -> Coordinates {
+        Coordinates { lon, lat }
+    }
+
+    #[test]
+    fn measures_legs_across_the_antimeridian() {
+        assert!((distance_nm(point(179.0, 0.0), point(-179.0, 0.0)) - 120.08).abs() < 0.01);
+        assert!((distance_nm(point(-179.0, 0.0), point(179.0, 0.0)) - 120.08).abs() < 0.01);
+        assert!((distance_nm(NZAA, PHNL) - 3827.5).abs() < 0.1);
+        assert!((distance_nm(PHNL, NZAA) - 3827.5).abs() < 0.1);
+    }
+
+    #[test]
+    fn measures_courses_across_the_antimeridian() {
+        assert!((initial_course(point(179.0, 0.0), point(-179.0, 0.0)) - 90.0).abs() < 1e-9);
+        assert!((initial_course(point(-179.0, 0.0), point(179.0, 0.0)) - 270.0).abs() < 1e-9);
+        assert!((initial_course(NZAA, PHNL) - 28.44).abs() < 0.01);
+        assert!((final_course(NZAA, PHNL) - 24.09).abs() < 0.01);
+    }
+
+    #[test]
+    fn finds_intermediate_points_across_the_antimeridian() {
+        let middle = intermediate(point(179.0, 0.0), point(-179.0, 0.0), 0.5);
+        assert!((middle.lon.abs() - 180.0).abs() < 1e-9);
+        assert!(middle.lat.abs() < 1e-9);
+
+        let past = destination(point(179.5, 0.0), 90.0, 60.0);
+        assert!((past.lon - -179.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn checks_antimeridian_crossings_along_the_great_circle() {
+        let cases = [
+            (point(179.0, 0.0), point(-179.0, 0.0), true),
+            (point(-179.0, 0.0), point(179.0, 0.0), true),
+            (NZAA, PHNL, true),
+            (point(1.0, 0.0), point(-1.0, 0.0), false),
+            (point(100.0, 10.0), point(170.0, 20.0), false),
+            (point(100.0, 80.0), point(-100.0, 80.0), true),
+            (point(80.0, 80.0), point(-80.0, 80.0), false),
+            // Over the poles.
+            (point(0.0, 80.0), point(180.0, 80.0), true),
+            (point(10.0, -80.0), point(-170.0, -80.0), true),
+            // Ending on it isn't crossing it.
+            (point(170.0, 0.0), point(180.0, 0.0), false),
+            (point(-170.0, 0.0), point(180.0, 0.0), false),
+        ];
+
+        for (from, to, crosses) in cases {
+            assert_eq!(
+                crosses_antimeridian(from, to),
+                crosses,
+                "{from:?} to {to:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn normalizes_longitudes() {
+        assert_eq!(normalize_longitude(190.0), -170.0);
+        assert_eq!(normalize_longitude(-540.0), 180.0);
+        assert_eq!(normalize_longitude(-180.0), 180.0);
+        assert!(point(10.0, 91.0).validate().is_err());
+    }
+}
@@ -0,0 +1,62 @@
+//! IATA codes of airports, like `EZE` for `SAEZ`.
+//!
+//! Route planners and maps often name the airports by their IATA code. FlightGear's `apt.dat` has
+//! the codes of the airports it knows, and the table here has the ones of the busiest airports for
+//! when it isn't available.
+
+/// The IATA codes of the busiest airports, by their ICAO code.
+const IATA_CODES: &[(&str, &str)] = &[
+    ("CYUL", "YUL"),
+    ("CYVR", "YVR"),
+    ("CYYZ", "YYZ"),
+    ("EDDF", "FRA"),
+    ("EDDM", "MUC"),
+    ("EGKK", "LGW"),
+    ("EGLL", "LHR"),
+    ("EHAM", "AMS"),
+    ("KATL", "ATL"),
+    ("KDFW", "DFW"),
+    ("KJFK", "JFK"),
+    ("KLAX", "LAX"),
+    ("KORD", "ORD"),
+    ("KSFO", "SFO"),
+    ("LEMD", "MAD"),
+    ("LFPG", "CDG"),
+    ("LIRF", "FCO"),
+    ("NZAA", "AKL"),
+    ("OMDB", "DXB"),
+    ("RJTT", "HND"),
+    ("SAAR", "ROS"),
+    ("SABE", "AEP"),
+    ("SACO", "COR"),
+    ("SAEZ", "EZE"),
+    ("SAME", "MDZ"),
+    ("SBGR", "GRU"),
+    ("SCEL", "SCL"),
+    ("SKBO", "BOG"),
+    ("SPJC", "LIM"),
+    ("SUMU", "MVD"),
+    ("VHHH", "HKG"),
+    ("WSSS", "SIN"),
+    ("YBBN", "BNE"),
+    ("YMML", "MEL"),
+    ("YSSY", "SYD"),
+];
+
+/// Returns the IATA code of the airport with the ICAO code `icao`, if the table has it.
+///
+/// # Example
+///
+/// ```
+/// # use kml_to_fgfp::iata::iata_code;
+/// assert_eq!(iata_code("saez"), Some("EZE"));
+/// assert_eq!(iata_code("ZZZZ"), None);
+/// ```
+pub fn iata_code(icao: &str) -> Option<&'static str> {
+    let icao = icao.trim().to_uppercase();
+
+    IATA_CODES
+        .iter()
+        .find(|(code, _)| *code == icao)
+        .map(|(_, iata)| *iata)
+}
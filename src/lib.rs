@@ -5,22 +5,25 @@
 //! file extension.
 //!
 //! ## How to use it:
-//! There are 6 steps to use this library:
+//! There are 8 steps to use this library:
 //!
 //! 1. Create an [`EventWriter`](xml::writer::EventWriter), it will be used to write to the output
 //!    file.
 //! 2. Write the beginning of the .fgfp xml tree using the
-//!    [`write_start_of_tree`](write_start_of_tree) function.
-//! 3. Create 2 `Option<kml_to_fgfp::Airport>` setting the value to `None` if no departure or
-//!    no destination airports are in the flight plan.
-//!
-//!    Then call the [`write_airports`](write_airports) function, passing the previous options as
-//!    arguments.
+//!    [`write_start_of_tree`](write_start_of_tree) function, for the [`FgTarget`](FgTarget) the
+//!    flight plan is meant for.
+//! 3. Create 2 `Option<kml_to_fgfp::Airport>` setting the value to `None` to use the departure or
+//!    destination airports of the .kml file, if it has them. They can be parsed from strings like
+//!    `YSSY/34L`, which are validated. The [`find_airports`](find_airports) function finds the
+//!    airports of the .kml file first, so they can be checked too.
 //! 4. Create an [`EventReader`](xml::reader::EventReader), it will be used to read the .kml file.
 //! 5. Call the [`transform_route`](transform_route) function, which will need the xml
-//!    `EventReader`, `EventWriter`, and 2 airport options. This function creates the .fgfp's route
-//!    using waypoints with the information in the .kml file.
-//! 6. Close the xml tree by calling the [`close_tree`](close_tree) function.
+//!    `EventReader`, `EventWriter`, 2 airport options and the [`RouteOptions`](RouteOptions). This
+//!    function creates the .fgfp's route using waypoints with the information in the .kml file, and
+//!    returns a [`Report`](Report) with the legs of the route and the airports it used.
+//! 6. Write the airports of the report with the [`write_airports`](write_airports) function.
+//! 7. Write the cruise altitude of the report with the [`write_cruise`](write_cruise) function.
+//! 8. Close the xml tree by calling the [`close_tree`](close_tree) function.
 
 use std::io::Write;
 
@@ -29,22 +32,54 @@ pub use xml::{reader::EventReader, writer::EmitterConfig};
 
 use xml::writer::{EventWriter, Result};
 
-// # Step 1: Start of tree
+// # Global: FlightGear versions
+// #############################
+
+mod target;
+pub use target::{Feature, FgTarget};
+
+// # Global: Geodesy
+// #################
+
+pub mod geodesy;
+pub mod magnetic;
+
+// # Global: Navigation data
+// #########################
+
+pub mod iata;
+pub mod metar;
+pub mod navdata;
+pub mod transition;
+
+// # Global: Reading the .fgfp
+// ###########################
+
+pub mod fgfp;
+
+// # Step 2: Start of tree
 // #######################
 
 /// Write the start of the .fgfp's xml tree. AKA the version, flight-rules, flight-type and
 /// estimated duration.
 ///
+/// The `target` decides the version of the format and whether the flight-rules, flight-type and
+/// estimated duration are written.
+///
 /// # Errors
 /// This function can fail if trying to write invalid xml or other io errors.
 #[rustfmt::skip]
-pub fn write_start_of_tree<W: Write>(writer: &mut EventWriter<W>) -> Result<()> {
+pub fn write_start_of_tree<W: Write>(writer: &mut EventWriter<W>, target: FgTarget) -> Result<()> {
     write_event(writer, EventType::OpeningElement, "PropertyList")?;
 
     write_event(writer, EventType::OpeningElement, "version type=int")?;
-    write_event(writer, EventType::Content, "2")?;
+    write_event(writer, EventType::Content, &target.format_version().to_string())?;
     write_event(writer, EventType::ClosingElement, "version")?;
 
+    if !target.supports(Feature::FlightInfo) {
+        return Ok(());
+    }
+
     write_event(writer, EventType::OpeningElement, "flight-rules type=string")?;
     write_event(writer, EventType::Content, "V")?;
     write_event(writer, EventType::ClosingElement, "flight-rules")?;
@@ -60,8 +95,19 @@ pub fn write_start_of_tree<W: Write>(writer: &mut EventWriter<W>) -> Result<()>
     Ok(())
 }
 
-// # Step: Airports
-// ################
+// # Steps 3 to 5: The route
+// ##########################
+
+// These steps were moved to their own module because of their size.
+mod route;
+pub use route::{
+    find_airports, transform_route, Airport, AltRestrict, AltitudeMode, AltitudeRounding, Densify,
+    Diagnostic, FixLookup, Hold, HoldLeg, Problem, Report, RouteOptions, Simplify, SpeedRestrict,
+    StepAt, StepClimb, Validation, VerticalProfile, WaypointType,
+};
+
+// # Step 6: Airports
+// ##################
 
 /// Write the destination and arrival airports to the .fgfp's xml tree. The airport codes should
 /// have [ICAO codes](https://en.wikipedia.org/wiki/List_of_airports_by_ICAO_code:_A).
@@ -70,6 +116,9 @@ pub fn write_start_of_tree<W: Write>(writer: &mut EventWriter<W>) -> Result<()>
 /// - Airport code: YSSY
 /// - Runway: 34L
 ///
+/// The SID of the departure, and the STAR and approach of the destination, are written when the
+/// airports have them.
+///
 /// # Errors
 /// This function can fail if trying to write invalid xml or other io errors.
 pub fn write_airports<W: Write>(
@@ -80,12 +129,15 @@ pub fn write_airports<W: Write>(
     if let Some(airport) = departure {
         write_event(writer, EventType::OpeningElement, "departure")?;
         write_airport_details(writer, &airport.ident, &airport.runway)?;
+        write_procedure(writer, "sid", &airport.procedure, &airport.transition)?;
         write_event(writer, EventType::ClosingElement, "departure")?;
     }
 
     if let Some(airport) = destination {
         write_event(writer, EventType::OpeningElement, "destination")?;
         write_airport_details(writer, &airport.ident, &airport.runway)?;
+        write_procedure(writer, "star", &airport.procedure, &airport.transition)?;
+        write_procedure(writer, "approach", &airport.approach, &None)?;
         write_event(writer, EventType::ClosingElement, "destination")?;
     }
 
@@ -111,14 +163,69 @@ fn write_airport_details<W: Write>(
     Ok(())
 }
 
-// # Step 3: The route
-// ###################
+/// Internal function to write the procedure of an airport, like the `sid` and its `sid_trans`.
+#[rustfmt::skip]
+fn write_procedure<W: Write>(
+    writer: &mut EventWriter<W>,
+    element: &str,
+    procedure: &Option<String>,
+    transition: &Option<String>,
+) -> Result<()> {
+    if let Some(procedure) = procedure {
+        write_event(writer, EventType::OpeningElement, &format!("{element} type=string"))?;
+        write_event(writer, EventType::Content, procedure)?;
+        write_event(writer, EventType::ClosingElement, element)?;
+
+        if let Some(transition) = transition {
+            write_event(writer, EventType::OpeningElement, &format!("{element}_trans type=string"))?;
+            write_event(writer, EventType::Content, transition)?;
+            write_event(writer, EventType::ClosingElement, &format!("{element}_trans"))?;
+        }
+    }
+
+    Ok(())
+}
+
+// # Step 7: Cruise
+// ################
 
-// This step was moved to it's own module because of it's size.
-mod route;
-pub use route::{transform_route, Airport};
+/// Write the cruise altitude of the flight plan, from the [`Report`](Report) of the route. It's
+/// written as a flight level when it's above the transition altitude.
+///
+/// Nothing is written without a cruise altitude, or when the `target` doesn't read the flight
+/// info.
+///
+/// # Errors
+/// This function can fail if trying to write invalid xml or other io errors.
+#[rustfmt::skip]
+pub fn write_cruise<W: Write>(
+    writer: &mut EventWriter<W>,
+    report: &Report,
+    target: FgTarget,
+) -> Result<()> {
+    let altitude = match report.cruise_altitude {
+        Some(altitude) if target.supports(Feature::FlightInfo) => altitude,
+        _ => return Ok(()),
+    };
+
+    write_event(writer, EventType::OpeningElement, "cruise")?;
+
+    if report.transition_altitude.is_some_and(|t| altitude > t) && target.supports(Feature::FlightLevel) {
+        write_event(writer, EventType::OpeningElement, "flight-level type=int")?;
+        write_event(writer, EventType::Content, format!("{:.0}", altitude / 100.0).as_str())?;
+        write_event(writer, EventType::ClosingElement, "flight-level")?;
+    } else {
+        write_event(writer, EventType::OpeningElement, "altitude-ft type=int")?;
+        write_event(writer, EventType::Content, format!("{:.0}", altitude).as_str())?;
+        write_event(writer, EventType::ClosingElement, "altitude-ft")?;
+    }
+
+    write_event(writer, EventType::ClosingElement, "cruise")?;
+
+    Ok(())
+}
 
-// # Step 4: CLosing tree
+// # Step 8: Closing tree
 // ######################
 
 /// Write the end of the .fgfp's xml tree.
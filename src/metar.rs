@@ -0,0 +1,287 @@
+//! The wind of METAR reports, used to choose the runways.
+//!
+//! Only the station and the wind group of the report are read, like `SAEZ 011200Z 09012G20KT`.
+//! METAR winds are from true north, like the runway headings of FlightGear's data.
+
+use std::{fmt, str::FromStr};
+
+/// A METAR report, with the parts this crate uses.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Metar {
+    /// The ICAO code of the airport that issued the report.
+    pub station: String,
+    pub wind: Wind,
+}
+
+/// The wind of a METAR report.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Wind {
+    /// Where the wind blows from in degrees true, or `None` when it's variable.
+    pub direction: Option<f64>,
+    pub speed_kt: f64,
+    pub gust_kt: Option<f64>,
+}
+
+impl Wind {
+    /// The headwind and crosswind components on a runway with the true `heading`, in knots. The
+    /// headwind is negative when it's a tailwind, and the crosswind is positive when it blows from
+    /// the right.
+    ///
+    /// A variable wind is taken as a tailwind, the worst case.
+    pub fn components(&self, heading: f64) -> (f64, f64) {
+        match self.direction {
+            Some(direction) => {
+                let angle = (direction - heading).to_radians();
+                (self.speed_kt * angle.cos(), self.speed_kt * angle.sin())
+            }
+            None => (-self.speed_kt, 0.0),
+        }
+    }
+}
+
+impl FromStr for Metar {
+    type Err = String;
+
+    /// Reads a METAR report, like `METAR SAEZ 011200Z 09012G20KT 9999 FEW030 18/12 Q1015`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use kml_to_fgfp::metar::Metar;
+    /// let metar: Metar = "METAR SAEZ 011200Z 09012G20KT 9999 FEW030 18/12 Q1015".parse().unwrap();
+    /// assert_eq!(metar.station, "SAEZ");
+    /// assert_eq!(metar.wind.direction, Some(90.0));
+    /// assert_eq!(metar.wind.speed_kt, 12.0);
+    /// assert_eq!(metar.wind.gust_kt, Some(20.0));
+    ///
+    /// assert!("SAEZ 011200Z /////KT 9999".parse::<Metar>().is_err());
+    /// assert!("SAEZ 011200Z 99912KT 9999".parse::<Metar>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = s
+            .split_whitespace()
+            .skip_while(|t| matches!(*t, "METAR" | "SPECI" | "COR"));
+
+        let station = match tokens.next() {
+            Some(station) if station.len() == 4 && station.chars().all(|c| c.is_alphanumeric()) => {
+                station.to_uppercase()
+            }
+            _ => return Err(format!("METAR `{s}` doesn't start with a station")),
+        };
+
+        let wind = tokens
+            .take(3)
+            .find_map(parse_wind)
+            .ok_or_else(|| format!("METAR `{s}` has no wind"))?;
+
+        Ok(Metar { station, wind })
+    }
+}
+
+/// The wind components on a runway, when it was chosen for the wind.
+#[derive(Clone, PartialEq, Debug)]
+pub struct RunwayWind {
+    pub runway: String,
+    /// The headwind in knots, negative when it's a tailwind.
+    pub headwind_kt: f64,
+    /// The crosswind in knots, positive when it blows from the right.
+    pub crosswind_kt: f64,
+}
+
+impl fmt::Display for RunwayWind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.headwind_kt < 0.0 {
+            true => write!(f, "tailwind {:.0} kt", -self.headwind_kt)?,
+            false => write!(f, "headwind {:.0} kt", self.headwind_kt)?,
+        }
+
+        match self.crosswind_kt {
+            crosswind if crosswind.round() == 0.0 => write!(f, ", no crosswind"),
+            crosswind if crosswind > 0.0 => {
+                write!(f, ", crosswind {crosswind:.0} kt from the right")
+            }
+            crosswind => write!(f, ", crosswind {:.0} kt from the left", -crosswind),
+        }
+    }
+}
+
+/// Chooses the runway with the most headwind, from the `runways` and their true headings. Returns
+/// `None` when there are no runways.
+///
+/// # Example
+///
+/// ```
+/// # use kml_to_fgfp::metar::{choose_runway, Metar};
+/// let metar: Metar = "SAEZ 011200Z 25015KT CAVOK".parse().unwrap();
+/// let runways = [(String::from("11"), 115.0), (String::from("29"), 295.0)];
+///
+/// let choice = choose_runway(&runways, &metar.wind).unwrap();
+/// assert_eq!(choice.runway, "29");
+/// assert!(choice.crosswind_kt < 0.0);
+/// ```
+pub fn choose_runway(runways: &[(String, f64)], wind: &Wind) -> Option<RunwayWind> {
+    runways
+        .iter()
+        .map(|(runway, heading)| {
+            let (headwind_kt, crosswind_kt) = wind.components(*heading);
+            RunwayWind {
+                runway: runway.clone(),
+                headwind_kt,
+                crosswind_kt,
+            }
+        })
+        .reduce(|best, other| match other.headwind_kt > best.headwind_kt {
+            true => other,
+            false => best,
+        })
+}
+
+/// Internal function that reads a wind group, like `09012G20KT`, `VRB03KT` or `27005MPS`.
+fn parse_wind(token: &str) -> Option<Wind> {
+    let (value, to_knots) = if let Some(value) = token.strip_suffix("KT") {
+        (value, 1.0)
+    } else if let Some(value) = token.strip_suffix("MPS") {
+        (value, 1.943844)
+    } else if let Some(value) = token.strip_suffix("KMH") {
+        (value, 0.539957)
+    } else {
+        return None;
+    };
+
+    let (direction, speeds) = (value.get(..3)?, value.get(3..)?);
+    let direction = match direction {
+        "VRB" => None,
+        direction => Some(direction.parse::<f64>().ok().filter(|d| *d <= 360.0)?),
+    };
+
+    let (speed, gust) = match speeds.split_once('G') {
+        Some((speed, gust)) => (speed, Some(gust.parse::<f64>().ok()? * to_knots)),
+        None => (speeds, None),
+    };
+
+    Some(Wind {
+        direction,
+        speed_kt: speed.parse::<f64>().ok()? * to_knots,
+        gust_kt: gust,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runways(headings: &[(&str, f64)]) -> Vec<(String, f64)> {
+        headings
+            .iter()
+            .map(|(runway, heading)| (String::from(*runway), *heading))
+            .collect()
+    }
+
+    #[test]
+    fn reads_the_wind_groups() {
+        let wind = |token| parse_wind(token).unwrap();
+
+        assert_eq!(
+            wind("09012G20KT"),
+            Wind {
+                direction: Some(90.0),
+                speed_kt: 12.0,
+                gust_kt: Some(20.0)
+            }
+        );
+        assert_eq!(wind("VRB03KT").direction, None);
+        assert_eq!(
+            wind("00000KT"),
+            Wind {
+                direction: Some(0.0),
+                speed_kt: 0.0,
+                gust_kt: None
+            }
+        );
+
+        let mps = wind("27005G10MPS");
+        assert!((mps.speed_kt - 9.72).abs() < 0.01, "{}", mps.speed_kt);
+        assert!((mps.gust_kt.unwrap() - 19.44).abs() < 0.01);
+
+        let kmh = wind("36020KMH");
+        assert_eq!(kmh.direction, Some(360.0));
+        assert!((kmh.speed_kt - 10.8).abs() < 0.01, "{}", kmh.speed_kt);
+    }
+
+    #[test]
+    fn rejects_invalid_wind_groups() {
+        for token in [
+            "99912KT", "36112KT", "/////KT", "090KT", "09012G", "9999", "AUTO",
+        ] {
+            assert_eq!(parse_wind(token), None, "{token}");
+        }
+    }
+
+    #[test]
+    fn finds_the_wind_after_other_groups() {
+        let metar: Metar = "METAR COR SAEZ 011200Z AUTO 18008KT 9999 NCD"
+            .parse()
+            .unwrap();
+        assert_eq!(metar.station, "SAEZ");
+        assert_eq!(metar.wind.direction, Some(180.0));
+
+        assert!("SAEZ 011200Z AUTO NIL".parse::<Metar>().is_err());
+        assert!("SAEZ 011200Z 99912KT 9999".parse::<Metar>().is_err());
+    }
+
+    #[test]
+    fn tells_the_crosswind_from_the_left_and_the_right() {
+        let from = |direction| Wind {
+            direction: Some(direction),
+            speed_kt: 10.0,
+            gust_kt: None,
+        };
+
+        let (headwind, crosswind) = from(90.0).components(0.0);
+        assert!(headwind.abs() < 1e-9);
+        assert!((crosswind - 10.0).abs() < 1e-9, "{crosswind}");
+
+        let (_, crosswind) = from(270.0).components(0.0);
+        assert!((crosswind + 10.0).abs() < 1e-9, "{crosswind}");
+
+        // Across north.
+        let (headwind, crosswind) = from(10.0).components(340.0);
+        assert!(headwind > 0.0 && crosswind > 0.0);
+        let (headwind, crosswind) = from(330.0).components(20.0);
+        assert!(headwind > 0.0 && crosswind < 0.0);
+
+        let (headwind, crosswind) = from(180.0).components(0.0);
+        assert!((headwind + 10.0).abs() < 1e-9 && crosswind.abs() < 1e-9);
+    }
+
+    #[test]
+    fn chooses_a_tailwind_when_theres_nothing_else() {
+        let wind = "SAEZ 011200Z 29010KT".parse::<Metar>().unwrap().wind;
+
+        let choice = choose_runway(&runways(&[("11", 110.0)]), &wind).unwrap();
+        assert_eq!(choice.runway, "11");
+        assert!((choice.headwind_kt + 10.0).abs() < 1e-9);
+        assert_eq!(choice.to_string(), "tailwind 10 kt, no crosswind");
+
+        assert_eq!(choose_runway(&[], &wind), None);
+    }
+
+    #[test]
+    fn keeps_the_first_runway_of_a_tie() {
+        let runways = runways(&[("11", 110.0), ("29", 290.0)]);
+
+        // Straight across the runways.
+        let across = "SAEZ 011200Z 20010KT".parse::<Metar>().unwrap().wind;
+        let choice = choose_runway(&runways, &across).unwrap();
+        assert_eq!(choice.runway, "11");
+        assert_eq!(
+            choice.to_string(),
+            "headwind 0 kt, crosswind 10 kt from the right"
+        );
+
+        let variable = "SAEZ 011200Z VRB05KT".parse::<Metar>().unwrap().wind;
+        let choice = choose_runway(&runways, &variable).unwrap();
+        assert_eq!(choice.runway, "11");
+        assert_eq!(choice.headwind_kt, -5.0);
+    }
+}
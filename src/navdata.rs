@@ -0,0 +1,55 @@
+//! Navigation data from FlightGear's `$FG_ROOT`, in the X-Plane `.dat` formats FlightGear uses.
+//!
+//! The files are read from a local FlightGear installation, either compressed (`.dat.gz`) like
+//! FlightGear ships them, or uncompressed. The procedures of the airports are read from the Level-D
+//! style `.procedures.xml` files FlightGear also reads, and their runway thresholds from the
+//! `.threshold.xml` files of the scenery.
+//!
+//! The user's own waypoints are kept in a CSV library, and take priority over FlightGear's fixes.
+
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use flate2::read::GzDecoder;
+
+mod apt;
+pub use apt::{AirportData, AirportDatabase, Runway};
+
+mod airways;
+pub use airways::{AirwayDatabase, AirwayFix, AirwayLevel};
+
+mod fixes;
+pub use fixes::{Fix, FixDatabase, FixKind};
+
+mod procedures;
+pub use procedures::{AirportProcedures, Procedure, ProcedureFix, ProcedureKind, Transition};
+
+mod thresholds;
+pub use thresholds::{RunwayThresholds, Threshold};
+
+mod user;
+pub use user::{UserWaypoint, UserWaypoints};
+
+/// Internal function that opens a `.dat` file, decompressing it when its name ends with `.gz`.
+fn open(path: &Path) -> Result<Box<dyn BufRead>, Box<dyn Error>> {
+    let file = File::open(path).map_err(|e| format!("Can't open {}: {e}", path.display()))?;
+
+    if path.extension().is_some_and(|e| e == "gz") {
+        Ok(Box::new(BufReader::new(GzDecoder::new(file))))
+    } else {
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+/// Internal function that reads the version of a `.dat` file from its header, like
+/// `1101 Version - data cycle 2013.10`.
+fn read_version(data: &[&str]) -> Option<u32> {
+    match data {
+        [version, "Version", ..] => version.parse().ok(),
+        _ => None,
+    }
+}
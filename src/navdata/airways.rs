@@ -0,0 +1,464 @@
+use std::{
+    collections::{hash_map::Entry, HashMap, VecDeque},
+    error::Error,
+    io::BufRead,
+    path::Path,
+};
+
+use super::FixDatabase;
+use crate::geodesy::{self, Coordinates};
+
+/// How far from its position in the route an airway's entry fix can be, in nautical miles. Airways
+/// in different countries share names, like `A1`.
+const MAX_ENTRY_DISTANCE_NM: f64 = 20.0;
+
+/// A fix along an airway.
+#[derive(Clone, PartialEq, Debug)]
+pub struct AirwayFix {
+    pub ident: String,
+    pub coordinates: Coordinates,
+}
+
+/// Whether an airway is for low or high altitudes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AirwayLevel {
+    Low,
+    High,
+}
+
+/// Which ways a segment of an airway can be flown.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Direction {
+    Both,
+    /// Only from the first fix to the second.
+    Forward,
+    /// Only from the second fix to the first.
+    Backward,
+}
+
+/// A segment of an airway between two fixes.
+struct Segment {
+    from: AirwayFix,
+    to: AirwayFix,
+    direction: Direction,
+    level: AirwayLevel,
+}
+
+/// The airways of FlightGear's `$FG_ROOT/Navaids/awy.dat.gz`, by name.
+#[derive(Default)]
+pub struct AirwayDatabase {
+    airways: HashMap<String, Vec<Segment>>,
+}
+
+impl AirwayDatabase {
+    /// Reads the airways of FlightGear's `Navaids/awy.dat.gz`, in the `fg_root` directory.
+    ///
+    /// # Errors
+    /// This function fails if the file can't be read.
+    pub fn from_fg_root(
+        fg_root: &Path,
+        fixes: &FixDatabase,
+    ) -> Result<AirwayDatabase, Box<dyn Error>> {
+        let mut database = AirwayDatabase::default();
+        database.read_awy_dat(super::open(&fg_root.join("Navaids/awy.dat.gz"))?, fixes)?;
+
+        Ok(database)
+    }
+
+    /// Reads the contents of an `awy.dat` file. Version 640 has the coordinates of the fixes in
+    /// every line, while version 1100 has their ICAO region, so they're found in the `fixes`.
+    /// Segments whose fixes can't be found are skipped.
+    ///
+    /// # Errors
+    /// This function fails if the contents can't be read.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use kml_to_fgfp::{geodesy::Coordinates, navdata::{AirwayDatabase, AirwayLevel, FixDatabase}};
+    /// let awy_dat = "I\n640 Version\n\
+    ///     EZE11 -34.80 -58.30 GESOL -34.60 -57.50 2 245 600 UM540\n\
+    ///     GESOL -34.60 -57.50 ARSOT -34.40 -56.70 2 245 600 UM540-UW10\n\
+    ///     99\n";
+    ///
+    /// let mut database = AirwayDatabase::default();
+    /// database.read_awy_dat(awy_dat.as_bytes(), &FixDatabase::default()).unwrap();
+    ///
+    /// let entry = Coordinates { lon: -58.30, lat: -34.80 };
+    /// let fixes = database.expand("UM540", "EZE11", entry, "ARSOT", AirwayLevel::High).unwrap();
+    /// let idents: Vec<&str> = fixes.iter().map(|f| f.ident.as_str()).collect();
+    /// assert_eq!(idents, ["GESOL", "ARSOT"]);
+    ///
+    /// assert!(database.expand("UM540", "EZE11", entry, "ESL", AirwayLevel::High).is_err());
+    /// ```
+    pub fn read_awy_dat(
+        &mut self,
+        reader: impl BufRead,
+        fixes: &FixDatabase,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut version = 0;
+
+        for line in reader.lines() {
+            let line = line?;
+            let data: Vec<&str> = line.split_whitespace().collect();
+
+            if let Some(v) = super::read_version(&data) {
+                version = v;
+                continue;
+            }
+
+            let segment = match version {
+                v if v >= 1100 => read_segment_1100(&data, fixes),
+                _ => read_segment_640(&data),
+            };
+
+            if let Some((segment, names)) = segment {
+                for name in names.split('-') {
+                    self.airways
+                        .entry(name.to_uppercase())
+                        .or_default()
+                        .push(Segment {
+                            from: segment.from.clone(),
+                            to: segment.to.clone(),
+                            ..segment
+                        });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether there's an airway called `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.airways.contains_key(&name.trim().to_uppercase())
+    }
+
+    /// Returns the fixes along the airway `name` from the `entry` fix, which is near
+    /// `entry_position`, to the `exit` fix. The entry isn't included, the exit is the last fix.
+    ///
+    /// Segments of the given `level` are preferred, and one-way segments are only flown their way.
+    ///
+    /// # Errors
+    /// This function fails if the airway is unknown, if the entry or exit fixes aren't on it, or if
+    /// the exit can't be reached from the entry.
+    pub fn expand(
+        &self,
+        name: &str,
+        entry: &str,
+        entry_position: Coordinates,
+        exit: &str,
+        level: AirwayLevel,
+    ) -> Result<Vec<AirwayFix>, String> {
+        let name = name.trim().to_uppercase();
+        let (entry, exit) = (&entry.trim().to_uppercase(), &exit.trim().to_uppercase());
+        let segments = match self.airways.get(&name) {
+            Some(segments) => segments,
+            None => return Err(format!("Unknown airway {name}")),
+        };
+
+        let preferred: Vec<&Segment> = segments.iter().filter(|s| s.level == level).collect();
+        let all: Vec<&Segment> = segments.iter().collect();
+
+        for candidates in [&preferred, &all] {
+            if let Some(path) = search(candidates, entry, entry_position, exit, true) {
+                return Ok(path);
+            }
+        }
+
+        let is_on_airway = |ident: &str| {
+            segments
+                .iter()
+                .any(|s| s.from.ident == ident || s.to.ident == ident)
+        };
+
+        Err(if !is_on_airway(entry) {
+            format!("{entry} isn't on the airway {name}")
+        } else if !is_on_airway(exit) {
+            format!("{exit} isn't on the airway {name}")
+        } else if search(&all, entry, entry_position, exit, false).is_some() {
+            format!("The airway {name} is one way, it can't be flown from {entry} to {exit}")
+        } else {
+            format!("{exit} can't be reached from {entry} along the airway {name}")
+        })
+    }
+}
+
+/// Internal function that reads a segment of the 640 format:
+/// `IDENT LAT LON IDENT LAT LON LEVEL BASE TOP NAMES`. Returns it with its names.
+fn read_segment_640<'a>(data: &[&'a str]) -> Option<(Segment, &'a str)> {
+    let fix = |i: usize| -> Option<AirwayFix> {
+        Some(AirwayFix {
+            ident: data.get(i)?.to_uppercase(),
+            coordinates: Coordinates {
+                lat: data.get(i + 1)?.parse().ok()?,
+                lon: data.get(i + 2)?.parse().ok()?,
+            },
+        })
+    };
+
+    let segment = Segment {
+        from: fix(0)?,
+        to: fix(3)?,
+        direction: Direction::Both,
+        level: read_level(data.get(6)?)?,
+    };
+
+    Some((segment, data.get(9)?))
+}
+
+/// Internal function that reads a segment of the 1100 format:
+/// `IDENT REGION TYPE IDENT REGION TYPE DIRECTION LEVEL BASE TOP NAMES`. Returns it with its names.
+fn read_segment_1100<'a>(data: &[&'a str], fixes: &FixDatabase) -> Option<(Segment, &'a str)> {
+    let fix = |i: usize| -> Option<AirwayFix> {
+        let found = fixes.find(data.get(i)?, &data.get(i + 1)?.to_uppercase())?;
+
+        Some(AirwayFix {
+            ident: found.ident.clone(),
+            coordinates: found.coordinates,
+        })
+    };
+
+    let direction = match *data.get(6)? {
+        "F" => Direction::Forward,
+        "B" => Direction::Backward,
+        _ => Direction::Both,
+    };
+
+    let segment = Segment {
+        from: fix(0)?,
+        to: fix(3)?,
+        direction,
+        level: read_level(data.get(7)?)?,
+    };
+
+    Some((segment, data.get(10)?))
+}
+
+/// Internal function that reads the level of an airway, `1` for low and `2` for high.
+fn read_level(level: &str) -> Option<AirwayLevel> {
+    match level {
+        "1" => Some(AirwayLevel::Low),
+        "2" => Some(AirwayLevel::High),
+        _ => None,
+    }
+}
+
+/// Internal function that finds the shortest path along the `segments` from the `entry` fix nearest
+/// to `entry_position` to the `exit` fix. When `directed` is false, one-way segments can be flown
+/// the wrong way.
+fn search(
+    segments: &[&Segment],
+    entry: &str,
+    entry_position: Coordinates,
+    exit: &str,
+    directed: bool,
+) -> Option<Vec<AirwayFix>> {
+    // Fixes are told apart by their ident and position, many fixes share an ident.
+    let key = |fix: &AirwayFix| {
+        (
+            fix.ident.clone(),
+            (fix.coordinates.lat * 1000.0).round() as i64,
+            (fix.coordinates.lon * 1000.0).round() as i64,
+        )
+    };
+
+    let mut next: HashMap<_, Vec<&AirwayFix>> = HashMap::new();
+    for segment in segments {
+        if !directed || segment.direction != Direction::Backward {
+            next.entry(key(&segment.from))
+                .or_default()
+                .push(&segment.to);
+        }
+        if !directed || segment.direction != Direction::Forward {
+            next.entry(key(&segment.to))
+                .or_default()
+                .push(&segment.from);
+        }
+    }
+
+    let start = segments
+        .iter()
+        .flat_map(|s| [&s.from, &s.to])
+        .filter(|fix| fix.ident == entry)
+        .map(|fix| (fix, geodesy::distance_nm(fix.coordinates, entry_position)))
+        .filter(|(_, distance)| *distance <= MAX_ENTRY_DISTANCE_NM)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))?
+        .0;
+
+    // A breadth-first search, remembering how every fix was reached.
+    let mut previous: HashMap<_, Option<&AirwayFix>> = HashMap::from([(key(start), None)]);
+    let mut queue = VecDeque::from([start]);
+
+    while let Some(fix) = queue.pop_front() {
+        if fix.ident == exit {
+            let mut path = vec![fix.clone()];
+            let mut current = fix;
+            while let Some(Some(before)) = previous.get(&key(current)) {
+                path.push((*before).clone());
+                current = before;
+            }
+
+            // Without the entry, which is the last one.
+            path.pop();
+            path.reverse();
+            return Some(path);
+        }
+
+        for following in next.get(&key(fix)).into_iter().flatten() {
+            if let Entry::Vacant(entry) = previous.entry(key(following)) {
+                entry.insert(Some(fix));
+                queue.push_back(following);
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIX_DAT: &str = "I\n1101 Version\n\
+        -34.80 -58.30 EZE11 ENRT SA\n\
+        -34.60 -57.50 GESOL ENRT SA\n\
+        45.60 2.50 GESOL ENRT LF\n\
+        -34.40 -56.70 ARSOT ENRT SU\n\
+        -34.90 -57.60 BAJOS ENRT SA\n\
+        99\n";
+
+    const AWY_DAT: &str = "I\n1100 Version\n\
+        EZE11 SA 11 GESOL SA 11 N 2 245 600 UM540\n\
+        GESOL SA 11 ARSOT SU 11 N 2 245 600 UM540\n\
+        GESOL SA 11 ARSOT SU 11 F 2 245 600 UW10\n\
+        EZE11 SA 11 ARSOT SU 11 N 2 245 600 A1\n\
+        EZE11 SA 11 BAJOS SA 11 N 1 30 180 A1\n\
+        BAJOS SA 11 ARSOT SU 11 N 1 30 180 A1\n\
+        NOFIX SA 11 ARSOT SU 11 N 2 245 600 UM540\n\
+        99\n";
+
+    const EZE11: Coordinates = Coordinates {
+        lon: -58.30,
+        lat: -34.80,
+    };
+    const GESOL: Coordinates = Coordinates {
+        lon: -57.50,
+        lat: -34.60,
+    };
+    const ARSOT: Coordinates = Coordinates {
+        lon: -56.70,
+        lat: -34.40,
+    };
+
+    fn database() -> AirwayDatabase {
+        let mut fixes = FixDatabase::default();
+        fixes.read_fix_dat(FIX_DAT.as_bytes()).unwrap();
+
+        let mut database = AirwayDatabase::default();
+        database.read_awy_dat(AWY_DAT.as_bytes(), &fixes).unwrap();
+        database
+    }
+
+    fn idents(fixes: &[AirwayFix]) -> Vec<&str> {
+        fixes.iter().map(|f| f.ident.as_str()).collect()
+    }
+
+    #[test]
+    fn reads_the_fixes_of_the_1100_format_by_region() {
+        let database = database();
+        assert!(database.contains("um540"));
+
+        let fixes = database
+            .expand("UM540", "EZE11", EZE11, "ARSOT", AirwayLevel::High)
+            .unwrap();
+        assert_eq!(idents(&fixes), ["GESOL", "ARSOT"]);
+        // The GESOL of the SA region, not the one in France.
+        assert_eq!(fixes[0].coordinates, GESOL);
+
+        // The segment whose fix isn't in the fix.dat is skipped.
+        assert_eq!(
+            database.expand("UM540", "EZE11", EZE11, "NOFIX", AirwayLevel::High),
+            Err(String::from("NOFIX isn't on the airway UM540"))
+        );
+    }
+
+    #[test]
+    fn expands_both_ways() {
+        let database = database();
+
+        let fixes = database
+            .expand("um540", "arsot", ARSOT, "eze11", AirwayLevel::High)
+            .unwrap();
+        assert_eq!(idents(&fixes), ["GESOL", "EZE11"]);
+    }
+
+    #[test]
+    fn flies_one_way_segments_only_their_way() {
+        let database = database();
+
+        let fixes = database
+            .expand("UW10", "GESOL", GESOL, "ARSOT", AirwayLevel::High)
+            .unwrap();
+        assert_eq!(idents(&fixes), ["ARSOT"]);
+
+        assert_eq!(
+            database.expand("UW10", "ARSOT", ARSOT, "GESOL", AirwayLevel::High),
+            Err(String::from(
+                "The airway UW10 is one way, it can't be flown from ARSOT to GESOL"
+            ))
+        );
+    }
+
+    #[test]
+    fn prefers_the_segments_of_the_level() {
+        let database = database();
+
+        let high = database
+            .expand("A1", "EZE11", EZE11, "ARSOT", AirwayLevel::High)
+            .unwrap();
+        assert_eq!(idents(&high), ["ARSOT"]);
+
+        let low = database
+            .expand("A1", "EZE11", EZE11, "ARSOT", AirwayLevel::Low)
+            .unwrap();
+        assert_eq!(idents(&low), ["BAJOS", "ARSOT"]);
+
+        // Without a high segment, the low ones are flown.
+        let fixes = database
+            .expand(
+                "A1",
+                "BAJOS",
+                low[0].coordinates,
+                "EZE11",
+                AirwayLevel::High,
+            )
+            .unwrap();
+        assert_eq!(idents(&fixes), ["EZE11"]);
+    }
+
+    #[test]
+    fn fails_for_unknown_airways_and_fixes() {
+        let database = database();
+        let expand =
+            |name, entry, exit| database.expand(name, entry, EZE11, exit, AirwayLevel::High);
+
+        assert_eq!(
+            expand("UM999", "EZE11", "ARSOT"),
+            Err(String::from("Unknown airway UM999"))
+        );
+        assert_eq!(
+            expand("UM540", "BAJOS", "ARSOT"),
+            Err(String::from("BAJOS isn't on the airway UM540"))
+        );
+        assert_eq!(
+            expand("UM540", "EZE11", "BAJOS"),
+            Err(String::from("BAJOS isn't on the airway UM540"))
+        );
+
+        // An entry far from the route is an airway with the same name somewhere else.
+        assert!(database
+            .expand("UM540", "EZE11", ARSOT, "GESOL", AirwayLevel::High)
+            .is_err());
+    }
+}
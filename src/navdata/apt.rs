@@ -0,0 +1,429 @@
+use std::{collections::HashMap, error::Error, io::BufRead, path::Path};
+
+use crate::{
+    geodesy::{self, Coordinates},
+    Airport,
+};
+
+/// An airport of the `apt.dat` file.
+#[derive(Clone, PartialEq, Debug)]
+pub struct AirportData {
+    pub ident: String,
+    pub name: String,
+    /// The IATA code of the airport, like `EZE`, when the file has it.
+    pub iata: Option<String>,
+    pub elevation_ft: f64,
+    /// The reference point of the airport, or the middle of its runways when the file doesn't
+    /// have one.
+    pub coordinates: Coordinates,
+    /// The ends of the runways, and the helipads.
+    pub runways: Vec<Runway>,
+}
+
+/// One end of a runway, like `11` or `34L`, or a helipad, like `H1`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Runway {
+    pub ident: String,
+    /// The threshold of the runway, or the center of the helipad.
+    pub threshold: Coordinates,
+    /// The true heading of the runway, from this threshold to the opposite one, or the
+    /// orientation of the helipad.
+    pub heading: f64,
+    pub length_m: f64,
+}
+
+impl Runway {
+    /// Checks if it's a helipad, whose ident starts with `H`.
+    pub fn is_helipad(&self) -> bool {
+        self.ident.starts_with('H')
+    }
+}
+
+/// The airports of FlightGear's `$FG_ROOT/Airports/apt.dat.gz`, by ICAO code.
+#[derive(Default)]
+pub struct AirportDatabase {
+    airports: HashMap<String, AirportData>,
+    /// The ICAO codes of the airports, by IATA code.
+    iata_codes: HashMap<String, String>,
+}
+
+impl AirportDatabase {
+    /// Reads the airports of an `apt.dat` file, compressed or not.
+    ///
+    /// # Errors
+    /// This function fails if the file can't be read.
+    pub fn from_apt_dat(path: &Path) -> Result<AirportDatabase, Box<dyn Error>> {
+        AirportDatabase::from_reader(super::open(path)?)
+    }
+
+    /// Reads the airports of the contents of an `apt.dat` file. Lines that can't be understood are
+    /// skipped.
+    ///
+    /// # Errors
+    /// This function fails if the contents can't be read.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use kml_to_fgfp::navdata::AirportDatabase;
+    /// let apt_dat = "I\n1100 Version\n\n\
+    ///     1 20 0 0 SAEZ Ministro Pistarini\n\
+    ///     100 45 1 0 0.25 0 2 1 11 -34.81340 -58.56280 0 0 3 0 0 0 29 -34.82857 -58.52217 0 0 3 0 0 0\n\
+    ///     1302 iata_code EZE\n\
+    ///     99\n";
+    ///
+    /// let database = AirportDatabase::from_reader(apt_dat.as_bytes()).unwrap();
+    /// let saez = database.get("SAEZ").unwrap();
+    /// assert_eq!(saez.name, "Ministro Pistarini");
+    /// assert_eq!(saez.iata.as_deref(), Some("EZE"));
+    /// assert_eq!(database.get_by_iata("eze"), Some(saez));
+    /// assert_eq!(saez.runways[0].ident, "11");
+    /// assert!((saez.runways[0].heading - 115.0).abs() < 1.0);
+    /// ```
+    pub fn from_reader(reader: impl BufRead) -> Result<AirportDatabase, Box<dyn Error>> {
+        let mut airports = HashMap::new();
+        let mut current: Option<(AirportData, Option<f64>, Option<f64>)> = None;
+
+        for line in reader.lines() {
+            let line = line?;
+            let data: Vec<&str> = line.split_whitespace().collect();
+
+            match data.first().copied() {
+                // A land airport, a seaplane base or a heliport, and the end of the file.
+                Some("1" | "16" | "17" | "99") => {
+                    if let Some(airport) = current.take().and_then(finish_airport) {
+                        airports.insert(airport.ident.clone(), airport);
+                    }
+
+                    if data.len() >= 5 && data[0] != "99" {
+                        current = Some((
+                            AirportData {
+                                ident: data[4].to_uppercase(),
+                                name: data[5..].join(" "),
+                                iata: None,
+                                elevation_ft: data[1].parse().unwrap_or(0.0),
+                                coordinates: Coordinates { lon: 0.0, lat: 0.0 },
+                                runways: Vec::new(),
+                            },
+                            None,
+                            None,
+                        ));
+                    }
+                }
+                Some("100") => {
+                    if let Some((airport, _, _)) = current.as_mut() {
+                        airport.runways.extend(read_runway(&data));
+                    }
+                }
+                Some("102") => {
+                    if let Some((airport, _, _)) = current.as_mut() {
+                        airport.runways.extend(read_helipad(&data));
+                    }
+                }
+                Some("1302") => {
+                    if let Some((airport, lat, lon)) = current.as_mut() {
+                        let value = data.get(2).and_then(|v| v.parse().ok());
+                        match data.get(1).copied() {
+                            Some("datum_lat") => *lat = value,
+                            Some("datum_lon") => *lon = value,
+                            Some("iata_code") => {
+                                airport.iata = data.get(2).map(|v| v.to_uppercase())
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        if let Some(airport) = current.and_then(finish_airport) {
+            airports.insert(airport.ident.clone(), airport);
+        }
+
+        // A few IATA codes are in more than one airport, the first ICAO code keeps them.
+        let mut idents: Vec<&String> = airports.keys().collect();
+        idents.sort();
+
+        let mut iata_codes = HashMap::new();
+        for ident in idents {
+            if let Some(iata) = &airports[ident].iata {
+                iata_codes
+                    .entry(iata.clone())
+                    .or_insert_with(|| ident.clone());
+            }
+        }
+
+        Ok(AirportDatabase {
+            airports,
+            iata_codes,
+        })
+    }
+
+    /// Returns the airport with the ICAO code `ident`, if it's in the database.
+    pub fn get(&self, ident: &str) -> Option<&AirportData> {
+        self.airports.get(&ident.trim().to_uppercase())
+    }
+
+    /// Returns the airport with the IATA code `iata`, if it's in the database.
+    pub fn get_by_iata(&self, iata: &str) -> Option<&AirportData> {
+        let icao = self.iata_codes.get(&iata.trim().to_uppercase())?;
+        self.airports.get(icao)
+    }
+
+    /// Returns every airport of the database.
+    pub fn airports(&self) -> impl Iterator<Item = &AirportData> {
+        self.airports.values()
+    }
+
+    /// Checks that the `airport` and its runway exist, and fills in its coordinates. An airport
+    /// named by its IATA code, like `EZE`, gets its ICAO code.
+    ///
+    /// # Errors
+    /// This function fails if the airport or its runway aren't in the database, suggesting the
+    /// closest runway of the airport or airports with a similar code.
+    pub fn resolve(&self, airport: &mut Airport) -> Result<(), String> {
+        let data = match self
+            .get(&airport.ident)
+            .or_else(|| self.get_by_iata(&airport.ident))
+        {
+            Some(data) => data,
+            None => return Err(self.unknown_airport(&airport.ident)),
+        };
+
+        airport.ident = data.ident.clone();
+        airport.name = Some(data.name.clone());
+        airport.iata = data.iata.clone().or(airport.iata.take());
+        airport.elevation_ft = Some(data.elevation_ft);
+        airport.coordinates = Some(data.coordinates);
+
+        if let Some(requested) = &airport.runway {
+            let runway = match data
+                .runways
+                .iter()
+                .find(|r| same_runway(&r.ident, requested))
+            {
+                Some(runway) => runway,
+                None => {
+                    let mut error = format!("Unknown runway {requested} at {}", data.ident);
+                    if let Some(closest) = closest_runway(&data.runways, requested) {
+                        error += &format!(", did you mean {}?", closest.ident);
+                    }
+                    return Err(error);
+                }
+            };
+
+            airport.runway = Some(runway.ident.clone());
+            airport.threshold = Some(runway.threshold);
+            airport.runway_heading = Some(runway.heading);
+        }
+
+        Ok(())
+    }
+
+    /// Internal function that builds the error for an unknown airport, suggesting the airports
+    /// whose code differs in a single letter.
+    fn unknown_airport(&self, ident: &str) -> String {
+        let ident = ident.trim().to_uppercase();
+
+        let mut similar: Vec<&str> = self
+            .airports
+            .keys()
+            .filter(|other| {
+                other.len() == ident.len()
+                    && other
+                        .chars()
+                        .zip(ident.chars())
+                        .filter(|(a, b)| a != b)
+                        .count()
+                        == 1
+            })
+            .map(|other| other.as_str())
+            .collect();
+        similar.sort_unstable();
+        similar.truncate(5);
+
+        match similar[..] {
+            [] => format!("Unknown airport {ident}"),
+            _ => format!(
+                "Unknown airport {ident}, did you mean {}?",
+                similar.join(" or ")
+            ),
+        }
+    }
+}
+
+/// Internal function that reads both ends of a land runway (row code 100).
+fn read_runway(data: &[&str]) -> Vec<Runway> {
+    let end = |i: usize| -> Option<(String, Coordinates)> {
+        let ident = data.get(i)?.to_uppercase();
+        let lat = data.get(i + 1)?.parse().ok()?;
+        let lon = data.get(i + 2)?.parse().ok()?;
+
+        Some((ident, Coordinates { lon, lat }))
+    };
+
+    let (first, second) = match (end(8), end(17)) {
+        (Some(first), Some(second)) => (first, second),
+        _ => return Vec::new(),
+    };
+
+    let length_m = geodesy::distance_nm(first.1, second.1) * 1852.0;
+
+    vec![
+        Runway {
+            heading: geodesy::initial_course(first.1, second.1),
+            ident: first.0,
+            threshold: first.1,
+            length_m,
+        },
+        Runway {
+            heading: geodesy::initial_course(second.1, first.1),
+            ident: second.0,
+            threshold: second.1,
+            length_m,
+        },
+    ]
+}
+
+/// Internal function that reads a helipad (row code 102).
+fn read_helipad(data: &[&str]) -> Option<Runway> {
+    let number = |i: usize| -> Option<f64> { data.get(i)?.parse().ok() };
+
+    Some(Runway {
+        ident: data.get(1)?.to_uppercase(),
+        threshold: Coordinates {
+            lon: number(3)?,
+            lat: number(2)?,
+        },
+        heading: number(4)?,
+        length_m: number(5)?,
+    })
+}
+
+/// Internal function that sets the coordinates of an airport once all its rows were read.
+fn finish_airport(
+    (mut airport, lat, lon): (AirportData, Option<f64>, Option<f64>),
+) -> Option<AirportData> {
+    airport.coordinates = match (lat, lon) {
+        (Some(lat), Some(lon)) => Coordinates { lon, lat },
+        _ if !airport.runways.is_empty() => {
+            let count = airport.runways.len() as f64;
+            Coordinates {
+                lon: airport.runways.iter().map(|r| r.threshold.lon).sum::<f64>() / count,
+                lat: airport.runways.iter().map(|r| r.threshold.lat).sum::<f64>() / count,
+            }
+        }
+        // Without a position the airport isn't useful.
+        _ => return None,
+    };
+
+    Some(airport)
+}
+
+/// Internal function that splits a runway ident like `09L` into its number and side.
+fn split_runway(ident: &str) -> Option<(u32, String)> {
+    let ident = ident.trim().to_uppercase();
+    let split = ident
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(ident.len());
+
+    Some((ident[..split].parse().ok()?, ident[split..].to_string()))
+}
+
+/// Internal function that compares runway idents, such that `9` and `09` are the same runway.
+pub(super) fn same_runway(a: &str, b: &str) -> bool {
+    match (split_runway(a), split_runway(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => a.trim().eq_ignore_ascii_case(b.trim()),
+    }
+}
+
+/// Internal function that finds the runway whose number is the closest to the `requested` one,
+/// preferring the same side (`L`, `C` or `R`).
+fn closest_runway<'a>(runways: &'a [Runway], requested: &str) -> Option<&'a Runway> {
+    let (number, side) = split_runway(requested)?;
+
+    let score = |runway: &Runway| {
+        let (other, other_side) = split_runway(&runway.ident)?;
+        let difference = (other as f64 - number as f64).abs() * 10.0;
+        let difference = difference.min(360.0 - difference);
+
+        Some(difference + if other_side == side { 0.0 } else { 5.0 })
+    };
+
+    runways
+        .iter()
+        .filter_map(|r| score(r).map(|s| (r, s)))
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(r, _)| r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const APT_DAT: &str = "I\n1100 Version\n\n\
+        1 20 0 0 SAEZ Ministro Pistarini\n\
+        1302 iata_code EZE\n\
+        100 45 1 0 0.25 0 2 1 11 -34.81340 -58.56280 0 0 3 0 0 0 29 -34.82857 -58.52217 0 0 3 0 0 0\n\
+        102 H1 -34.81900 -58.54000 45.00 20.00 20.00 1 0 0 0.25 0\n\
+        17 10 0 0 SA01 Hospital Heliport\n\
+        102 H1 -34.60000 -58.40000 0.00 15.00 15.00 1 0 0 0.25 0\n\
+        99\n";
+
+    #[test]
+    fn reads_helipads() {
+        let database = AirportDatabase::from_reader(APT_DAT.as_bytes()).unwrap();
+
+        let saez = database.get("SAEZ").unwrap();
+        let idents: Vec<&str> = saez.runways.iter().map(|r| r.ident.as_str()).collect();
+        assert_eq!(idents, ["11", "29", "H1"]);
+        assert!(saez.runways[2].is_helipad() && !saez.runways[0].is_helipad());
+        assert_eq!(saez.runways[2].heading, 45.0);
+
+        // Heliports are found by their helipads.
+        let heliport = database.get("SA01").unwrap();
+        assert_eq!(
+            heliport.coordinates,
+            Coordinates {
+                lon: -58.4,
+                lat: -34.6
+            }
+        );
+    }
+
+    #[test]
+    fn resolves_helipads() {
+        let database = AirportDatabase::from_reader(APT_DAT.as_bytes()).unwrap();
+
+        let mut airport: Airport = "SAEZ/H1".parse().unwrap();
+        database.resolve(&mut airport).unwrap();
+        assert_eq!(
+            airport.threshold,
+            Some(Coordinates {
+                lon: -58.54,
+                lat: -34.819
+            })
+        );
+
+        let mut airport: Airport = "SAEZ/H2".parse().unwrap();
+        assert_eq!(
+            database.resolve(&mut airport),
+            Err(String::from("Unknown runway H2 at SAEZ"))
+        );
+    }
+
+    #[test]
+    fn resolves_iata_codes() {
+        let database = AirportDatabase::from_reader(APT_DAT.as_bytes()).unwrap();
+
+        let mut airport: Airport = "eze/11".parse().unwrap();
+        database.resolve(&mut airport).unwrap();
+        assert_eq!(airport.ident, "SAEZ");
+        assert_eq!(airport.iata.as_deref(), Some("EZE"));
+        assert!(airport.threshold.is_some());
+
+        assert!(database.get_by_iata("SA01").is_none());
+    }
+}
@@ -0,0 +1,307 @@
+use std::{collections::HashMap, error::Error, io::BufRead, path::Path};
+
+use super::UserWaypoints;
+use crate::geodesy::{self, Coordinates};
+
+/// A fix or navaid of the `fix.dat` and `nav.dat` files.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Fix {
+    pub ident: String,
+    pub coordinates: Coordinates,
+    pub kind: FixKind,
+    /// The ICAO region of the fix, like `SA`, which the newer files have.
+    pub region: Option<String>,
+}
+
+/// What kind of point a [`Fix`](Fix) is.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FixKind {
+    /// An intersection of the `fix.dat` file.
+    Fix,
+    Ndb,
+    Vor,
+    Dme,
+    /// A waypoint of the user's library, which FlightGear doesn't know.
+    User,
+}
+
+/// The fixes and navaids of FlightGear's `$FG_ROOT/Navaids/fix.dat.gz` and `nav.dat.gz`, by ident.
+///
+/// Many fixes around the world share an ident, so they're told apart by their position.
+#[derive(Default)]
+pub struct FixDatabase {
+    fixes: HashMap<String, Vec<Fix>>,
+}
+
+impl FixDatabase {
+    /// Reads the fixes of FlightGear's `Navaids/fix.dat.gz` and the navaids of
+    /// `Navaids/nav.dat.gz`, in the `fg_root` directory.
+    ///
+    /// # Errors
+    /// This function fails if any of the files can't be read.
+    pub fn from_fg_root(fg_root: &Path) -> Result<FixDatabase, Box<dyn Error>> {
+        let mut database = FixDatabase::default();
+        database.read_fix_dat(super::open(&fg_root.join("Navaids/fix.dat.gz"))?)?;
+        database.read_nav_dat(super::open(&fg_root.join("Navaids/nav.dat.gz"))?)?;
+
+        Ok(database)
+    }
+
+    /// Reads the contents of a `fix.dat` file, where every line is `LAT LON IDENT ...`, followed by
+    /// the airport and the ICAO region since version 1101.
+    ///
+    /// # Errors
+    /// This function fails if the contents can't be read.
+    pub fn read_fix_dat(&mut self, reader: impl BufRead) -> Result<(), Box<dyn Error>> {
+        let mut version = 0;
+
+        for line in reader.lines() {
+            let line = line?;
+            let data: Vec<&str> = line.split_whitespace().collect();
+
+            if let Some(v) = super::read_version(&data) {
+                version = v;
+                continue;
+            }
+
+            if let [lat, lon, ident, ..] = data[..] {
+                if let (Ok(lat), Ok(lon)) = (lat.parse(), lon.parse()) {
+                    let region = data.get(4).filter(|_| version >= 1101);
+                    self.insert(ident, Coordinates { lon, lat }, FixKind::Fix, region);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the NDBs, VORs and DMEs of the contents of a `nav.dat` file, where every line is
+    /// `TYPE LAT LON ELEVATION FREQUENCY RANGE VARIATION IDENT ...`, followed by the airport and the
+    /// ICAO region since version 1100. The parts of ILSs are skipped.
+    ///
+    /// # Errors
+    /// This function fails if the contents can't be read.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use kml_to_fgfp::{geodesy::Coordinates, navdata::{FixDatabase, FixKind}};
+    /// let nav_dat = "I\n1150 Version\n\
+    ///     3 -34.86008 -58.54400 66 11590 130 -8.0 EZE EZEIZA VOR-DME\n\
+    ///     99\n";
+    ///
+    /// let mut database = FixDatabase::default();
+    /// database.read_nav_dat(nav_dat.as_bytes()).unwrap();
+    ///
+    /// let (fix, distance) = database
+    ///     .nearest("EZE", Coordinates { lon: -58.55, lat: -34.85 })
+    ///     .unwrap();
+    /// assert_eq!(fix.kind, FixKind::Vor);
+    /// assert!(distance < 1.0);
+    /// ```
+    pub fn read_nav_dat(&mut self, reader: impl BufRead) -> Result<(), Box<dyn Error>> {
+        let mut version = 0;
+
+        for line in reader.lines() {
+            let line = line?;
+            let data: Vec<&str> = line.split_whitespace().collect();
+
+            if let Some(v) = super::read_version(&data) {
+                version = v;
+                continue;
+            }
+
+            let kind = match data.first().copied() {
+                Some("2") => FixKind::Ndb,
+                Some("3") => FixKind::Vor,
+                Some("12" | "13") => FixKind::Dme,
+                _ => continue,
+            };
+
+            if let [_, lat, lon, _, _, _, _, ident, ..] = data[..] {
+                if let (Ok(lat), Ok(lon)) = (lat.parse(), lon.parse()) {
+                    // A VOR-DME is in the file twice, as a VOR and as a DME.
+                    let coordinates = Coordinates { lon, lat };
+                    if kind == FixKind::Dme && self.is_at(ident, coordinates) {
+                        continue;
+                    }
+                    let region = data.get(9).filter(|_| version >= 1100);
+                    self.insert(ident, coordinates, kind, region);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Adds the waypoints of the user's `library`, which take priority over the fixes and navaids
+    /// with the same ident.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use kml_to_fgfp::{geodesy::Coordinates, navdata::{FixDatabase, FixKind, UserWaypoints}};
+    /// let mut database = FixDatabase::default();
+    /// database.read_fix_dat("I\n1101 Version\n-34.50 -58.50 PUMA ENRT SA\n99\n".as_bytes()).unwrap();
+    ///
+    /// let library = UserWaypoints::from_reader("PUMA,-34.62,-58.71".as_bytes()).unwrap();
+    /// database.add_user_waypoints(&library);
+    ///
+    /// let (fix, _) = database.nearest("PUMA", Coordinates { lon: -58.50, lat: -34.50 }).unwrap();
+    /// assert_eq!(fix.kind, FixKind::User);
+    /// ```
+    pub fn add_user_waypoints(&mut self, library: &UserWaypoints) {
+        for waypoint in library.iter() {
+            self.insert(&waypoint.ident, waypoint.coordinates, FixKind::User, None);
+        }
+    }
+
+    /// Returns the fix or navaid called `ident` that's the nearest to `position`, and how far it
+    /// is in nautical miles. The user's waypoints are returned before any other.
+    pub fn nearest(&self, ident: &str, position: Coordinates) -> Option<(&Fix, f64)> {
+        let fixes = self.fixes.get(&ident.trim().to_uppercase())?;
+        let has_user = fixes.iter().any(|fix| fix.kind == FixKind::User);
+
+        fixes
+            .iter()
+            .filter(|fix| !has_user || fix.kind == FixKind::User)
+            .map(|fix| (fix, geodesy::distance_nm(fix.coordinates, position)))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+    }
+
+    /// Returns the fix or navaid called `ident` in the ICAO `region`.
+    pub fn find(&self, ident: &str, region: &str) -> Option<&Fix> {
+        self.fixes
+            .get(&ident.trim().to_uppercase())?
+            .iter()
+            .find(|fix| fix.region.as_deref() == Some(region))
+    }
+
+    /// Internal function that adds a fix to the database.
+    fn insert(
+        &mut self,
+        ident: &str,
+        coordinates: Coordinates,
+        kind: FixKind,
+        region: Option<&&str>,
+    ) {
+        let ident = ident.to_uppercase();
+
+        self.fixes.entry(ident.clone()).or_default().push(Fix {
+            ident,
+            coordinates,
+            kind,
+            region: region.map(|r| r.to_uppercase()),
+        });
+    }
+
+    /// Internal function that checks if there's already a fix called `ident` at `coordinates`.
+    fn is_at(&self, ident: &str, coordinates: Coordinates) -> bool {
+        self.nearest(ident, coordinates)
+            .is_some_and(|(_, distance)| distance < 0.1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_regions_of_1101_files() {
+        let fix_dat = "I\n1101 Version - data cycle 2013.10\n\
+            -34.60 -57.50 GESOL ENRT SA\n\
+            45.60 2.50 gesol ENRT lf\n\
+            99\n";
+
+        let mut database = FixDatabase::default();
+        database.read_fix_dat(fix_dat.as_bytes()).unwrap();
+
+        let fix = database.find("GESOL", "LF").unwrap();
+        assert_eq!(fix.ident, "GESOL");
+        assert_eq!(fix.region.as_deref(), Some("LF"));
+        assert_eq!(
+            fix.coordinates,
+            Coordinates {
+                lon: 2.50,
+                lat: 45.60
+            }
+        );
+        assert_eq!(fix.kind, FixKind::Fix);
+
+        assert!(database.find("GESOL", "SU").is_none());
+    }
+
+    #[test]
+    fn has_no_regions_before_1101() {
+        let fix_dat = "I\n600 Version\n-34.60 -57.50 GESOL\n99\n";
+
+        let mut database = FixDatabase::default();
+        database.read_fix_dat(fix_dat.as_bytes()).unwrap();
+
+        let (fix, _) = database
+            .nearest(
+                "GESOL",
+                Coordinates {
+                    lon: -57.5,
+                    lat: -34.6,
+                },
+            )
+            .unwrap();
+        assert_eq!(fix.region, None);
+    }
+
+    #[test]
+    fn finds_the_nearest_fix_with_the_ident() {
+        let fix_dat = "I\n1101 Version\n\
+            -34.60 -57.50 GESOL ENRT SA\n\
+            45.60 2.50 GESOL ENRT LF\n\
+            -33.00 -57.00 GESOL ENRT SU\n\
+            99\n";
+
+        let mut database = FixDatabase::default();
+        database.read_fix_dat(fix_dat.as_bytes()).unwrap();
+
+        let (fix, distance) = database
+            .nearest(
+                "gesol",
+                Coordinates {
+                    lon: -57.4,
+                    lat: -34.5,
+                },
+            )
+            .unwrap();
+        assert_eq!(fix.region.as_deref(), Some("SA"));
+        assert!(distance > 5.0 && distance < 10.0, "{distance}");
+
+        let (fix, _) = database
+            .nearest(
+                "GESOL",
+                Coordinates {
+                    lon: 3.0,
+                    lat: 45.0,
+                },
+            )
+            .unwrap();
+        assert_eq!(fix.region.as_deref(), Some("LF"));
+
+        assert!(database
+            .nearest("ARSOT", Coordinates { lon: 0.0, lat: 0.0 })
+            .is_none());
+    }
+
+    #[test]
+    fn reads_vor_dmes_once() {
+        let nav_dat = "I\n1150 Version\n\
+            3 -34.86008 -58.54400 66 11590 130 -8.0 EZE ENRT SA EZEIZA VOR-DME\n\
+            12 -34.86008 -58.54400 66 11590 130 0.0 EZE ENRT SA EZEIZA VOR-DME\n\
+            4 -34.81 -58.56 66 11030 18 110.0 IEZ SAEZ SA 11 ILS-cat-I\n\
+            99\n";
+
+        let mut database = FixDatabase::default();
+        database.read_nav_dat(nav_dat.as_bytes()).unwrap();
+
+        assert_eq!(database.fixes["EZE"].len(), 1);
+        assert_eq!(database.fixes["EZE"][0].region.as_deref(), Some("SA"));
+        assert!(!database.fixes.contains_key("IEZ"));
+    }
+}
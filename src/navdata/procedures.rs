@@ -0,0 +1,522 @@
+use std::{error::Error, fmt, fs::File, io::BufReader, io::Read, path::PathBuf};
+
+use xml::{attribute::OwnedAttribute, reader::XmlEvent, EventReader};
+
+use super::apt::same_runway;
+use crate::{geodesy::Coordinates, AltRestrict};
+
+/// The kind of a [`Procedure`](Procedure).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ProcedureKind {
+    Sid,
+    Star,
+    Approach,
+}
+
+impl fmt::Display for ProcedureKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProcedureKind::Sid => write!(f, "SID"),
+            ProcedureKind::Star => write!(f, "STAR"),
+            ProcedureKind::Approach => write!(f, "approach"),
+        }
+    }
+}
+
+/// A fix of a procedure, with the restrictions to cross it.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ProcedureFix {
+    pub ident: String,
+    pub coordinates: Coordinates,
+    /// The altitude restriction and the altitude in feet, when the fix has one.
+    pub altitude: Option<(AltRestrict, f64)>,
+    /// The highest speed in knots, when the fix has one.
+    pub speed: Option<f64>,
+}
+
+/// A transition of a procedure, which joins it to the route (or the route to it, for arrivals).
+#[derive(Clone, PartialEq, Debug)]
+pub struct Transition {
+    pub name: String,
+    pub fixes: Vec<ProcedureFix>,
+}
+
+/// A SID, STAR or approach. Only the fixes with a position are read, the legs flown by heading
+/// are skipped, and so are the missed approaches.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Procedure {
+    pub name: String,
+    pub kind: ProcedureKind,
+    /// The runways the procedure is for, empty when it's for all of them.
+    pub runways: Vec<String>,
+    pub fixes: Vec<ProcedureFix>,
+    pub transitions: Vec<Transition>,
+}
+
+impl Procedure {
+    /// Checks if the procedure can be flown from or to the `runway`.
+    pub fn is_for(&self, runway: &str) -> bool {
+        self.runways.is_empty() || self.runways.iter().any(|r| same_runway(r, runway))
+    }
+}
+
+/// The procedures of an airport, from FlightGear's Level-D style `ICAO.procedures.xml` files.
+#[derive(Default)]
+pub struct AirportProcedures {
+    pub ident: String,
+    pub procedures: Vec<Procedure>,
+}
+
+impl AirportProcedures {
+    /// Reads the procedures of the airport `icao` from the first of the `directories` that has
+    /// them, like `Airports/S/A/E/SAEZ.procedures.xml`.
+    ///
+    /// # Errors
+    /// This function fails if none of the directories has them, if the file can't be read, or if
+    /// it isn't valid xml.
+    pub fn find_in(
+        directories: &[PathBuf],
+        icao: &str,
+    ) -> Result<AirportProcedures, Box<dyn Error>> {
+        let icao = icao.trim().to_uppercase();
+
+        for directory in directories {
+            let mut path = directory.join("Airports");
+            for letter in icao.chars().take(3) {
+                path.push(letter.to_string());
+            }
+            path.push(format!("{icao}.procedures.xml"));
+
+            if path.is_file() {
+                let file =
+                    File::open(&path).map_err(|e| format!("Can't open {}: {e}", path.display()))?;
+                let mut procedures = AirportProcedures::from_reader(BufReader::new(file))?;
+                procedures.ident = icao;
+
+                return Ok(procedures);
+            }
+        }
+
+        Err(format!("No procedures for {icao}").into())
+    }
+
+    /// Reads the procedures of the contents of a `procedures.xml` file.
+    ///
+    /// # Errors
+    /// This function fails if the contents aren't valid xml.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use kml_to_fgfp::navdata::{AirportProcedures, ProcedureKind};
+    /// let xml = r#"<ProceduresDB><Airport ICAO="SAEZ">
+    ///     <Sid Name="ANDO1A" Runways="11,17">
+    ///         <Sid_Waypoint ID="1">
+    ///             <Name>EZE</Name><Type>Normal</Type>
+    ///             <Latitude>-34.86</Latitude><Longitude>-58.54</Longitude>
+    ///             <Speed>0</Speed><Altitude>3000</Altitude>
+    ///             <AltitudeRestriction>above</AltitudeRestriction>
+    ///         </Sid_Waypoint>
+    ///         <Sid_Transition Name="ARSOT">
+    ///             <SidTr_Waypoint ID="1">
+    ///                 <Name>ARSOT</Name><Type>Normal</Type>
+    ///                 <Latitude>-34.40</Latitude><Longitude>-56.70</Longitude>
+    ///             </SidTr_Waypoint>
+    ///         </Sid_Transition>
+    ///     </Sid>
+    /// </Airport></ProceduresDB>"#;
+    ///
+    /// let procedures = AirportProcedures::from_reader(xml.as_bytes()).unwrap();
+    /// let fixes = procedures
+    ///     .find(ProcedureKind::Sid, "ANDO1A", Some("ARSOT"), Some("11"))
+    ///     .unwrap();
+    /// let idents: Vec<&str> = fixes.iter().map(|f| f.ident.as_str()).collect();
+    /// assert_eq!(idents, ["EZE", "ARSOT"]);
+    ///
+    /// assert!(procedures.find(ProcedureKind::Sid, "ANDO1A", None, Some("29")).is_err());
+    /// ```
+    pub fn from_reader(reader: impl Read) -> Result<AirportProcedures, Box<dyn Error>> {
+        let mut airport = AirportProcedures::default();
+
+        let mut fix: Option<RawFix> = None;
+        let mut element = String::new();
+        let mut in_transition = false;
+        // The fixes after the runway of an approach are its missed approach.
+        let mut missed_approach = false;
+
+        for event in EventReader::new(reader) {
+            match event? {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => match name.local_name.as_str() {
+                    "Airport" => {
+                        airport.ident = attribute(&attributes, "ICAO").to_uppercase();
+                    }
+                    kind @ ("Sid" | "Star" | "Approach") => {
+                        let runways = attribute(&attributes, "Runways");
+                        airport.procedures.push(Procedure {
+                            name: attribute(&attributes, "Name").to_uppercase(),
+                            kind: match kind {
+                                "Sid" => ProcedureKind::Sid,
+                                "Star" => ProcedureKind::Star,
+                                _ => ProcedureKind::Approach,
+                            },
+                            runways: match runways.eq_ignore_ascii_case("all") {
+                                true => Vec::new(),
+                                false => runways
+                                    .split(',')
+                                    .map(|r| r.trim().to_uppercase())
+                                    .filter(|r| !r.is_empty())
+                                    .collect(),
+                            },
+                            fixes: Vec::new(),
+                            transitions: Vec::new(),
+                        });
+                        missed_approach = false;
+                    }
+                    "Sid_Transition" | "Star_Transition" | "App_Transition" => {
+                        if let Some(procedure) = airport.procedures.last_mut() {
+                            procedure.transitions.push(Transition {
+                                name: attribute(&attributes, "Name").to_uppercase(),
+                                fixes: Vec::new(),
+                            });
+                            in_transition = true;
+                        }
+                    }
+                    "Sid_Waypoint" | "Star_Waypoint" | "App_Waypoint" | "SidTr_Waypoint"
+                    | "StarTr_Waypoint" | "AppTr_Waypoint" => fix = Some(RawFix::default()),
+                    other => element = String::from(other),
+                },
+                XmlEvent::Characters(text) => {
+                    if let Some(fix) = fix.as_mut() {
+                        fix.set(&element, text.trim());
+                    }
+                }
+                XmlEvent::EndElement { name } => match name.local_name.as_str() {
+                    "Sid_Transition" | "Star_Transition" | "App_Transition" => {
+                        in_transition = false;
+                    }
+                    "Sid_Waypoint" | "Star_Waypoint" | "App_Waypoint" | "SidTr_Waypoint"
+                    | "StarTr_Waypoint" | "AppTr_Waypoint" => {
+                        let (Some(raw), Some(procedure)) =
+                            (fix.take(), airport.procedures.last_mut())
+                        else {
+                            continue;
+                        };
+
+                        if raw.kind.eq_ignore_ascii_case("runway")
+                            && procedure.kind == ProcedureKind::Approach
+                            && !in_transition
+                        {
+                            missed_approach = true;
+                        }
+
+                        let fixes = match procedure.transitions.last_mut() {
+                            Some(transition) if in_transition => &mut transition.fixes,
+                            _ if missed_approach => continue,
+                            _ => &mut procedure.fixes,
+                        };
+                        fixes.extend(raw.finish());
+                    }
+                    _ => element.clear(),
+                },
+                _ => (),
+            }
+        }
+
+        Ok(airport)
+    }
+
+    /// Returns the procedures of the `kind`.
+    pub fn list(&self, kind: ProcedureKind) -> impl Iterator<Item = &Procedure> {
+        self.procedures.iter().filter(move |p| p.kind == kind)
+    }
+
+    /// Returns the fixes of the procedure called `name`, in the order they're flown: the SIDs
+    /// before their `transition`, and the STARs and approaches after it.
+    ///
+    /// # Errors
+    /// This function fails if there's no such procedure or transition, or if the procedure isn't
+    /// for the `runway`. The errors list the procedures or transitions there are.
+    pub fn find(
+        &self,
+        kind: ProcedureKind,
+        name: &str,
+        transition: Option<&str>,
+        runway: Option<&str>,
+    ) -> Result<Vec<ProcedureFix>, String> {
+        let name = name.trim().to_uppercase();
+        let procedure = match self.list(kind).find(|p| p.name == name) {
+            Some(procedure) => procedure,
+            None => {
+                let names: Vec<&str> = self.list(kind).map(|p| p.name.as_str()).collect();
+                return Err(match names[..] {
+                    [] => format!("{} has no {kind}", self.ident),
+                    _ => format!(
+                        "Unknown {kind} {name} at {}, expected {}",
+                        self.ident,
+                        names.join(", ")
+                    ),
+                });
+            }
+        };
+
+        if let Some(runway) = runway.filter(|r| !procedure.is_for(r)) {
+            return Err(format!(
+                "The {kind} {name} isn't for runway {runway}, it's for {}",
+                procedure.runways.join(", ")
+            ));
+        }
+
+        let transition = match transition.map(|t| t.trim().to_uppercase()) {
+            Some(transition) => match procedure.transitions.iter().find(|t| t.name == transition) {
+                Some(found) => found.fixes.as_slice(),
+                None => {
+                    let names: Vec<&str> = procedure
+                        .transitions
+                        .iter()
+                        .map(|t| t.name.as_str())
+                        .collect();
+                    return Err(match names[..] {
+                        [] => format!("The {kind} {name} has no transitions"),
+                        _ => format!(
+                            "Unknown transition {transition} of the {kind} {name}, expected {}",
+                            names.join(", ")
+                        ),
+                    });
+                }
+            },
+            None => &[],
+        };
+
+        let (first, second) = match kind {
+            ProcedureKind::Sid => (&procedure.fixes[..], transition),
+            _ => (transition, &procedure.fixes[..]),
+        };
+
+        // The transition and the procedure share the fix where they meet.
+        let mut fixes = first.to_vec();
+        for fix in second {
+            if fixes.last().is_none_or(|last| last.ident != fix.ident) {
+                fixes.push(fix.clone());
+            }
+        }
+
+        Ok(fixes)
+    }
+}
+
+/// The values of a waypoint of the `procedures.xml` file, as they're read.
+#[derive(Default)]
+struct RawFix {
+    name: String,
+    kind: String,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    altitude: Option<f64>,
+    restriction: String,
+    speed: Option<f64>,
+}
+
+impl RawFix {
+    /// Stores the contents of the `element` of the waypoint.
+    fn set(&mut self, element: &str, value: &str) {
+        match element {
+            "Name" => self.name = value.to_uppercase(),
+            "Type" => self.kind = String::from(value),
+            "Latitude" => self.lat = value.parse().ok(),
+            "Longitude" => self.lon = value.parse().ok(),
+            "Altitude" => self.altitude = value.parse().ok(),
+            "AltitudeRestriction" => self.restriction = value.to_lowercase(),
+            "Speed" => self.speed = value.parse().ok(),
+            _ => (),
+        }
+    }
+
+    /// Builds the fix, if it's a fix with a position. Zeroes mean there's no restriction.
+    fn finish(self) -> Option<ProcedureFix> {
+        if !self.kind.eq_ignore_ascii_case("normal") {
+            return None;
+        }
+
+        let coordinates = Coordinates {
+            lon: self.lon?,
+            lat: self.lat?,
+        }
+        .validate()
+        .ok()?;
+
+        let restriction = match self.restriction.as_str() {
+            "above" => AltRestrict::Above,
+            "below" => AltRestrict::Below,
+            _ => AltRestrict::At,
+        };
+
+        Some(ProcedureFix {
+            ident: self.name,
+            coordinates,
+            altitude: self.altitude.filter(|a| *a > 0.0).map(|a| (restriction, a)),
+            speed: self.speed.filter(|s| *s > 0.0),
+        })
+    }
+}
+
+/// Internal function that returns the value of the attribute called `name`, or an empty string.
+fn attribute<'a>(attributes: &'a [OwnedAttribute], name: &str) -> &'a str {
+    attributes
+        .iter()
+        .find(|a| a.name.local_name == name)
+        .map_or("", |a| a.value.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A waypoint of a `procedures.xml` file, of the `element` and `kind`.
+    fn waypoint(element: &str, kind: &str, name: &str, lat: f64, altitude: u32) -> String {
+        format!(
+            "<{element} ID=\"1\"><Name>{name}</Name><Type>{kind}</Type>\
+            <Latitude>{lat}</Latitude><Longitude>-58.0</Longitude>\
+            <Speed>0</Speed><Altitude>{altitude}</Altitude>\
+            <AltitudeRestriction>above</AltitudeRestriction></{element}>"
+        )
+    }
+
+    fn procedures() -> AirportProcedures {
+        let xml = format!(
+            "<ProceduresDB><Airport ICAO=\"saez\">\
+            <Sid Name=\"ando1a\" Runways=\"11, 17\">{}{}\
+                <Sid_Transition Name=\"arsot\">{}{}</Sid_Transition>\
+            </Sid>\
+            <Sid Name=\"PUNTA1\" Runways=\"All\">{}</Sid>\
+            <Star Name=\"DORV2B\" Runways=\"\">{}{}\
+                <Star_Transition Name=\"ESL\">{}{}</Star_Transition>\
+            </Star>\
+            <Approach Name=\"ILS11\" Runways=\"11\">{}{}{}\
+                <App_Transition Name=\"DORVO\">{}{}</App_Transition>\
+            </Approach>\
+            </Airport></ProceduresDB>",
+            waypoint("Sid_Waypoint", "Normal", "EZE", -34.8, 3000),
+            waypoint("Sid_Waypoint", "Normal", "ANDOR", -34.7, 5000),
+            waypoint("SidTr_Waypoint", "Normal", "ANDOR", -34.7, 5000),
+            waypoint("SidTr_Waypoint", "Normal", "ARSOT", -34.4, 0),
+            waypoint("Sid_Waypoint", "Normal", "PUNTA", -34.6, 0),
+            waypoint("Star_Waypoint", "Normal", "DORVO", -35.0, 8000),
+            waypoint("Star_Waypoint", "Vectors", "VECT", -35.0, 0),
+            waypoint("StarTr_Waypoint", "Normal", "ESL", -35.5, 0),
+            waypoint("StarTr_Waypoint", "Normal", "DORVO", -35.0, 8000),
+            waypoint("App_Waypoint", "Normal", "FAP11", -34.9, 2000),
+            waypoint("App_Waypoint", "Runway", "RW11", -34.8, 0),
+            waypoint("App_Waypoint", "Normal", "MISSD", -34.7, 3000),
+            waypoint("AppTr_Waypoint", "Normal", "DORVO", -35.0, 8000),
+            waypoint("AppTr_Waypoint", "Normal", "FAP11", -34.9, 2000),
+        );
+
+        AirportProcedures::from_reader(xml.as_bytes()).unwrap()
+    }
+
+    fn idents(fixes: &[ProcedureFix]) -> Vec<&str> {
+        fixes.iter().map(|f| f.ident.as_str()).collect()
+    }
+
+    #[test]
+    fn reads_the_procedures_and_their_transitions() {
+        let procedures = procedures();
+        assert_eq!(procedures.ident, "SAEZ");
+
+        let sid = &procedures.procedures[0];
+        assert_eq!(sid.name, "ANDO1A");
+        assert_eq!(sid.runways, ["11", "17"]);
+        assert_eq!(idents(&sid.fixes), ["EZE", "ANDOR"]);
+        assert_eq!(sid.fixes[0].altitude, Some((AltRestrict::Above, 3000.0)));
+        assert_eq!(sid.fixes[0].speed, None);
+        assert_eq!(sid.transitions[0].name, "ARSOT");
+        assert_eq!(idents(&sid.transitions[0].fixes), ["ANDOR", "ARSOT"]);
+        assert_eq!(sid.transitions[0].fixes[1].altitude, None);
+
+        // The legs without a position are skipped.
+        let star = &procedures.procedures[2];
+        assert_eq!(star.kind, ProcedureKind::Star);
+        assert_eq!(idents(&star.fixes), ["DORVO"]);
+
+        // And so is the missed approach.
+        let approach = &procedures.procedures[3];
+        assert_eq!(approach.kind, ProcedureKind::Approach);
+        assert_eq!(idents(&approach.fixes), ["FAP11"]);
+        assert_eq!(idents(&approach.transitions[0].fixes), ["DORVO", "FAP11"]);
+    }
+
+    #[test]
+    fn tells_runway_procedures_from_all_runway_ones() {
+        let procedures = procedures();
+        let [sid, punta, star, approach] = &procedures.procedures[..] else {
+            panic!("expected 4 procedures");
+        };
+
+        assert!(sid.is_for("11") && sid.is_for("17") && !sid.is_for("29"));
+        assert!(punta.runways.is_empty() && punta.is_for("29"));
+        assert!(star.runways.is_empty() && star.is_for("35"));
+        assert!(approach.is_for("11") && !approach.is_for("29"));
+
+        assert_eq!(
+            procedures.find(ProcedureKind::Approach, "ILS11", None, Some("29")),
+            Err(String::from(
+                "The approach ILS11 isn't for runway 29, it's for 11"
+            ))
+        );
+        assert!(procedures
+            .find(ProcedureKind::Sid, "PUNTA1", None, Some("29"))
+            .is_ok());
+    }
+
+    #[test]
+    fn joins_the_transitions_in_the_order_theyre_flown() {
+        let procedures = procedures();
+
+        let sid = procedures
+            .find(ProcedureKind::Sid, "ANDO1A", Some("arsot"), None)
+            .unwrap();
+        assert_eq!(idents(&sid), ["EZE", "ANDOR", "ARSOT"]);
+
+        let star = procedures
+            .find(ProcedureKind::Star, "DORV2B", Some("ESL"), None)
+            .unwrap();
+        assert_eq!(idents(&star), ["ESL", "DORVO"]);
+
+        let approach = procedures
+            .find(ProcedureKind::Approach, "ILS11", Some("DORVO"), Some("11"))
+            .unwrap();
+        assert_eq!(idents(&approach), ["DORVO", "FAP11"]);
+    }
+
+    #[test]
+    fn lists_the_procedures_and_transitions_there_are() {
+        let procedures = procedures();
+
+        assert_eq!(
+            procedures.find(ProcedureKind::Sid, "ANDO2B", None, None),
+            Err(String::from(
+                "Unknown SID ANDO2B at SAEZ, expected ANDO1A, PUNTA1"
+            ))
+        );
+        assert_eq!(
+            procedures.find(ProcedureKind::Sid, "ANDO1A", Some("ESL"), None),
+            Err(String::from(
+                "Unknown transition ESL of the SID ANDO1A, expected ARSOT"
+            ))
+        );
+        assert_eq!(
+            procedures.find(ProcedureKind::Sid, "PUNTA1", Some("ESL"), None),
+            Err(String::from("The SID PUNTA1 has no transitions"))
+        );
+        assert_eq!(
+            AirportProcedures::from_reader(
+                "<ProceduresDB><Airport ICAO=\"SUMU\"/></ProceduresDB>".as_bytes()
+            )
+            .unwrap()
+            .find(ProcedureKind::Star, "DORV2B", None, None),
+            Err(String::from("SUMU has no STAR"))
+        );
+    }
+}
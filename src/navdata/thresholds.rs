@@ -0,0 +1,307 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufReader, Read},
+    path::PathBuf,
+};
+
+use xml::{reader::XmlEvent, EventReader};
+
+use super::apt::same_runway;
+use crate::{
+    geodesy::{self, Coordinates},
+    Airport,
+};
+
+/// One end of a runway of a `threshold.xml` file.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Threshold {
+    pub runway: String,
+    /// The position of the end of the runway, where the takeoffs start.
+    pub coordinates: Coordinates,
+    /// The true heading of the runway, from this threshold to the opposite one.
+    pub heading: f64,
+    /// How far the landing threshold is displaced along the runway, in meters.
+    pub displacement_m: f64,
+    /// The length of the stopway before the runway, in meters.
+    pub stopway_m: f64,
+}
+
+impl Threshold {
+    /// The position of the landing threshold, after the displacement.
+    pub fn landing_coordinates(&self) -> Coordinates {
+        geodesy::destination(self.coordinates, self.heading, self.displacement_m / 1852.0)
+    }
+}
+
+/// The runway thresholds of an airport, from the `Airports/S/A/E/SAEZ.threshold.xml` files of
+/// FlightGear's scenery, like the one TerraSync downloads.
+#[derive(Default)]
+pub struct RunwayThresholds {
+    pub ident: String,
+    pub thresholds: Vec<Threshold>,
+}
+
+impl RunwayThresholds {
+    /// Reads the thresholds of the airport `icao` from the first of the scenery `directories`
+    /// that has them. Returns `None` when none of them has them.
+    ///
+    /// # Errors
+    /// This function fails if a file can't be read, or if it isn't valid xml.
+    pub fn find_in(
+        directories: &[PathBuf],
+        icao: &str,
+    ) -> Result<Option<RunwayThresholds>, Box<dyn Error>> {
+        let icao = icao.trim().to_uppercase();
+
+        for directory in directories {
+            let mut path = directory.join("Airports");
+            for letter in icao.chars().take(3) {
+                path.push(letter.to_string());
+            }
+            path.push(format!("{icao}.threshold.xml"));
+
+            if path.is_file() {
+                let file =
+                    File::open(&path).map_err(|e| format!("Can't open {}: {e}", path.display()))?;
+                let mut thresholds = RunwayThresholds::from_reader(BufReader::new(file))?;
+                thresholds.ident = icao;
+
+                return Ok(Some(thresholds));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Reads the thresholds of the contents of a `threshold.xml` file.
+    ///
+    /// # Errors
+    /// This function fails if the contents aren't valid xml.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use kml_to_fgfp::navdata::RunwayThresholds;
+    /// let xml = "<PropertyList><runway>\
+    ///     <threshold><lon>-58.562800</lon><lat>-34.813400</lat><rwy>11</rwy>\
+    ///         <hdg-deg>115.04</hdg-deg><displ-m>300.0</displ-m><stopw-m>0.0</stopw-m></threshold>\
+    ///     <threshold><lon>-58.522170</lon><lat>-34.828570</lat><rwy>29</rwy>\
+    ///         <hdg-deg>295.06</hdg-deg><displ-m>0.0</displ-m><stopw-m>60.0</stopw-m></threshold>\
+    /// </runway></PropertyList>";
+    ///
+    /// let thresholds = RunwayThresholds::from_reader(xml.as_bytes()).unwrap();
+    /// let threshold = thresholds.get("11").unwrap();
+    /// assert_eq!(threshold.heading, 115.04);
+    ///
+    /// // The landing threshold is 300 m down the runway.
+    /// let landing = threshold.landing_coordinates();
+    /// assert!(landing.lon > threshold.coordinates.lon);
+    /// assert!(thresholds.get("17").is_none());
+    /// ```
+    pub fn from_reader(reader: impl Read) -> Result<RunwayThresholds, Box<dyn Error>> {
+        let mut thresholds = RunwayThresholds::default();
+
+        let mut current: Option<RawThreshold> = None;
+        let mut element = String::new();
+
+        for event in EventReader::new(reader) {
+            match event? {
+                XmlEvent::StartElement { name, .. } => match name.local_name.as_str() {
+                    "threshold" => current = Some(RawThreshold::default()),
+                    other => element = String::from(other),
+                },
+                XmlEvent::Characters(text) => {
+                    if let Some(threshold) = current.as_mut() {
+                        threshold.set(&element, text.trim());
+                    }
+                }
+                XmlEvent::EndElement { name } => match name.local_name.as_str() {
+                    "threshold" => {
+                        thresholds
+                            .thresholds
+                            .extend(current.take().and_then(RawThreshold::finish));
+                    }
+                    _ => element.clear(),
+                },
+                _ => (),
+            }
+        }
+
+        Ok(thresholds)
+    }
+
+    /// Returns the threshold of the `runway`, if it's listed.
+    pub fn get(&self, runway: &str) -> Option<&Threshold> {
+        self.thresholds
+            .iter()
+            .find(|t| same_runway(&t.runway, runway))
+    }
+
+    /// Fills in the threshold and heading of the `airport`'s runway. The `landing` threshold is
+    /// the displaced one, where the arrivals touch down.
+    ///
+    /// # Errors
+    /// This function fails if the runway isn't listed, naming the ones that are.
+    pub fn resolve(&self, airport: &mut Airport, landing: bool) -> Result<(), String> {
+        let runway = match &airport.runway {
+            Some(runway) => runway,
+            None => return Ok(()),
+        };
+
+        let threshold = match self.get(runway) {
+            Some(threshold) => threshold,
+            None => {
+                let runways: Vec<&str> =
+                    self.thresholds.iter().map(|t| t.runway.as_str()).collect();
+                return Err(format!(
+                    "runway {runway} isn't in the thresholds of {}, which has {}",
+                    self.ident,
+                    runways.join(", ")
+                ));
+            }
+        };
+
+        airport.threshold = Some(match landing {
+            true => threshold.landing_coordinates(),
+            false => threshold.coordinates,
+        });
+        airport.runway_heading = Some(threshold.heading);
+
+        Ok(())
+    }
+}
+
+/// The values of a `threshold` element, as they're read.
+#[derive(Default)]
+struct RawThreshold {
+    runway: String,
+    lon: Option<f64>,
+    lat: Option<f64>,
+    heading: Option<f64>,
+    displacement_m: f64,
+    stopway_m: f64,
+}
+
+impl RawThreshold {
+    /// Stores the contents of the `element` of the threshold.
+    fn set(&mut self, element: &str, value: &str) {
+        match element {
+            "rwy" => self.runway = value.to_uppercase(),
+            "lon" => self.lon = value.parse().ok(),
+            "lat" => self.lat = value.parse().ok(),
+            "hdg-deg" => self.heading = value.parse().ok(),
+            "displ-m" => self.displacement_m = value.parse().unwrap_or(0.0),
+            "stopw-m" => self.stopway_m = value.parse().unwrap_or(0.0),
+            _ => (),
+        }
+    }
+
+    /// Builds the threshold, if it has a runway and a position.
+    fn finish(self) -> Option<Threshold> {
+        if self.runway.is_empty() {
+            return None;
+        }
+
+        Some(Threshold {
+            runway: self.runway,
+            coordinates: Coordinates {
+                lon: self.lon?,
+                lat: self.lat?,
+            }
+            .validate()
+            .ok()?,
+            heading: self.heading?,
+            displacement_m: self.displacement_m,
+            stopway_m: self.stopway_m,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const THRESHOLD_XML: &str = "<PropertyList><runway>\
+        <threshold><lon>-58.562800</lon><lat>-34.813400</lat><rwy>11</rwy>\
+            <hdg-deg>115.04</hdg-deg><displ-m>300.0</displ-m><stopw-m>0.0</stopw-m></threshold>\
+        <threshold><lon>-58.522170</lon><lat>-34.828570</lat><rwy>29</rwy>\
+            <hdg-deg>295.06</hdg-deg><displ-m>0.0</displ-m><stopw-m>60.0</stopw-m></threshold>\
+        </runway></PropertyList>";
+
+    fn thresholds() -> RunwayThresholds {
+        let mut thresholds = RunwayThresholds::from_reader(THRESHOLD_XML.as_bytes()).unwrap();
+        thresholds.ident = String::from("SAEZ");
+        thresholds
+    }
+
+    fn airport(runway: &str) -> Airport {
+        format!("SAEZ/{runway}").parse().unwrap()
+    }
+
+    #[test]
+    fn resolves_the_takeoff_and_landing_thresholds() {
+        let thresholds = thresholds();
+
+        let mut departure = airport("11");
+        thresholds.resolve(&mut departure, false).unwrap();
+        let takeoff = thresholds.get("11").unwrap().coordinates;
+        assert_eq!(departure.threshold, Some(takeoff));
+        assert_eq!(departure.runway_heading, Some(115.04));
+
+        // The arrivals touch down 300 m down the runway.
+        let mut destination = airport("11");
+        thresholds.resolve(&mut destination, true).unwrap();
+        let landing = destination.threshold.unwrap();
+        let displacement = geodesy::distance_nm(takeoff, landing) * 1852.0;
+        assert!((displacement - 300.0).abs() < 1.0, "{displacement}");
+
+        // Without a displacement, both are the same.
+        let mut destination = airport("29");
+        thresholds.resolve(&mut destination, true).unwrap();
+        assert_eq!(
+            destination.threshold,
+            Some(thresholds.get("29").unwrap().coordinates)
+        );
+    }
+
+    #[test]
+    fn fails_for_runways_that_arent_listed() {
+        let mut airport = airport("17");
+
+        assert_eq!(
+            thresholds().resolve(&mut airport, false),
+            Err(String::from(
+                "runway 17 isn't in the thresholds of SAEZ, which has 11, 29"
+            ))
+        );
+        assert_eq!(airport.threshold, None);
+
+        // Without a runway there's nothing to resolve.
+        let mut airport: Airport = "SAEZ".parse().unwrap();
+        thresholds().resolve(&mut airport, true).unwrap();
+        assert_eq!(airport.threshold, None);
+    }
+
+    #[test]
+    fn finds_the_thresholds_in_the_scenery() {
+        let scenery =
+            std::env::temp_dir().join(format!("kml_to_fgfp-scenery-{}", std::process::id()));
+        let empty = scenery.join("empty");
+        let terrasync = scenery.join("TerraSync");
+        let directory = terrasync.join("Airports/S/A/E");
+        fs::create_dir_all(&directory).unwrap();
+        fs::create_dir_all(&empty).unwrap();
+        fs::write(directory.join("SAEZ.threshold.xml"), THRESHOLD_XML).unwrap();
+
+        let found = RunwayThresholds::find_in(&[empty.clone(), terrasync.clone()], "saez");
+        let missing = RunwayThresholds::find_in(&[empty, terrasync], "SUMU");
+        fs::remove_dir_all(&scenery).unwrap();
+
+        let found = found.unwrap().unwrap();
+        assert_eq!(found.ident, "SAEZ");
+        assert_eq!(found.thresholds.len(), 2);
+        assert!(missing.unwrap().is_none());
+    }
+}
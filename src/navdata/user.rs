@@ -0,0 +1,335 @@
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use crate::geodesy::Coordinates;
+
+/// A waypoint of the user's own library, like a VFR reporting point or a company fix that isn't in
+/// any database.
+#[derive(Clone, PartialEq, Debug)]
+pub struct UserWaypoint {
+    pub ident: String,
+    pub coordinates: Coordinates,
+    /// The altitude to fly over the waypoint in feet, if it has one.
+    pub altitude_ft: Option<f64>,
+    pub notes: String,
+}
+
+impl FromStr for UserWaypoint {
+    type Err = String;
+
+    /// Reads a waypoint from a line of the library, `IDENT,LAT,LON[,ALTITUDE[,NOTES]]`. The notes
+    /// can have commas.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use kml_to_fgfp::navdata::UserWaypoint;
+    /// let waypoint: UserWaypoint = "puma,-34.62,-58.71,1500,Over the bridge, north side"
+    ///     .parse()
+    ///     .unwrap();
+    /// assert_eq!(waypoint.ident, "PUMA");
+    /// assert_eq!(waypoint.altitude_ft, Some(1500.0));
+    /// assert_eq!(waypoint.notes, "Over the bridge, north side");
+    ///
+    /// assert!("PUMA,-34.62".parse::<UserWaypoint>().is_err());
+    /// assert!("PUMA,-94.62,-58.71".parse::<UserWaypoint>().is_err());
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.splitn(5, ',').map(|f| f.trim());
+
+        let ident = match fields.next() {
+            Some(ident) if !ident.is_empty() && !ident.contains(char::is_whitespace) => {
+                ident.to_uppercase()
+            }
+            _ => return Err(format!("Invalid waypoint `{s}`, it needs an ident")),
+        };
+
+        let mut number = |name: &str| -> Result<Option<f64>, String> {
+            match fields.next() {
+                None | Some("") => Ok(None),
+                Some(value) => value
+                    .parse()
+                    .map(Some)
+                    .map_err(|_| format!("Invalid {name} `{value}` of the waypoint {ident}")),
+            }
+        };
+
+        let (lat, lon) = match (number("latitude")?, number("longitude")?) {
+            (Some(lat), Some(lon)) => (lat, lon),
+            _ => {
+                return Err(format!(
+                    "The waypoint {ident} needs a latitude and longitude"
+                ))
+            }
+        };
+        let altitude_ft = number("altitude")?;
+
+        let coordinates = Coordinates { lon, lat }
+            .validate()
+            .map_err(|e| format!("Invalid position of the waypoint {ident}: {e}"))?;
+
+        Ok(UserWaypoint {
+            ident,
+            coordinates,
+            altitude_ft,
+            notes: String::from(fields.next().unwrap_or_default()),
+        })
+    }
+}
+
+/// The user's library of waypoints, kept in a CSV file with the columns
+/// `ident,lat,lon,altitude,notes`. Empty lines and lines starting with `#` are skipped.
+///
+/// Its waypoints take priority over FlightGear's navigation data.
+#[derive(Clone, Default)]
+pub struct UserWaypoints {
+    waypoints: Vec<UserWaypoint>,
+}
+
+impl UserWaypoints {
+    /// Reads the library at `path`. A library that doesn't exist yet is empty.
+    ///
+    /// # Errors
+    /// This function fails if the file can't be read, or if a line isn't a valid waypoint.
+    pub fn load(path: &Path) -> Result<UserWaypoints, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(UserWaypoints::default());
+        }
+
+        let file = File::open(path).map_err(|e| format!("Can't open {}: {e}", path.display()))?;
+        UserWaypoints::from_reader(BufReader::new(file))
+            .map_err(|e| format!("{}: {e}", path.display()).into())
+    }
+
+    /// Reads the waypoints of the contents of a library. The header, `ident,lat,lon,...`, is
+    /// optional.
+    ///
+    /// # Errors
+    /// This function fails if the contents can't be read, or if a line isn't a valid waypoint.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use kml_to_fgfp::navdata::UserWaypoints;
+    /// let csv = "ident,lat,lon,altitude,notes\n\
+    ///     ## Reporting points\n\
+    ///     PUMA,-34.62,-58.71,1500,Over the bridge\n\
+    ///     CIUDA,-34.58,-58.40\n";
+    ///
+    /// let library = UserWaypoints::from_reader(csv.as_bytes()).unwrap();
+    /// assert_eq!(library.iter().count(), 2);
+    /// assert_eq!(library.get("ciuda").unwrap().altitude_ft, None);
+    ///
+    /// assert!(UserWaypoints::from_reader("PUMA,-34.62,west".as_bytes()).is_err());
+    /// ```
+    pub fn from_reader(reader: impl BufRead) -> Result<UserWaypoints, Box<dyn Error>> {
+        let mut library = UserWaypoints::default();
+
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+
+            let is_header = number == 0 && line.to_lowercase().starts_with("ident,");
+            if line.is_empty() || line.starts_with('#') || is_header {
+                continue;
+            }
+
+            let waypoint = line
+                .parse()
+                .map_err(|e| format!("line {}: {e}", number + 1))?;
+            library.insert(waypoint);
+        }
+
+        Ok(library)
+    }
+
+    /// Writes the library to `path`, creating it if it doesn't exist.
+    ///
+    /// # Errors
+    /// This function fails if the file can't be written.
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(directory) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(directory)?;
+        }
+
+        let file =
+            File::create(path).map_err(|e| format!("Can't create {}: {e}", path.display()))?;
+        self.write(BufWriter::new(file))?;
+
+        Ok(())
+    }
+
+    /// Writes the library as CSV, with a header.
+    ///
+    /// # Errors
+    /// This function fails if the `writer` fails.
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "ident,lat,lon,altitude,notes")?;
+
+        for waypoint in &self.waypoints {
+            let altitude = waypoint
+                .altitude_ft
+                .map(|a| a.to_string())
+                .unwrap_or_default();
+
+            writeln!(
+                writer,
+                "{},{:.6},{:.6},{},{}",
+                waypoint.ident,
+                waypoint.coordinates.lat,
+                waypoint.coordinates.lon,
+                altitude,
+                waypoint.notes
+            )?;
+        }
+
+        writer.flush()
+    }
+
+    /// Returns the waypoint called `ident`, if it's in the library.
+    pub fn get(&self, ident: &str) -> Option<&UserWaypoint> {
+        let ident = ident.trim().to_uppercase();
+        self.waypoints.iter().find(|w| w.ident == ident)
+    }
+
+    /// Returns the waypoints of the library, in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = &UserWaypoint> {
+        self.waypoints.iter()
+    }
+
+    /// Adds a `waypoint` to the library. Returns the one it replaces, if there was one with the
+    /// same ident.
+    pub fn insert(&mut self, waypoint: UserWaypoint) -> Option<UserWaypoint> {
+        match self
+            .waypoints
+            .iter()
+            .position(|w| w.ident == waypoint.ident)
+        {
+            Some(i) => Some(std::mem::replace(&mut self.waypoints[i], waypoint)),
+            None => {
+                self.waypoints.push(waypoint);
+                None
+            }
+        }
+    }
+
+    /// Removes the waypoint called `ident` from the library, returning it.
+    pub fn remove(&mut self, ident: &str) -> Option<UserWaypoint> {
+        let ident = ident.trim().to_uppercase();
+        let i = self.waypoints.iter().position(|w| w.ident == ident)?;
+
+        Some(self.waypoints.remove(i))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn waypoint(line: &str) -> UserWaypoint {
+        line.parse().unwrap()
+    }
+
+    #[test]
+    fn saves_and_loads_the_library() {
+        let directory =
+            std::env::temp_dir().join(format!("kml_to_fgfp-user-{}", std::process::id()));
+        let path = directory.join("library").join("waypoints.csv");
+
+        // A library that doesn't exist yet is empty.
+        assert_eq!(UserWaypoints::load(&path).unwrap().iter().count(), 0);
+
+        let mut library = UserWaypoints::default();
+        library.insert(waypoint(
+            "PUMA,-34.62,-58.71,1500,Over the bridge, north side",
+        ));
+        library.insert(waypoint("CIUDA,-34.58,-58.40"));
+        library.save(&path).unwrap();
+
+        let loaded = UserWaypoints::load(&path).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        let waypoints: Vec<&UserWaypoint> = loaded.iter().collect();
+        let saved: Vec<&UserWaypoint> = library.iter().collect();
+        assert_eq!(waypoints, saved);
+    }
+
+    #[test]
+    fn replaces_waypoints_with_the_same_ident() {
+        let mut library = UserWaypoints::default();
+        assert_eq!(library.insert(waypoint("PUMA,-34.62,-58.71")), None);
+        assert_eq!(library.insert(waypoint("CIUDA,-34.58,-58.40")), None);
+
+        let replaced = library.insert(waypoint("puma,-34.60,-58.70,2000"));
+        assert_eq!(replaced.unwrap().altitude_ft, None);
+
+        // The replaced waypoint keeps its place.
+        let idents: Vec<&str> = library.iter().map(|w| w.ident.as_str()).collect();
+        assert_eq!(idents, ["PUMA", "CIUDA"]);
+        assert_eq!(library.get("Puma").unwrap().altitude_ft, Some(2000.0));
+
+        // And the last line of a file with the same ident wins.
+        let library =
+            UserWaypoints::from_reader("PUMA,-34.62,-58.71\nPUMA,-34.60,-58.70\n".as_bytes())
+                .unwrap();
+        assert_eq!(library.iter().count(), 1);
+        assert_eq!(library.get("PUMA").unwrap().coordinates.lat, -34.60);
+    }
+
+    #[test]
+    fn removes_waypoints() {
+        let mut library = UserWaypoints::default();
+        library.insert(waypoint("PUMA,-34.62,-58.71"));
+        library.insert(waypoint("CIUDA,-34.58,-58.40"));
+
+        assert_eq!(library.remove(" puma ").unwrap().ident, "PUMA");
+        assert_eq!(library.remove("PUMA"), None);
+        assert!(library.get("PUMA").is_none());
+        assert_eq!(library.iter().count(), 1);
+    }
+
+    #[test]
+    fn reports_the_line_of_malformed_waypoints() {
+        let cases = [
+            (
+                "ident,lat,lon\nPUMA,-34.62\n",
+                "line 2: The waypoint PUMA needs a latitude and longitude",
+            ),
+            (
+                "PUMA,-34.62,-58.71\n,-34.58,-58.40\n",
+                "line 2: Invalid waypoint `,-34.58,-58.40`, it needs an ident",
+            ),
+            (
+                "PUMA,south,-58.71\n",
+                "line 1: Invalid latitude `south` of the waypoint PUMA",
+            ),
+            (
+                "PUMA,-34.62,-58.71,high\n",
+                "line 1: Invalid altitude `high` of the waypoint PUMA",
+            ),
+            (
+                "LA PAZ,-16.5,-68.2\n",
+                "line 1: Invalid waypoint `LA PAZ,-16.5,-68.2`, it needs an ident",
+            ),
+        ];
+
+        for (csv, error) in cases {
+            match UserWaypoints::from_reader(csv.as_bytes()) {
+                Ok(_) => panic!("{csv:?} was read"),
+                Err(e) => assert_eq!(e.to_string(), error),
+            }
+        }
+
+        assert_eq!(
+            "PUMA,-94.62,-58.71".parse::<UserWaypoint>(),
+            Err(String::from(
+                "Invalid position of the waypoint PUMA: latitude -94.62 is beyond ±90°"
+            ))
+        );
+    }
+}
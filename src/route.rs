@@ -1,121 +1,1444 @@
 use std::{
     error::Error,
+    fmt,
     io::{Read, Write},
     result,
+    str::FromStr,
 };
 
-pub use xml::{
-    reader::EventReader,
-    writer::{EmitterConfig, EventWriter},
-};
+pub use xml::{reader::EventReader, writer::EventWriter};
 
-use super::EventType;
+use super::{
+    geodesy::{self, Coordinates, Leg},
+    iata,
+    magnetic::{self, MagneticModel},
+    metar::RunwayWind,
+    navdata::{AirwayDatabase, FixDatabase, ProcedureFix, UserWaypoints},
+    target, transition, EventType, Feature, FgTarget,
+};
 
+mod airways;
+mod cruise;
+mod densify;
 mod handlers;
-use handlers::{LookingFor, Waypoint};
+mod holds;
+mod procedures;
+mod profile;
+mod simplify;
+mod snap;
+mod user;
+mod validate;
+use handlers::{first_word, DataField, LookingFor, Waypoint};
 
 /// Represents an airport by it's ICAO code and runway.
+///
+/// Only the ident is needed, the rest can be filled in by an
+/// [`AirportDatabase`](crate::navdata::AirportDatabase).
+#[derive(Clone, PartialEq, Debug, Default)]
 pub struct Airport {
     pub ident: String,
     pub runway: Option<String>,
+    /// The position of the airport, when it's known.
+    pub coordinates: Option<Coordinates>,
+    pub name: Option<String>,
+    /// The IATA code of the airport, like `EZE`, when it's known.
+    pub iata: Option<String>,
+    pub elevation_ft: Option<f64>,
+    /// The position of the runway's threshold, when it's known.
+    pub threshold: Option<Coordinates>,
+    /// The true heading of the runway, when it's known.
+    pub runway_heading: Option<f64>,
+    /// The wind on the runway, when it was chosen for the wind of a METAR.
+    pub wind: Option<RunwayWind>,
+    /// The SID of a departure, or the STAR of a destination.
+    pub procedure: Option<String>,
+    /// The transition of the [`procedure`](Airport::procedure).
+    pub transition: Option<String>,
+    /// The approach to a destination.
+    pub approach: Option<String>,
+}
+
+impl FromStr for Airport {
+    type Err = String;
+
+    /// Reads an airport like `SAEZ`, `SAEZ/11` or `YSSY/34L/ARDO1B.GESOL/ILS34L`, in the format
+    /// `IDENT[/RUNWAY[/PROCEDURE[.TRANSITION][/APPROACH]]]`. The procedure is the SID of a
+    /// departure or the STAR of a destination, always with its name first. The runway can be left
+    /// empty, like `SAEZ//ANDO1A`.
+    ///
+    /// Idents have 4 letters and digits like ICAO codes, or 3 to 7 for private strips. Runways are
+    /// `01` to `36`, followed by `L`, `C` or `R`, or helipads like `H1`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use kml_to_fgfp::Airport;
+    /// let airport: Airport = "saez/11/ando1a.arsot".parse().unwrap();
+    /// assert_eq!(airport.ident, "SAEZ");
+    /// assert_eq!(airport.runway.as_deref(), Some("11"));
+    /// assert_eq!(airport.procedure.as_deref(), Some("ANDO1A"));
+    /// assert_eq!(airport.transition.as_deref(), Some("ARSOT"));
+    ///
+    /// let airport: Airport = "YSSY".parse().unwrap();
+    /// assert_eq!(airport.runway, None);
+    ///
+    /// assert!("SA-EZ/11".parse::<Airport>().is_err());
+    /// assert!("SAEZ/37".parse::<Airport>().is_err());
+    /// assert!("SAEZ/11X".parse::<Airport>().is_err());
+    /// ```
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split('/').map(|p| p.trim()).collect();
+        let error = |problem: String| format!("Invalid airport `{s}`: {problem}");
+
+        if parts.len() > 4 {
+            return Err(error(String::from(
+                "expected IDENT[/RUNWAY[/PROCEDURE[.TRANSITION][/APPROACH]]]",
+            )));
+        }
+
+        let ident = parts[0].to_uppercase();
+        if !(3..=7).contains(&ident.len()) {
+            return Err(error(format!(
+                "the ident `{ident}` should have 4 letters or digits, like `SAEZ`"
+            )));
+        }
+        if let Some(c) = ident.chars().find(|c| !c.is_ascii_alphanumeric()) {
+            return Err(error(format!(
+                "the ident `{ident}` can only have letters and digits, not `{c}`"
+            )));
+        }
+
+        let runway = match parts.get(1) {
+            None | Some(&"") => None,
+            Some(runway) => Some(parse_runway(runway).map_err(error)?),
+        };
+
+        let (procedure, transition) = match parts.get(2) {
+            None | Some(&"") => (None, None),
+            Some(procedure) => {
+                let (name, transition) = match procedure.split_once('.') {
+                    Some((name, transition)) => (name, Some(transition)),
+                    None => (*procedure, None),
+                };
+
+                let transition = transition
+                    .map(|t| parse_procedure_name(t, "transition"))
+                    .transpose()
+                    .map_err(error)?;
+                (
+                    Some(parse_procedure_name(name, "procedure").map_err(error)?),
+                    transition,
+                )
+            }
+        };
+
+        let approach = match parts.get(3) {
+            None | Some(&"") => None,
+            Some(approach) => Some(parse_procedure_name(approach, "approach").map_err(error)?),
+        };
+
+        Ok(Airport {
+            ident,
+            runway,
+            procedure,
+            transition,
+            approach,
+            ..Default::default()
+        })
+    }
+}
+
+/// Internal function that checks a runway designator, like `11`, `34L` or the helipad `H1`. A
+/// single digit is padded, so `9` is `09`.
+fn parse_runway(runway: &str) -> result::Result<String, String> {
+    let runway = runway.to_uppercase();
+
+    if let Some(number) = runway.strip_prefix('H') {
+        return match !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()) {
+            true => Ok(runway),
+            false => Err(format!("the helipad `{runway}` needs a number, like `H1`")),
+        };
+    }
+
+    let split = runway
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(runway.len());
+    let (number, side) = runway.split_at(split);
+
+    let number = match number.parse::<u32>() {
+        Ok(n) if number.len() <= 2 && (1..=36).contains(&n) => n,
+        Ok(_) => return Err(format!("the runway `{runway}` isn't between 01 and 36")),
+        Err(_) => {
+            return Err(format!(
+                "the runway `{runway}` should be a number, like `11`, `34L` or the helipad `H1`"
+            ))
+        }
+    };
+
+    match side {
+        "" | "L" | "C" | "R" => Ok(format!("{number:02}{side}")),
+        _ => Err(format!(
+            "the runway `{runway}` can only end in `L`, `C` or `R`"
+        )),
+    }
+}
+
+/// Internal function that checks the name of a procedure, its transition or an approach, which
+/// is one word like `ANDO1A` or `RNV11-Y`.
+fn parse_procedure_name(name: &str, what: &str) -> result::Result<String, String> {
+    if name.is_empty() {
+        return Err(format!("the {what} is empty"));
+    }
+
+    match name
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && *c != '-')
+    {
+        Some(c) => Err(format!(
+            "the {what} `{name}` can only have letters, digits and `-`, not `{c}`"
+        )),
+        None => Ok(name.to_uppercase()),
+    }
+}
+
+/// What [`transform_route`](transform_route) found out about the route while writing it.
+#[derive(Default)]
+pub struct Report {
+    /// The departure airport of the route, as given or as found in the .kml file.
+    pub departure: Option<Airport>,
+    /// The destination airport of the route, as given or as found in the .kml file.
+    pub destination: Option<Airport>,
+    /// Every leg of the route, including the departure and destination legs when the airports'
+    /// coordinates are known.
+    pub legs: Vec<Leg>,
+    /// The idents of the waypoints that were generated, instead of read from the .kml file.
+    pub generated: Vec<String>,
+    /// How many points of paths were removed by the simplification.
+    pub simplified: usize,
+    /// The problems found by the validation of the route.
+    pub diagnostics: Vec<Diagnostic>,
+    /// How far the top of climb is from the origin, in nautical miles.
+    pub top_of_climb_nm: Option<f64>,
+    /// How far the top of descent is from the destination, in nautical miles.
+    pub top_of_descent_nm: Option<f64>,
+    /// The altitude the route cruises at after the climb, in feet.
+    pub cruise_altitude: Option<f64>,
+    /// The altitude in feet above which altitudes are flight levels, when it's known.
+    pub transition_altitude: Option<f64>,
+}
+
+impl Report {
+    /// Writes an altitude in feet as a flight level like `FL350` when it's above the
+    /// [`transition_altitude`](Report::transition_altitude), or in feet otherwise.
+    pub fn format_altitude(&self, feet: f64) -> String {
+        transition::format_altitude(feet, self.transition_altitude)
+    }
+}
+
+/// Options to customize how [`transform_route`](transform_route) builds the route.
+///
+/// Use `RouteOptions::default()` to get the default behavior.
+#[derive(Default)]
+pub struct RouteOptions {
+    /// Force this altitude restriction on every waypoint, instead of inferring it from the vertical
+    /// profile of the route.
+    ///
+    /// Waypoints with an `alt-restrict` field in their `ExtendedData` keep their own restriction.
+    pub alt_restrict: Option<AltRestrict>,
+    /// A speed limit applied to every waypoint below an altitude in feet, like the usual 250 kt
+    /// below FL100.
+    pub speed_limit: Option<(SpeedRestrict, f64)>,
+    /// Speed restrictions for specific waypoints, by ident. These take priority over the
+    /// `ExtendedData` and the [`speed_limit`](RouteOptions::speed_limit).
+    pub speed_rules: Vec<(String, SpeedRestrict)>,
+    /// A route string like `SAEZ DCT EZE11 UM540 ARSOT`, used to know which airways connect the
+    /// waypoints. Placemarks can also have an `airway` field in their `ExtendedData`.
+    pub route_string: Option<String>,
+    /// Write every fix along the airways as a basic waypoint, instead of using `via` waypoints.
+    pub expand_airways: bool,
+    /// Holding patterns at specific waypoints, by ident. Placemarks can also have a `hold` field
+    /// in their `ExtendedData`.
+    pub holds: Vec<(String, Hold)>,
+    /// Idents of the waypoints followed by a discontinuity. Placemarks named `DISCON` are also
+    /// discontinuities.
+    pub discontinuities: Vec<String>,
+    /// The FlightGear release the route is written for. Features it can't represent are dropped
+    /// with a warning.
+    pub target: FgTarget,
+    /// The model used to calculate the magnetic variation. The default is the embedded WMM2025.
+    pub magnetic_model: MagneticModel,
+    /// The date of the flight as a decimal year, used for the magnetic variation. The default is
+    /// the current date.
+    pub date: Option<f64>,
+    /// Insert waypoints along the great circle of long legs.
+    pub densify: Option<Densify>,
+    /// Read the points of the paths (`LineString`s) in the .kml file, like hand-drawn paths or
+    /// recorded tracks, as waypoints named after the Placemark: `Path001`, `Path002`, ...
+    pub read_paths: bool,
+    /// Remove the points of paths that don't change the shape of the route.
+    pub simplify: Option<Simplify>,
+    /// How the altitudes of the waypoints are rounded.
+    pub altitude_rounding: AltitudeRounding,
+    /// What to do with the problems found in the route.
+    pub validation: Validation,
+    /// The climb and descent used to find the top of climb and the top of descent.
+    pub vertical_profile: VerticalProfile,
+    /// Insert the top of climb and the top of descent in the route, as the waypoints `T/C` and
+    /// `T/D`.
+    pub insert_tops: bool,
+    /// Fly the en-route part of the route at this altitude in feet, instead of the altitudes of
+    /// the .kml file.
+    pub cruise_altitude: Option<f64>,
+    /// Climbs (or descents) to new cruise altitudes along the en-route part of the route.
+    pub step_climbs: Vec<StepClimb>,
+    /// Look up the waypoints in FlightGear's fixes and navaids.
+    pub fixes: Option<FixLookup>,
+    /// Insert the fixes along the airways of the [`route_string`](RouteOptions::route_string).
+    pub airways: Option<AirwayDatabase>,
+    /// The user's own waypoints. Placemarks named like them are moved to their position, and the
+    /// [`route_string`](RouteOptions::route_string) can use them without a Placemark.
+    pub user_waypoints: Option<UserWaypoints>,
+    /// The fixes of the SID, which replace the start of the route when the FMS can't load
+    /// procedures. See [`AirportProcedures::find`](crate::navdata::AirportProcedures::find).
+    pub departure_procedure: Vec<ProcedureFix>,
+    /// The fixes of the STAR and the approach, which replace the end of the route.
+    pub arrival_procedure: Vec<ProcedureFix>,
+    /// Drop the Placemarks within this many nautical miles of the departure or destination
+    /// airport, which are the airport under another name. Placemarks named like the airport, by
+    /// its ICAO or IATA code, are always dropped.
+    pub airport_radius_nm: Option<f64>,
+    /// The altitude in feet above which altitudes are flight levels. The default is the usual
+    /// transition altitude of the departure airport's country or, if it isn't known, of the
+    /// destination's.
+    pub transition_altitude: Option<f64>,
+}
+
+/// The fixes and navaids the waypoints of the route are looked up in. Waypoints that aren't in
+/// the database, or are too far from it, are reported in the [`Report`](Report).
+pub struct FixLookup {
+    pub database: FixDatabase,
+    /// How far from the database a waypoint can be, in nautical miles.
+    pub tolerance_nm: f64,
+    /// Move the waypoints to the position of the database, and write them as navaids.
+    pub snap: bool,
+}
+
+/// A step climb to a new cruise altitude, which applies to the rest of the en-route part of the
+/// route.
+#[derive(Clone, PartialEq, Debug)]
+pub struct StepClimb {
+    pub at: StepAt,
+    /// The new cruise altitude, in feet.
+    pub altitude: f64,
+}
+
+/// Where a [`StepClimb`](StepClimb) starts.
+#[derive(Clone, PartialEq, Debug)]
+pub enum StepAt {
+    /// After the waypoint with this ident.
+    Waypoint(String),
+    /// At the first waypoint this many nautical miles or more from the origin.
+    Distance(f64),
+}
+
+impl fmt::Display for StepAt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StepAt::Waypoint(ident) => write!(f, "{ident}"),
+            StepAt::Distance(distance) => write!(f, "{distance} NM"),
+        }
+    }
+}
+
+/// How steep the route climbs to its cruise altitude and descends from it, in feet per nautical
+/// mile. The default is the usual 3° path.
+///
+/// It can be parsed from `CLIMB[/DESCENT]`, where both are given in feet per nautical mile or, with
+/// the `deg` suffix, as the angle of the path, like `500/3deg`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct VerticalProfile {
+    pub climb_gradient: f64,
+    pub descent_gradient: f64,
+}
+
+impl VerticalProfile {
+    /// The gradient of a path of `degrees`, in feet per nautical mile.
+    pub fn gradient_for(degrees: f64) -> f64 {
+        degrees.to_radians().tan() * 6076.12
+    }
+}
+
+impl Default for VerticalProfile {
+    fn default() -> Self {
+        let gradient = VerticalProfile::gradient_for(3.0);
+
+        VerticalProfile {
+            climb_gradient: gradient,
+            descent_gradient: gradient,
+        }
+    }
+}
+
+impl FromStr for VerticalProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        let gradient = |value: &str| {
+            let value = value.trim().to_lowercase();
+            let gradient = match value.strip_suffix("deg") {
+                Some(degrees) => degrees
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|d| (0.0..90.0).contains(d))
+                    .map(VerticalProfile::gradient_for),
+                None => value.parse().ok(),
+            };
+
+            gradient
+                .filter(|g: &f64| *g > 0.0 && g.is_finite())
+                .ok_or(format!(
+                    "Invalid gradient `{value}`, expected FT/NM or DEGREESdeg"
+                ))
+        };
+
+        let (climb, descent) = match s.split_once('/') {
+            Some((climb, descent)) => (gradient(climb)?, gradient(descent)?),
+            None => {
+                let climb = gradient(s)?;
+                (climb, VerticalProfile::default().descent_gradient)
+            }
+        };
+
+        Ok(VerticalProfile {
+            climb_gradient: climb,
+            descent_gradient: descent,
+        })
+    }
+}
+
+/// What to do with the problems the validation finds in the route. By default they're only
+/// reported in the [`Report`](Report).
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Validation {
+    /// Merge consecutive waypoints at the same position into one.
+    pub merge_duplicates: bool,
+    /// Remove the waypoints where the route turns back on itself.
+    pub remove_spikes: bool,
+    /// Fail instead of writing a route with problems, even if they were fixed.
+    pub strict: bool,
+}
+
+/// A problem found in the route by the validation, or by the lookup of its fixes.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Diagnostic {
+    /// The ident of the waypoint with the problem.
+    pub ident: String,
+    pub problem: Problem,
+    /// Whether the problem was fixed, as the [`Validation`](Validation) options say.
+    pub fixed: bool,
+}
+
+/// The problems the validation can find in the route, usually mistakes made editing the .kml file.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Problem {
+    /// The waypoint is at the same position as the previous one.
+    Duplicate,
+    /// The route already went through the waypoint.
+    Loop,
+    /// The route turns back at the waypoint, by this many degrees.
+    Spike(f64),
+    /// The route climbs (positive) or descends (negative) this many feet per nautical mile to reach
+    /// the waypoint, more than any aircraft can.
+    Gradient(f64),
+    /// The waypoint isn't in the fix database.
+    UnknownFix,
+    /// The waypoint is this many nautical miles away from the fix with its ident.
+    FixMoved(f64),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Duplicate => write!(f, "it's at the same position as the previous waypoint"),
+            Problem::Loop => write!(f, "the route already went through it"),
+            Problem::Spike(turn) => write!(f, "the route turns {turn:.0}° back at it"),
+            Problem::Gradient(gradient) if *gradient > 0.0 => {
+                write!(f, "the route climbs {gradient:.0} ft/NM to reach it")
+            }
+            Problem::Gradient(gradient) => {
+                write!(f, "the route descends {:.0} ft/NM to reach it", -gradient)
+            }
+            Problem::UnknownFix => write!(f, "it isn't in the navigation data"),
+            Problem::FixMoved(distance) => {
+                write!(
+                    f,
+                    "it's {distance:.1} NM away from the fix in the navigation data"
+                )
+            }
+        }
+    }
+}
+
+/// How the altitudes of the waypoints are rounded, it can be parsed from `none`, `10`, `100`,
+/// `500` or `fl`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum AltitudeRounding {
+    /// Keep the altitudes as precise as they are in the .kml file.
+    None,
+    Feet10,
+    #[default]
+    Feet100,
+    Feet500,
+    /// Round to whole thousands of feet, the usual cruising flight levels like FL350.
+    FlightLevel,
+}
+
+impl AltitudeRounding {
+    /// Rounds an altitude in feet.
+    pub fn round(&self, feet: f64) -> f64 {
+        let step = match self {
+            AltitudeRounding::None => return feet,
+            AltitudeRounding::Feet10 => 10.0,
+            AltitudeRounding::Feet100 => 100.0,
+            AltitudeRounding::Feet500 => 500.0,
+            AltitudeRounding::FlightLevel => 1000.0,
+        };
+
+        // We divide by the step and multiply by it to let the round function do the work. With a
+        // step of one hundred feet, 12478.64 feet becomes 12500 feet.
+        // Adding zero turns the -0 of small negative altitudes into 0.
+        (feet / step).round() * step + 0.0
+    }
+}
+
+impl FromStr for AltitudeRounding {
+    type Err = String;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "none" => Ok(AltitudeRounding::None),
+            "10" => Ok(AltitudeRounding::Feet10),
+            "100" => Ok(AltitudeRounding::Feet100),
+            "500" => Ok(AltitudeRounding::Feet500),
+            "fl" => Ok(AltitudeRounding::FlightLevel),
+            _ => Err(format!(
+                "Unknown altitude rounding `{s}`, expected none, 10, 100, 500 or fl"
+            )),
+        }
+    }
+}
+
+/// How the altitude of a Placemark is measured, as the `altitudeMode` of the .kml says. Placemarks
+/// without an `altitudeMode` are taken as absolute, which is what SimBrief means.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum AltitudeMode {
+    /// Above mean sea level.
+    #[default]
+    Absolute,
+    /// Above the ground, which can't be written without knowing the terrain's elevation.
+    RelativeToGround,
+    /// On the ground, the altitude is ignored.
+    ClampToGround,
+}
+
+impl FromStr for AltitudeMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        match s.trim() {
+            "absolute" => Ok(AltitudeMode::Absolute),
+            "relativeToGround" | "relativeToSeaFloor" => Ok(AltitudeMode::RelativeToGround),
+            "clampToGround" | "clampToSeaFloor" => Ok(AltitudeMode::ClampToGround),
+            _ => Err(format!("Unknown altitudeMode `{s}`")),
+        }
+    }
+}
+
+/// The tolerances used to simplify the paths of the route. A point of a path is removed when the
+/// route without it stays within both tolerances.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Simplify {
+    /// The horizontal tolerance, in nautical miles.
+    pub tolerance_nm: f64,
+    /// The vertical tolerance, in feet.
+    pub tolerance_ft: f64,
+}
+
+/// When to insert waypoints along the great circle of a direct leg, so autopilots that fly rhumb
+/// lines or cut corners stay on the route.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Densify {
+    /// The longest leg allowed, in nautical miles.
+    pub max_leg_nm: f64,
+    /// The largest change of true course allowed along a leg, in degrees.
+    pub max_track_change: Option<f64>,
+}
+
+/// The type of a waypoint in the .fgfp's route.
+#[derive(Clone, PartialEq, Debug)]
+pub enum WaypointType {
+    /// A waypoint given by its coordinates.
+    Basic,
+    /// The exit fix of a leg flown along the waypoint's airway.
+    Via,
+    /// A break in the route, the aircraft won't sequence past it on its own.
+    Discontinuity,
+    /// A holding pattern at the waypoint.
+    Hold(Hold),
+}
+
+/// A holding pattern, which can be parsed from strings like `270/R/1MIN` or `090/L/4NM`: the
+/// inbound course, the direction of the turns and the length of the legs.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Hold {
+    /// The inbound course in degrees.
+    pub inbound_course: f64,
+    pub right_turns: bool,
+    pub leg: HoldLeg,
+}
+
+/// The length of the legs of a [`Hold`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum HoldLeg {
+    Minutes(f64),
+    NauticalMiles(f64),
+}
+
+impl FromStr for Hold {
+    type Err = String;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        let data: Vec<String> = s.split('/').map(|d| d.trim().to_uppercase()).collect();
+
+        let inbound_course: f64 = match data[0].parse() {
+            Ok(course) if (0.0..360.0).contains(&course) => course,
+            _ => return Err(format!("Invalid inbound course in hold `{s}`")),
+        };
+
+        let right_turns = match data.get(1).map(|d| d.as_str()) {
+            Some("R") | None => true,
+            Some("L") => false,
+            Some(_) => return Err(format!("Invalid turn direction in hold `{s}`, use R or L")),
+        };
+
+        // The legs can't be empty, nor go backwards.
+        let length = |leg: &str| match leg.trim().parse::<f64>() {
+            Ok(length) if length > 0.0 && length.is_finite() => Ok(length),
+            _ => Err(format!("Invalid leg length in hold `{s}`")),
+        };
+
+        let leg = match data.get(2) {
+            Some(leg) if leg.ends_with("NM") => {
+                HoldLeg::NauticalMiles(length(leg.trim_end_matches("NM"))?)
+            }
+            Some(leg) => HoldLeg::Minutes(length(leg.trim_end_matches("MIN"))?),
+            None => HoldLeg::Minutes(1.0),
+        };
+
+        Ok(Hold {
+            inbound_course,
+            right_turns,
+            leg,
+        })
+    }
+}
+
+/// The altitude restriction of a waypoint, meaning how FlightGear's VNAV treats the waypoint's
+/// altitude.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AltRestrict {
+    /// Cross the waypoint exactly at its altitude.
+    At,
+    /// Cross the waypoint at or above its altitude, used while climbing.
+    Above,
+    /// Cross the waypoint at or below its altitude, used while descending.
+    Below,
+    /// The altitude is not a constraint, it isn't written to the .fgfp.
+    None,
+}
+
+impl AltRestrict {
+    /// The value FlightGear uses for the restriction in the `alt-restrict` element.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AltRestrict::At => "at",
+            AltRestrict::Above => "above",
+            AltRestrict::Below => "below",
+            AltRestrict::None => "none",
+        }
+    }
+}
+
+impl FromStr for AltRestrict {
+    type Err = String;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "at" => Ok(AltRestrict::At),
+            "above" => Ok(AltRestrict::Above),
+            "below" => Ok(AltRestrict::Below),
+            "none" => Ok(AltRestrict::None),
+            _ => Err(format!(
+                "Unknown altitude restriction `{s}`, expected at, above, below or none"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for AltRestrict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The speed restriction of a waypoint. Speeds are given in knots, except for
+/// [`SpeedRestrict::Mach`].
+///
+/// It can be parsed from strings like `250`, `below 250`, `above 210` or `M0.78`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SpeedRestrict {
+    At(u32),
+    Above(u32),
+    Below(u32),
+    /// Cross the waypoint at this Mach number.
+    Mach(f64),
+}
+
+impl SpeedRestrict {
+    /// The value FlightGear uses for the restriction in the `speed-restrict` element.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpeedRestrict::At(_) => "at",
+            SpeedRestrict::Above(_) => "above",
+            SpeedRestrict::Below(_) => "below",
+            SpeedRestrict::Mach(_) => "mach",
+        }
+    }
+}
+
+impl FromStr for SpeedRestrict {
+    type Err = String;
+
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        let lowercase = s.trim().to_lowercase();
+        let (kind, speed) = match lowercase.split_once(char::is_whitespace) {
+            Some((kind, speed)) => (kind, speed.trim()),
+            None => ("at", lowercase.as_str()),
+        };
+
+        if let Some(mach) = speed.strip_prefix('m') {
+            let mach = mach
+                .parse()
+                .map_err(|_| format!("Invalid Mach number in speed restriction `{s}`"))?;
+
+            return match kind {
+                "at" => Ok(SpeedRestrict::Mach(mach)),
+                _ => Err(format!("Mach speed restriction `{s}` can only be `at`")),
+            };
+        }
+
+        let knots = speed
+            .trim_end_matches("kt")
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid speed in speed restriction `{s}`"))?;
+
+        match kind {
+            "at" => Ok(SpeedRestrict::At(knots)),
+            "above" => Ok(SpeedRestrict::Above(knots)),
+            "below" => Ok(SpeedRestrict::Below(knots)),
+            _ => Err(format!(
+                "Unknown speed restriction `{kind}`, expected at, above or below"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SpeedRestrict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpeedRestrict::At(knots) => write!(f, "{knots} kt"),
+            SpeedRestrict::Above(knots) => write!(f, "above {knots} kt"),
+            SpeedRestrict::Below(knots) => write!(f, "below {knots} kt"),
+            SpeedRestrict::Mach(mach) => write!(f, "M{mach:.2}"),
+        }
+    }
+}
+
+/// This function finds the departure and destination airports of the .kml file, the first and last
+/// Placemarks when they're airports, with their positions. The `departure` and `destination` given
+/// only set the runways of the airports of the .kml file, and they're used when it doesn't have
+/// them.
+///
+/// It's meant to be called before [`transform_route`](transform_route), so the airports that are
+/// only in the .kml file can be checked like the given ones.
+///
+/// # Errors
+/// This function fails if an airport given isn't the one of the .kml file.
+///
+/// # Example
+///
+/// ```
+/// # use kml_to_fgfp::{find_airports, EventReader};
+/// let kml = r#"<kml><Document>
+///     <Placemark><name>SAEZ</name><styleUrl>#AirportMark</styleUrl>
+///         <Point><coordinates>-58.5358,-34.8222,0</coordinates></Point></Placemark>
+///     <Placemark><name>ARSOT</name><styleUrl>#FixMark</styleUrl>
+///         <Point><coordinates>-56.70,-34.40,10000</coordinates></Point></Placemark>
+///     <Placemark><name>SUMU</name><styleUrl>#AirportMark</styleUrl>
+///         <Point><coordinates>-56.0308,-34.8384,0</coordinates></Point></Placemark>
+/// </Document></kml>"#;
+///
+/// let parser = || EventReader::new(kml.as_bytes());
+///
+/// let (departure, destination) = find_airports(parser(), &None, &None, false).unwrap();
+/// assert_eq!(departure.unwrap().ident, "SAEZ");
+/// assert!(destination.unwrap().coordinates.is_some());
+///
+/// // The given airports only set the runways.
+/// let runway = Some("SAEZ/11".parse().unwrap());
+/// let (departure, _) = find_airports(parser(), &runway, &None, false).unwrap();
+/// assert_eq!(departure.unwrap().runway.as_deref(), Some("11"));
+///
+/// let other = Some("SABE/13".parse().unwrap());
+/// assert!(find_airports(parser(), &other, &None, false).is_err());
+/// ```
+pub fn find_airports<R: Read>(
+    parser: EventReader<R>,
+    departure: &Option<Airport>,
+    destination: &Option<Airport>,
+    read_paths: bool,
+) -> result::Result<(Option<Airport>, Option<Airport>), Box<dyn Error>> {
+    let mut route = read_route(parser, departure, destination, read_paths);
+    let (found_departure, found_destination) = take_airports(&mut route);
+
+    Ok((
+        merge_airport(departure, found_departure, "departure")?,
+        merge_airport(destination, found_destination, "destination")?,
+    ))
 }
 
 // TODO Idea: Use `output: Option<PathBuf>` to handle writing to a file or stdout.
 /// This function will use the Placemarks in the .kml file to write a route using waypoints for the
-/// .fgfp file.
+/// .fgfp file. It returns a [`Report`](Report) about the route.
+///
+/// The first and last Placemarks are the departure and destination airports when they're airports.
+/// The `departure` and `destination` given only set their runways, and are used when the .kml file
+/// doesn't have them. To check the airports of the .kml file before, they're found with
+/// [`find_airports`](find_airports).
 ///
 /// # Errors
-/// This function can fail if trying to write invalid xml or other io errors.
+/// This function can fail if an airport given isn't the one of the .kml file, if trying to write
+/// invalid xml or other io errors.
 pub fn transform_route<W: Write, R: Read>(
     parser: EventReader<R>,
     writer: &mut EventWriter<W>,
     departure: &Option<Airport>,
     destination: &Option<Airport>,
-) -> result::Result<(), Box<dyn Error>> {
+    options: &RouteOptions,
+) -> result::Result<Report, Box<dyn Error>> {
+    let mut route = read_route(parser, departure, destination, options.read_paths);
+    let (found_departure, found_destination) = take_airports(&mut route);
+    let departure = &merge_airport(departure, found_departure, "departure")?;
+    let destination = &merge_airport(destination, found_destination, "destination")?;
+    drop_airport_placemarks(
+        &mut route,
+        departure,
+        destination,
+        options.airport_radius_nm,
+    );
+
+    let mut report = Report {
+        departure: departure.clone(),
+        destination: destination.clone(),
+        transition_altitude: options.transition_altitude.or_else(|| {
+            [departure, destination]
+                .iter()
+                .filter_map(|ap| ap.as_ref())
+                .find_map(|ap| transition::transition_altitude(&ap.ident))
+        }),
+        ..Default::default()
+    };
+
+    apply_altitude_modes(&mut route);
+    if let Some(library) = &options.user_waypoints {
+        user::apply_user_waypoints(&mut route, library);
+    }
+    procedures::insert_procedures(
+        &mut route,
+        &options.departure_procedure,
+        &options.arrival_procedure,
+    );
+    round_altitudes(&mut route, options.altitude_rounding);
+
+    route = holds::apply_holds_and_discontinuities(route, options);
+    // The fixes inserted along the airways have interpolated altitudes.
+    airways::apply_airways(
+        &mut route,
+        &options.route_string,
+        options.airways.as_ref(),
+        options.user_waypoints.as_ref(),
+    )?;
+    round_altitudes(&mut route, options.altitude_rounding);
+
+    if let Some(lookup) = &options.fixes {
+        report.diagnostics = snap::snap_to_fixes(&mut route, lookup);
+    }
+
+    let diagnostics;
+    (route, diagnostics) = validate::validate(route, &options.validation);
+    report.diagnostics.extend(diagnostics);
+
+    for diagnostic in &report.diagnostics {
+        let action = if diagnostic.fixed {
+            "Fixing"
+        } else {
+            "Warning"
+        };
+
+        eprintln!(
+            "\x1B[01;33m{}\x1B[00;01m {}\x1B[00m waypoint: {}",
+            action, diagnostic.ident, diagnostic.problem
+        );
+    }
+
+    if options.validation.strict && !report.diagnostics.is_empty() {
+        return Err(format!(
+            "The route has {} problems, refusing to write it",
+            report.diagnostics.len()
+        )
+        .into());
+    }
+
+    let origin = departure.as_ref().and_then(|ap| ap.coordinates);
+    let steps: Vec<StepClimb> = options
+        .step_climbs
+        .iter()
+        .map(|step| StepClimb {
+            altitude: options.altitude_rounding.round(step.altitude),
+            ..step.clone()
+        })
+        .collect();
+    report.cruise_altitude = cruise::apply_cruise(
+        &mut route,
+        options
+            .cruise_altitude
+            .map(|c| options.altitude_rounding.round(c)),
+        &steps,
+        origin,
+    );
+
+    if let Some(s) = &options.simplify {
+        (route, report.simplified) = simplify::simplify(route, s);
+    }
+
+    if let Some(d) = &options.densify {
+        route = densify::densify(route, d);
+        round_altitudes(&mut route, options.altitude_rounding);
+    }
+
+    let end = destination.as_ref().and_then(|ap| ap.coordinates);
+    let top_of_climb =
+        profile::top_of_climb(&route, report.cruise_altitude, &options.vertical_profile);
+    let top_of_descent = profile::top_of_descent(&route, &options.vertical_profile);
+
+    report.top_of_climb_nm = top_of_climb
+        .as_ref()
+        .map(|top| profile::distances(&route, top, origin, end).0);
+    report.top_of_descent_nm = top_of_descent
+        .as_ref()
+        .map(|top| profile::distances(&route, top, origin, end).1);
+
+    if options.insert_tops {
+        // The top of descent goes first, so the position of the top of climb stays the same.
+        if let Some(top) = &top_of_descent {
+            profile::insert_top(&mut route, top, "T/D");
+        }
+        if let Some(top) = &top_of_climb {
+            profile::insert_top(&mut route, top, "T/C");
+        }
+    }
+
+    infer_alt_restrictions(&mut route, options.alt_restrict, report.cruise_altitude);
+    apply_speed_restrictions(&mut route, options);
+
+    let year = options.date.unwrap_or_else(magnetic::current_year);
+    let model = &options.magnetic_model;
+    if !model.is_valid_for(year) {
+        eprintln!(
+            "\x1B[01;33mWarning\x1B[00m: {} is valid from {} to {}, the magnetic variation may be inaccurate",
+            model.name,
+            model.epoch,
+            model.epoch + 5.0
+        );
+    }
+    let variation = |c| model.declination(c, 0.0, year);
+
+    for waypoint in route.iter_mut() {
+        waypoint.variation = variation(waypoint.coordinates());
+    }
+
+    // Measure the legs before collapsing the airways, so they follow every fix along them.
+    report.legs = measure_legs(&route, departure, destination, variation);
+    report.generated = route
+        .iter()
+        .filter(|w| w.generated)
+        .map(|w| w.ident.clone())
+        .collect();
+
+    if !options.expand_airways {
+        route = airways::collapse_airways(route);
+    }
+
+    adapt_to_target(&mut route, options.target);
+
+    // Altitudes above the transition altitude are written as flight levels, when the target can.
+    let transition = report
+        .transition_altitude
+        .filter(|_| options.target.supports(Feature::FlightLevel));
+
+    write_route(
+        writer,
+        &route,
+        departure,
+        destination,
+        options.target,
+        transition,
+    )?;
+
+    Ok(report)
+}
+
+/// Internal function that measures the legs of the route, skipping discontinuities.
+fn measure_legs(
+    route: &[Waypoint],
+    departure: &Option<Airport>,
+    destination: &Option<Airport>,
+    variation: impl Fn(Coordinates) -> f64,
+) -> Vec<Leg> {
+    let airport_point = |airport: &Option<Airport>| {
+        airport
+            .as_ref()
+            .and_then(|ap| ap.coordinates.map(|c| (ap.ident.clone(), c)))
+    };
+
+    let mut points = Vec::with_capacity(route.len() + 2);
+    points.extend(airport_point(departure));
+    points.extend(
+        route
+            .iter()
+            .filter(|w| w.kind != WaypointType::Discontinuity)
+            .map(|w| (w.ident.clone(), w.coordinates())),
+    );
+    points.extend(airport_point(destination));
+
+    geodesy::legs(&points, variation)
+}
+
+/// Internal function that reads the waypoints of the route from the Placemarks in the .kml file.
+fn read_route<R: Read>(
+    parser: EventReader<R>,
+    departure: &Option<Airport>,
+    destination: &Option<Airport>,
+    read_paths: bool,
+) -> Vec<Waypoint> {
     use xml::reader::XmlEvent;
 
     let mut current_search = LookingFor::OpeningPlacemark;
 
     // The waypoint information
-    let mut wp = 0;
+    let mut route = Vec::new();
     let mut drop = false;
-    let mut waypoint = Waypoint {
-        number: wp,
-        ident: String::new(),
-        lon: 0f64,
-        lat: 0f64,
-        altitude: 0,
+    let mut waypoint = Waypoint::new();
+    let mut field: Option<DataField> = None;
+
+    for element in parser {
+        match element {
+            Ok(XmlEvent::StartElement {
+                name, attributes, ..
+            }) => {
+                let name = name.to_string();
+                let name = simplify_name(&name);
+
+                field = handlers::handle_data_start(field, &current_search, name, &attributes);
+
+                (waypoint, current_search, drop) =
+                    handlers::handle_start_event(waypoint, current_search, drop, name);
+            }
+            Ok(XmlEvent::Characters(line)) => {
+                if handlers::handle_data_characters(&mut waypoint, &mut field, &line) {
+                    continue;
+                }
+
+                (waypoint, current_search, drop) = handlers::handle_characters_event(
+                    waypoint,
+                    current_search,
+                    drop,
+                    line,
+                    departure,
+                    destination,
+                    read_paths,
+                );
+            }
+            Ok(XmlEvent::EndElement { name }) => {
+                let name = name.to_string();
+                let name = simplify_name(&name);
+
+                field = handlers::handle_data_end(field, name);
+
+                (waypoint, current_search) =
+                    handlers::handle_end_event(&mut route, waypoint, current_search, drop, name);
+            }
+            Err(e) => {
+                // Split the error message to simplify it.
+                let msg = e.to_string();
+                let msg: Vec<&str> = msg.split_whitespace().map(simplify_name).collect();
+
+                // Make a string from the vector.
+                let mut message = String::new();
+                for m in msg {
+                    message.push_str(format!("{m} ").as_str());
+                }
+
+                // Print error and exit loop.
+                eprintln!("\x1B[01;33mError on line\x1B[00m: {message}");
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    route
+}
+
+/// Internal function that takes the airport Placemarks out of the route. Returns the airports of
+/// the first and last Placemarks, which are the departure and destination.
+///
+/// Placemarks marked as airports by their style, like SimBrief's, are only airports at the ends of
+/// the route. In between, they're dropped like the other Placemarks without the `#FixMark` style.
+fn take_airports(route: &mut Vec<Waypoint>) -> (Option<Airport>, Option<Airport>) {
+    let to_airport = |waypoint: &Waypoint| Airport {
+        ident: first_word(&waypoint.ident).to_uppercase(),
+        coordinates: Some(waypoint.coordinates()),
+        ..Default::default()
+    };
+    let is_airport = |waypoint: &&Waypoint| waypoint.airport || waypoint.airport_mark;
+
+    let departure = route.first().filter(is_airport).map(to_airport);
+    let destination = match route.len() {
+        0 | 1 => None,
+        _ => route.last().filter(is_airport).map(to_airport),
     };
 
+    route.retain(|w| !w.airport && !w.airport_mark);
+
+    (departure, destination)
+}
+
+/// Internal function that drops the Placemarks that are the `departure` or `destination` airport
+/// under another name: their ICAO code in any case, like `saez` or `SAEZ Ministro Pistarini`, their
+/// IATA code, like `EZE`, or, with a `radius_nm`, any name near the airport's reference point.
+fn drop_airport_placemarks(
+    route: &mut Vec<Waypoint>,
+    departure: &Option<Airport>,
+    destination: &Option<Airport>,
+    radius_nm: Option<f64>,
+) {
+    let airports: Vec<&Airport> = [departure, destination].into_iter().flatten().collect();
+
+    let is_airport = |waypoint: &Waypoint, airport: &Airport| {
+        let name = first_word(&waypoint.ident);
+        let iata = airport
+            .iata
+            .as_deref()
+            .or_else(|| iata::iata_code(&airport.ident));
+
+        let is_near = match (radius_nm, airport.coordinates) {
+            (Some(radius), Some(coordinates)) => {
+                geodesy::distance_nm(waypoint.coordinates(), coordinates) <= radius
+            }
+            _ => false,
+        };
+
+        name.eq_ignore_ascii_case(&airport.ident)
+            || iata.is_some_and(|iata| name.eq_ignore_ascii_case(iata))
+            || is_near
+    };
+
+    route.retain(|waypoint| {
+        // Only the named fixes of the .kml can be the airports.
+        if waypoint.track_point || waypoint.kind == WaypointType::Discontinuity {
+            return true;
+        }
+
+        match airports.iter().find(|ap| is_airport(waypoint, ap)) {
+            Some(airport) => {
+                eprintln!(
+                    "\x1B[01;33mDropping\x1B[00;01m {}\x1B[00m waypoint: it's the airport {}",
+                    waypoint.ident, airport.ident
+                );
+                false
+            }
+            None => true,
+        }
+    });
+}
+
+/// Internal function that combines an airport given by the caller with the one found in the .kml
+/// file. The .kml's airport keeps its ident and position, and takes the runway, procedures and
+/// whatever else is known about the given one. A different airport is an error.
+fn merge_airport(
+    given: &Option<Airport>,
+    found: Option<Airport>,
+    role: &str,
+) -> result::Result<Option<Airport>, String> {
+    match (given, found) {
+        (Some(given), Some(found)) if is_same_airport(given, &found.ident) => Ok(Some(Airport {
+            ident: found.ident,
+            coordinates: found.coordinates,
+            ..given.clone()
+        })),
+        (Some(given), Some(found)) => Err(format!(
+            "The {role} airport of the .kml file is {}, not {}",
+            found.ident, given.ident
+        )),
+        (given, found) => Ok(given.clone().or(found)),
+    }
+}
+
+/// Internal function that checks if the airport with the ICAO code `ident` is the `airport` given,
+/// which can be named by its IATA code.
+fn is_same_airport(airport: &Airport, ident: &str) -> bool {
+    let iata = airport.iata.as_deref().or_else(|| iata::iata_code(ident));
+
+    airport.ident.eq_ignore_ascii_case(ident)
+        || iata.is_some_and(|iata| airport.ident.eq_ignore_ascii_case(iata))
+}
+
+/// Internal function that writes the route, with the airport waypoints around it, to the .fgfp file.
+fn write_route<W: Write>(
+    writer: &mut EventWriter<W>,
+    route: &[Waypoint],
+    departure: &Option<Airport>,
+    destination: &Option<Airport>,
+    target: FgTarget,
+    transition: Option<f64>,
+) -> xml::writer::Result<()> {
+    let mut wp = 0;
+
     super::write_event(writer, EventType::OpeningElement, "route")?;
 
+    // The legacy format only has the airports in the departure and destination elements.
+    let departure = departure
+        .as_ref()
+        .filter(|_| target.supports(Feature::RunwayWaypoints));
+    let destination = destination
+        .as_ref()
+        .filter(|_| target.supports(Feature::RunwayWaypoints));
+
     if let Some(ap) = departure {
         write_ap_waypoint(writer, ap, true, wp)?;
         wp += 1;
     }
 
-    for element in parser {
-        match element {
-            Ok(XmlEvent::StartElement { name, .. }) => {
-                let name = name.to_string();
-                let name = simplify_name(&name);
+    for waypoint in route {
+        match target.format_version() {
+            1 => write_legacy_waypoint(writer, waypoint)?,
+            _ => write_waypoint(writer, waypoint, wp, transition)?,
+        }
+        wp += 1;
+    }
+
+    if let Some(ap) = destination {
+        write_ap_waypoint(writer, ap, false, wp)?;
+    }
+
+    super::write_event(writer, EventType::ClosingElement, "route")?;
+
+    Ok(())
+}
+
+/// Internal function that removes from the route what the `target` can't represent, printing a
+/// warning for every kind of feature that's lost.
+///
+/// `via` waypoints and holds become basic waypoints at the same fix, and discontinuities are
+/// removed.
+fn adapt_to_target(route: &mut Vec<Waypoint>, target: FgTarget) {
+    let mut lost = Vec::new();
+
+    for waypoint in route.iter_mut() {
+        let feature = match waypoint.kind {
+            WaypointType::Basic => None,
+            WaypointType::Via => Some(Feature::Via),
+            WaypointType::Hold(_) => Some(Feature::Hold),
+            WaypointType::Discontinuity => Some(Feature::Discontinuity),
+        };
+
+        if let Some(feature) = feature.filter(|f| !target.supports(*f)) {
+            if feature != Feature::Discontinuity {
+                waypoint.kind = WaypointType::Basic;
+            }
+            lost.push(feature);
+        }
+
+        let is_lost = matches!(
+            waypoint.alt_restrict,
+            AltRestrict::Above | AltRestrict::Below
+        );
+        if is_lost && !target.supports(Feature::AltRestrict) {
+            waypoint.alt_restrict = AltRestrict::At;
+            lost.push(Feature::AltRestrict);
+        }
+
+        if waypoint.speed_restrict.is_some() && !target.supports(Feature::SpeedRestrict) {
+            waypoint.speed_restrict = None;
+            lost.push(Feature::SpeedRestrict);
+        }
+    }
+
+    if !target.supports(Feature::Discontinuity) {
+        route.retain(|w| w.kind != WaypointType::Discontinuity);
+    }
+
+    let mut warned = Vec::new();
+    for feature in lost {
+        if !warned.contains(&feature) {
+            target::warn_unsupported(target, feature);
+            warned.push(feature);
+        }
+    }
+}
+
+/// Internal function that sets the altitude mode of every waypoint from its `altitudeMode`, printing
+/// a warning for the altitudes relative to the ground, which are written without altitude.
+fn apply_altitude_modes(route: &mut [Waypoint]) {
+    let mut relative = 0;
+
+    for waypoint in route.iter_mut() {
+        if let Some(value) = waypoint.data("altitudeMode") {
+            match value.parse() {
+                Ok(mode) => waypoint.altitude_mode = mode,
+                Err(e) => eprintln!(
+                    "\x1B[01;33mIgnoring\x1B[00;01m {}\x1B[00m altitude mode: {}",
+                    waypoint.ident, e
+                ),
+            }
+        }
+
+        if waypoint.altitude_mode == AltitudeMode::RelativeToGround {
+            relative += 1;
+        }
+    }
+
+    if relative > 0 {
+        eprintln!(
+            "\x1B[01;33mWarning\x1B[00m: {relative} waypoints have altitudes relative to the ground, they're written without altitude"
+        );
+    }
+}
 
-                (waypoint, current_search, drop) =
-                    handlers::handle_start_event(waypoint, current_search, drop, wp, name);
-            }
-            Ok(XmlEvent::Characters(line)) => {
-                (waypoint, current_search, drop) = handlers::handle_characters_event(
-                    waypoint,
-                    current_search,
-                    drop,
-                    line,
-                    departure,
-                    destination,
-                );
-            }
-            Ok(XmlEvent::EndElement { name }) => {
-                let name = name.to_string();
-                let name = simplify_name(&name);
+/// Internal function that rounds the altitude of every waypoint.
+fn round_altitudes(route: &mut [Waypoint], rounding: AltitudeRounding) {
+    for waypoint in route.iter_mut() {
+        waypoint.altitude = rounding.round(waypoint.altitude);
+    }
+}
 
-                (waypoint, current_search, wp) =
-                    handlers::handle_end_event(writer, waypoint, current_search, drop, wp, name)?;
-            }
-            Err(e) => {
-                // Split the error message to simplify it.
-                let msg = e.to_string();
-                let msg: Vec<&str> = msg
-                    .split_whitespace()
-                    .map(simplify_name)
-                    .collect();
+/// Internal function that sets the altitude restriction of every waypoint in the route.
+///
+/// Unless `forced` is given, the restriction comes from the vertical profile: waypoints before
+/// reaching the cruise altitude are climbing, so they get [`AltRestrict::Above`]; waypoints after
+/// leaving it are descending, so they get [`AltRestrict::Below`]; and the cruise waypoints get
+/// [`AltRestrict::At`].
+///
+/// A waypoint with an `alt-restrict` field in its `ExtendedData` always keeps that restriction.
+fn infer_alt_restrictions(
+    route: &mut [Waypoint],
+    forced: Option<AltRestrict>,
+    cruise: Option<f64>,
+) {
+    let is_absolute = |w: &Waypoint| w.altitude_mode == AltitudeMode::Absolute;
 
-                // Make a string from the vector.
-                let mut message = String::new();
-                for m in msg {
-                    message.push_str(format!("{m} ").as_str());
-                }
+    // The climb ends at the cruise altitude, and the descent starts at the highest step.
+    let highest = route
+        .iter()
+        .filter(|w| is_absolute(w))
+        .map(|w| w.altitude)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let cruise = cruise.unwrap_or(highest);
+    let top_of_climb = route.iter().position(|w| is_absolute(w) && w.is_at(cruise));
+    let top_of_descent = route
+        .iter()
+        .rposition(|w| is_absolute(w) && w.is_at(highest));
 
-                // Print error and exit loop.
-                eprintln!("\x1B[01;33mError on line\x1B[00m: {message}");
-                break;
+    for (i, waypoint) in route.iter_mut().enumerate() {
+        waypoint.alt_restrict = match forced {
+            // Only absolute altitudes can be a restriction.
+            _ if !is_absolute(waypoint) => AltRestrict::None,
+            Some(restriction) => restriction,
+            None if top_of_climb.is_some_and(|toc| i < toc) => AltRestrict::Above,
+            None if top_of_descent.is_some_and(|tod| i > tod) => AltRestrict::Below,
+            None => AltRestrict::At,
+        };
+
+        if let Some(value) = waypoint
+            .data("alt-restrict")
+            .filter(|_| is_absolute(waypoint))
+        {
+            match value.parse() {
+                Ok(restriction) => waypoint.alt_restrict = restriction,
+                Err(e) => eprintln!(
+                    "\x1B[01;33mIgnoring\x1B[00;01m {}\x1B[00m altitude restriction: {}",
+                    waypoint.ident, e
+                ),
             }
-            _ => {}
         }
     }
+}
 
-    if let Some(ap) = destination {
-        write_ap_waypoint(writer, ap, false, wp)?;
-    }
+/// Internal function that sets the speed restriction of the waypoints in the route.
+///
+/// In order of priority, the restriction comes from the `speed_rules` of the options, the `speed`
+/// field in the `ExtendedData`, and the `speed_limit` of the options.
+fn apply_speed_restrictions(route: &mut [Waypoint], options: &RouteOptions) {
+    for waypoint in route.iter_mut() {
+        if let Some((limit, altitude)) = options.speed_limit {
+            if waypoint.altitude_mode == AltitudeMode::Absolute && waypoint.altitude < altitude {
+                waypoint.speed_restrict = Some(limit);
+            }
+        }
 
-    super::write_event(writer, EventType::ClosingElement, "route")?;
+        if let Some(value) = waypoint.data("speed") {
+            match value.parse() {
+                Ok(restriction) => waypoint.speed_restrict = Some(restriction),
+                Err(e) => eprintln!(
+                    "\x1B[01;33mIgnoring\x1B[00;01m {}\x1B[00m speed restriction: {}",
+                    waypoint.ident, e
+                ),
+            }
+        }
 
-    Ok(())
+        if let Some((_, restriction)) = options
+            .speed_rules
+            .iter()
+            .find(|(ident, _)| *ident == waypoint.ident)
+        {
+            waypoint.speed_restrict = Some(*restriction);
+        }
+    }
 }
 
 /// Function that takes a waypoint and writes it to the .fgfp file
 #[rustfmt::skip]
-fn write_waypoint<W: Write>(writer: &mut EventWriter<W>, wp: &Waypoint) -> xml::writer::Result<()> {
-    let number = if wp.number > 0 {
-        format!(" n={}", wp.number)
+fn write_waypoint<W: Write>(
+    writer: &mut EventWriter<W>,
+    wp: &Waypoint,
+    wp_counter: usize,
+    transition: Option<f64>,
+) -> xml::writer::Result<()> {
+    let number = if wp_counter > 0 {
+        format!(" n={wp_counter}")
     } else {
         String::new()
     };
@@ -123,17 +1446,33 @@ fn write_waypoint<W: Write>(writer: &mut EventWriter<W>, wp: &Waypoint) -> xml::
 
     super::write_event(writer, EventType::OpeningElement, &opening)?;
 
+    let kind = match &wp.kind {
+        WaypointType::Basic if wp.navaid => "navaid",
+        WaypointType::Basic => "basic",
+        WaypointType::Hold(_) => "hold",
+        WaypointType::Via => {
+            write_via_details(writer, wp, transition)?;
+            super::write_event(writer, EventType::ClosingElement, "wp")?;
+            return Ok(());
+        }
+        WaypointType::Discontinuity => {
+            super::write_event(writer, EventType::OpeningElement, "type type=string")?;
+            super::write_event(writer, EventType::Content, "discontinuity")?;
+            super::write_event(writer, EventType::ClosingElement, "type")?;
+            super::write_event(writer, EventType::ClosingElement, "wp")?;
+            return Ok(());
+        }
+    };
+
     super::write_event(writer, EventType::OpeningElement, "type type=string")?;
-    super::write_event(writer, EventType::Content, "basic")?;
+    super::write_event(writer, EventType::Content, kind)?;
     super::write_event(writer, EventType::ClosingElement, "type")?;
 
-    super::write_event(writer, EventType::OpeningElement, "alt-restrict type=string")?;
-    super::write_event(writer, EventType::Content, "at")?;
-    super::write_event(writer, EventType::ClosingElement, "alt-restrict")?;
+    if let WaypointType::Hold(hold) = &wp.kind {
+        write_hold_details(writer, hold)?;
+    }
 
-    super::write_event(writer, EventType::OpeningElement, "altitude-ft type=double")?;
-    super::write_event(writer, EventType::Content, format!("{:.0}", &wp.altitude).as_str())?;
-    super::write_event(writer, EventType::ClosingElement, "altitude-ft")?;
+    write_restrictions(writer, wp, transition)?;
 
     super::write_event(writer, EventType::OpeningElement, "ident type=string")?;
     super::write_event(writer, EventType::Content, &wp.ident)?;
@@ -152,6 +1491,141 @@ fn write_waypoint<W: Write>(writer: &mut EventWriter<W>, wp: &Waypoint) -> xml::
     Ok(())
 }
 
+/// Function that takes a waypoint and writes it to a legacy version-1 route file, which only knows
+/// the ident, coordinates and altitude of the waypoints.
+#[rustfmt::skip]
+fn write_legacy_waypoint<W: Write>(writer: &mut EventWriter<W>, wp: &Waypoint) -> xml::writer::Result<()> {
+    super::write_event(writer, EventType::OpeningElement, "wp")?;
+
+    super::write_event(writer, EventType::OpeningElement, "ident type=string")?;
+    super::write_event(writer, EventType::Content, &wp.ident)?;
+    super::write_event(writer, EventType::ClosingElement, "ident")?;
+
+    super::write_event(writer, EventType::OpeningElement, "longitude-deg type=double")?;
+    super::write_event(writer, EventType::Content, format!("{:.6}", wp.lon).as_str())?;
+    super::write_event(writer, EventType::ClosingElement, "longitude-deg")?;
+
+    super::write_event(writer, EventType::OpeningElement, "latitude-deg type=double")?;
+    super::write_event(writer, EventType::Content, format!("{:.6}", wp.lat).as_str())?;
+    super::write_event(writer, EventType::ClosingElement, "latitude-deg")?;
+
+    if wp.alt_restrict != AltRestrict::None {
+        super::write_event(writer, EventType::OpeningElement, "altitude-ft type=double")?;
+        super::write_event(writer, EventType::Content, format!("{}", wp.altitude).as_str())?;
+        super::write_event(writer, EventType::ClosingElement, "altitude-ft")?;
+    }
+
+    super::write_event(writer, EventType::ClosingElement, "wp")?;
+
+    Ok(())
+}
+
+/// Internal function that writes the details of a `hold` waypoint: the inbound course, the direction
+/// of the turns and the length of the legs.
+#[rustfmt::skip]
+fn write_hold_details<W: Write>(writer: &mut EventWriter<W>, hold: &Hold) -> xml::writer::Result<()> {
+    let (is_distance, length) = match hold.leg {
+        HoldLeg::Minutes(minutes) => (false, minutes * 60.0),
+        HoldLeg::NauticalMiles(nm) => (true, nm),
+    };
+
+    super::write_event(writer, EventType::OpeningElement, "hold-radial type=double")?;
+    super::write_event(writer, EventType::Content, format!("{:.1}", hold.inbound_course).as_str())?;
+    super::write_event(writer, EventType::ClosingElement, "hold-radial")?;
+
+    super::write_event(writer, EventType::OpeningElement, "hold-righthanded type=bool")?;
+    super::write_event(writer, EventType::Content, &hold.right_turns.to_string())?;
+    super::write_event(writer, EventType::ClosingElement, "hold-righthanded")?;
+
+    super::write_event(writer, EventType::OpeningElement, "hold-distance type=bool")?;
+    super::write_event(writer, EventType::Content, &is_distance.to_string())?;
+    super::write_event(writer, EventType::ClosingElement, "hold-distance")?;
+
+    // FlightGear wants the time of the legs in seconds.
+    super::write_event(writer, EventType::OpeningElement, "hold-td type=double")?;
+    super::write_event(writer, EventType::Content, format!("{length:.1}").as_str())?;
+    super::write_event(writer, EventType::ClosingElement, "hold-td")?;
+
+    Ok(())
+}
+
+/// Function that writes the altitude and speed restrictions of a waypoint to the .fgfp file.
+/// Altitudes above the `transition` altitude are written as flight levels.
+#[rustfmt::skip]
+fn write_restrictions<W: Write>(
+    writer: &mut EventWriter<W>,
+    wp: &Waypoint,
+    transition: Option<f64>,
+) -> xml::writer::Result<()> {
+    if wp.alt_restrict != AltRestrict::None {
+        super::write_event(writer, EventType::OpeningElement, "alt-restrict type=string")?;
+        super::write_event(writer, EventType::Content, wp.alt_restrict.as_str())?;
+        super::write_event(writer, EventType::ClosingElement, "alt-restrict")?;
+
+        if transition.is_some_and(|t| wp.altitude > t) {
+            super::write_event(writer, EventType::OpeningElement, "flight-level type=int")?;
+            super::write_event(writer, EventType::Content, format!("{:.0}", wp.altitude / 100.0).as_str())?;
+            super::write_event(writer, EventType::ClosingElement, "flight-level")?;
+        } else {
+            super::write_event(writer, EventType::OpeningElement, "altitude-ft type=double")?;
+            super::write_event(writer, EventType::Content, format!("{}", wp.altitude).as_str())?;
+            super::write_event(writer, EventType::ClosingElement, "altitude-ft")?;
+        }
+    }
+
+    if let Some(speed) = &wp.speed_restrict {
+        let (opening, value) = match speed {
+            SpeedRestrict::Mach(mach) => ("speed type=double", format!("{mach:.2}")),
+            SpeedRestrict::At(knots)
+            | SpeedRestrict::Above(knots)
+            | SpeedRestrict::Below(knots) => ("speed type=int", knots.to_string()),
+        };
+
+        super::write_event(writer, EventType::OpeningElement, "speed-restrict type=string")?;
+        super::write_event(writer, EventType::Content, speed.as_str())?;
+        super::write_event(writer, EventType::ClosingElement, "speed-restrict")?;
+
+        super::write_event(writer, EventType::OpeningElement, opening)?;
+        super::write_event(writer, EventType::Content, &value)?;
+        super::write_event(writer, EventType::ClosingElement, "speed")?;
+    }
+
+    Ok(())
+}
+
+/// Internal function that writes the details of a `via` waypoint, which references the airway and
+/// its exit fix.
+#[rustfmt::skip]
+fn write_via_details<W: Write>(
+    writer: &mut EventWriter<W>,
+    wp: &Waypoint,
+    transition: Option<f64>,
+) -> xml::writer::Result<()> {
+    super::write_event(writer, EventType::OpeningElement, "type type=string")?;
+    super::write_event(writer, EventType::Content, "via")?;
+    super::write_event(writer, EventType::ClosingElement, "type")?;
+
+    write_restrictions(writer, wp, transition)?;
+
+    super::write_event(writer, EventType::OpeningElement, "airway type=string")?;
+    super::write_event(writer, EventType::Content, wp.airway.as_deref().unwrap_or_default())?;
+    super::write_event(writer, EventType::ClosingElement, "airway")?;
+
+    super::write_event(writer, EventType::OpeningElement, "to type=string")?;
+    super::write_event(writer, EventType::Content, &wp.ident)?;
+    super::write_event(writer, EventType::ClosingElement, "to")?;
+
+    super::write_event(writer, EventType::OpeningElement, "lon type=double")?;
+    super::write_event(writer, EventType::Content, format!("{:.6}", wp.lon).as_str())?;
+    super::write_event(writer, EventType::ClosingElement, "lon")?;
+
+    super::write_event(writer, EventType::OpeningElement, "lat type=double")?;
+    super::write_event(writer, EventType::Content, format!("{:.6}", wp.lat).as_str())?;
+    super::write_event(writer, EventType::ClosingElement, "lat")?;
+
+    Ok(())
+}
+
 /// Function that takes an airport waypoint and writes it to the .fgfp file
 fn write_ap_waypoint<W: Write>(
     writer: &mut EventWriter<W>,
@@ -193,6 +1667,31 @@ fn write_ap_waypoint<W: Write>(
     super::write_event(writer, EventType::Content, &airport.ident)?;
     super::write_event(writer, EventType::ClosingElement, "icao")?;
 
+    // The exact position of the runway, when the threshold is known.
+    if let Some(threshold) = &airport.threshold {
+        super::write_event(writer, EventType::OpeningElement, "lon type=double")?;
+        super::write_event(
+            writer,
+            EventType::Content,
+            format!("{:.6}", threshold.lon).as_str(),
+        )?;
+        super::write_event(writer, EventType::ClosingElement, "lon")?;
+
+        super::write_event(writer, EventType::OpeningElement, "lat type=double")?;
+        super::write_event(
+            writer,
+            EventType::Content,
+            format!("{:.6}", threshold.lat).as_str(),
+        )?;
+        super::write_event(writer, EventType::ClosingElement, "lat")?;
+    }
+
+    if let Some(heading) = airport.runway_heading {
+        super::write_event(writer, EventType::OpeningElement, "heading-deg type=double")?;
+        super::write_event(writer, EventType::Content, format!("{heading:.2}").as_str())?;
+        super::write_event(writer, EventType::ClosingElement, "heading-deg")?;
+    }
+
     super::write_event(writer, EventType::ClosingElement, "wp")?;
     Ok(())
 }
@@ -210,3 +1709,761 @@ fn simplify_name(name: &str) -> &str {
 
     split_name[is_split]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a Placemark of a .kml file, whose `geometry` is `Point` or `LineString`.
+    fn placemark(name: &str, style: &str, geometry: &str, coordinates: &str) -> String {
+        format!(
+            "<Placemark><name>{name}</name><styleUrl>{style}</styleUrl>\
+            <{geometry}><coordinates>{coordinates}</coordinates></{geometry}></Placemark>"
+        )
+    }
+
+    /// Builds a fix of a .kml file, like SimBrief writes them.
+    fn fix(name: &str, coordinates: &str) -> String {
+        placemark(name, "#FixMark", "Point", coordinates)
+    }
+
+    fn kml(placemarks: &[String]) -> String {
+        format!(
+            "<?xml version=\"1.0\"?><kml><Document>{}</Document></kml>",
+            placemarks.concat()
+        )
+    }
+
+    fn read(kml: &str, read_paths: bool) -> Vec<Waypoint> {
+        read_route(EventReader::new(kml.as_bytes()), &None, &None, read_paths)
+    }
+
+    #[test]
+    fn reads_tuples_with_spaces_after_the_commas() {
+        let route = read(&kml(&[fix("AAA", "-58.0, -34.7, 500")]), false);
+
+        assert_eq!(route.len(), 1);
+        assert_eq!(
+            route[0].coordinates(),
+            Coordinates {
+                lon: -58.0,
+                lat: -34.7
+            }
+        );
+        assert!((route[0].altitude - 1640.42).abs() < 0.01);
+    }
+
+    #[test]
+    fn reads_paths_with_spaces_after_the_commas() {
+        let path = "-58.0, -34.7, 500 -57.0, -34.0, 600\n-56.0,-33.0,700";
+        let route = read(
+            &kml(&[placemark("Track", "#PathMark", "LineString", path)]),
+            true,
+        );
+        let points: Vec<Coordinates> = route.iter().map(|w| w.coordinates()).collect();
+
+        assert_eq!(
+            points,
+            [
+                Coordinates {
+                    lon: -58.0,
+                    lat: -34.7
+                },
+                Coordinates {
+                    lon: -57.0,
+                    lat: -34.0
+                },
+                Coordinates {
+                    lon: -56.0,
+                    lat: -33.0
+                },
+            ]
+        );
+    }
+
+    /// Builds a fix with an `altitudeMode`.
+    fn fix_with_mode(name: &str, mode: &str, coordinates: &str) -> String {
+        format!(
+            "<Placemark><name>{name}</name><styleUrl>#FixMark</styleUrl>\
+            <Point><altitudeMode>{mode}</altitudeMode><coordinates>{coordinates}</coordinates></Point></Placemark>"
+        )
+    }
+
+    #[test]
+    fn reads_tuples_without_altitude() {
+        let route = read(
+            &kml(&[fix("AAA", "-58.0,-34.7"), fix("BBB", "-57.0,-34.0,")]),
+            false,
+        );
+
+        assert_eq!(route.len(), 2);
+        assert_eq!(
+            route[0].coordinates(),
+            Coordinates {
+                lon: -58.0,
+                lat: -34.7
+            }
+        );
+        assert_eq!(route[0].altitude, 0.0);
+        assert_eq!(route[1].altitude, 0.0);
+    }
+
+    #[test]
+    fn reads_paths_without_altitude() {
+        let path = "-58.0,-34.7 -57.0,-34.0 -56.0,-33.0";
+        let route = read(
+            &kml(&[placemark("Track", "#PathMark", "LineString", path)]),
+            true,
+        );
+
+        assert_eq!(route.len(), 3);
+        assert!(route.iter().all(|w| w.altitude == 0.0));
+    }
+
+    #[test]
+    fn reads_each_altitude_mode() {
+        let cases = [
+            (
+                "absolute",
+                "-58.0,-34.7,500",
+                AltitudeMode::Absolute,
+                1640.42,
+            ),
+            (
+                "relativeToGround",
+                "-58.0,-34.7,300",
+                AltitudeMode::RelativeToGround,
+                984.25,
+            ),
+            (
+                "relativeToGround",
+                "-58.0,-34.7",
+                AltitudeMode::RelativeToGround,
+                0.0,
+            ),
+            (
+                "clampToGround",
+                "-58.0,-34.7",
+                AltitudeMode::ClampToGround,
+                0.0,
+            ),
+            (
+                "clampToGround",
+                "-58.0,-34.7,0",
+                AltitudeMode::ClampToGround,
+                0.0,
+            ),
+            (
+                "clampToSeaFloor",
+                "-58.0,-34.7",
+                AltitudeMode::ClampToGround,
+                0.0,
+            ),
+        ];
+
+        for (mode, coordinates, expected, altitude) in cases {
+            let mut route = read(&kml(&[fix_with_mode("AAA", mode, coordinates)]), false);
+            apply_altitude_modes(&mut route);
+
+            assert_eq!(route.len(), 1, "{mode} {coordinates}");
+            assert_eq!(route[0].altitude_mode, expected, "{mode} {coordinates}");
+            assert!(
+                (route[0].altitude - altitude).abs() < 0.01,
+                "{mode} {coordinates}: {}",
+                route[0].altitude
+            );
+        }
+    }
+
+    #[test]
+    fn reads_placemarks_without_altitude_mode_as_absolute() {
+        let mut route = read(&kml(&[fix("AAA", "-58.0,-34.7,500")]), false);
+        apply_altitude_modes(&mut route);
+
+        assert_eq!(route[0].altitude_mode, AltitudeMode::Absolute);
+    }
+
+    #[test]
+    fn sets_the_airway_on_the_fixes_in_the_middle_of_an_airway() {
+        let mut route = read(
+            &kml(&[
+                fix("ALPHA", "-58,-34,3000"),
+                fix("BRAVO", "-57,-33,3000"),
+                fix("CHARL", "-56,-32,3000"),
+                fix("DELTA", "-55,-31,3000"),
+            ]),
+            false,
+        );
+        let route_string = Some(String::from("ALPHA UA1 CHARL DCT DELTA"));
+        airways::apply_airways(&mut route, &route_string, None, None).unwrap();
+
+        let airways: Vec<Option<&str>> = route.iter().map(|w| w.airway.as_deref()).collect();
+        assert_eq!(airways, [None, Some("UA1"), Some("UA1"), None]);
+
+        let collapsed = airways::collapse_airways(route);
+        let idents: Vec<&str> = collapsed.iter().map(|w| w.ident.as_str()).collect();
+        assert_eq!(idents, ["ALPHA", "CHARL", "DELTA"]);
+        assert_eq!(collapsed[1].kind, WaypointType::Via);
+    }
+
+    #[test]
+    fn writes_the_restrictions_of_via_waypoints() {
+        let mut waypoint = Waypoint::new();
+        waypoint.ident = String::from("CHARL");
+        waypoint.kind = WaypointType::Via;
+        waypoint.airway = Some(String::from("UA1"));
+        waypoint.altitude = 24000.0;
+        waypoint.alt_restrict = AltRestrict::At;
+        waypoint.speed_restrict = Some(SpeedRestrict::At(280));
+
+        let write = |transition| {
+            let mut output = Vec::new();
+            let mut writer = crate::EmitterConfig::new().create_writer(&mut output);
+            write_waypoint(&mut writer, &waypoint, 1, transition).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        let fgfp = write(None);
+        assert!(fgfp.contains("<type type=\"string\">via</type>"), "{fgfp}");
+        assert!(
+            fgfp.contains("<alt-restrict type=\"string\">at</alt-restrict>"),
+            "{fgfp}"
+        );
+        assert!(
+            fgfp.contains("<altitude-ft type=\"double\">24000</altitude-ft>"),
+            "{fgfp}"
+        );
+        assert!(
+            fgfp.contains("<speed-restrict type=\"string\">at</speed-restrict>"),
+            "{fgfp}"
+        );
+        assert!(fgfp.contains("<speed type=\"int\">280</speed>"), "{fgfp}");
+
+        let fgfp = write(Some(18000.0));
+        assert!(
+            fgfp.contains("<flight-level type=\"int\">240</flight-level>"),
+            "{fgfp}"
+        );
+    }
+
+    #[test]
+    fn parses_holds() {
+        let hold = |s: &str| s.parse::<Hold>();
+
+        assert_eq!(
+            hold("270/R/1MIN"),
+            Ok(Hold {
+                inbound_course: 270.0,
+                right_turns: true,
+                leg: HoldLeg::Minutes(1.0)
+            })
+        );
+        assert_eq!(
+            hold(" 090 / l / 4.5nm "),
+            Ok(Hold {
+                inbound_course: 90.0,
+                right_turns: false,
+                leg: HoldLeg::NauticalMiles(4.5)
+            })
+        );
+        // Right turns and one minute legs are the standard.
+        assert_eq!(
+            hold("0"),
+            Ok(Hold {
+                inbound_course: 0.0,
+                right_turns: true,
+                leg: HoldLeg::Minutes(1.0)
+            })
+        );
+        assert_eq!(hold("180/L/2").unwrap().leg, HoldLeg::Minutes(2.0));
+    }
+
+    #[test]
+    fn rejects_invalid_holds() {
+        let cases = [
+            ("360/R/1MIN", "Invalid inbound course in hold `360/R/1MIN`"),
+            ("-10/R/1MIN", "Invalid inbound course in hold `-10/R/1MIN`"),
+            ("EAST/R", "Invalid inbound course in hold `EAST/R`"),
+            (
+                "270/X/1MIN",
+                "Invalid turn direction in hold `270/X/1MIN`, use R or L",
+            ),
+            ("270/R/0MIN", "Invalid leg length in hold `270/R/0MIN`"),
+            ("270/R/-4NM", "Invalid leg length in hold `270/R/-4NM`"),
+            ("270/R/infNM", "Invalid leg length in hold `270/R/infNM`"),
+            ("270/R/LONG", "Invalid leg length in hold `270/R/LONG`"),
+        ];
+
+        for (hold, error) in cases {
+            assert_eq!(hold.parse::<Hold>(), Err(String::from(error)));
+        }
+    }
+
+    #[test]
+    fn writes_the_details_of_holds() {
+        let write = |hold: &str| {
+            let mut waypoint = Waypoint::new();
+            waypoint.ident = String::from("GESOL");
+            waypoint.kind = WaypointType::Hold(hold.parse().unwrap());
+
+            let mut output = Vec::new();
+            let mut writer = crate::EmitterConfig::new().create_writer(&mut output);
+            write_waypoint(&mut writer, &waypoint, 1, None).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        let fgfp = write("270/R/1.5MIN");
+        for element in [
+            "<type type=\"string\">hold</type>",
+            "<hold-radial type=\"double\">270.0</hold-radial>",
+            "<hold-righthanded type=\"bool\">true</hold-righthanded>",
+            "<hold-distance type=\"bool\">false</hold-distance>",
+            // The time of the legs is in seconds.
+            "<hold-td type=\"double\">90.0</hold-td>",
+        ] {
+            assert!(fgfp.contains(element), "{element} in {fgfp}");
+        }
+
+        let fgfp = write("095.5/L/4NM");
+        for element in [
+            "<hold-radial type=\"double\">95.5</hold-radial>",
+            "<hold-righthanded type=\"bool\">false</hold-righthanded>",
+            "<hold-distance type=\"bool\">true</hold-distance>",
+            "<hold-td type=\"double\">4.0</hold-td>",
+        ] {
+            assert!(fgfp.contains(element), "{element} in {fgfp}");
+        }
+    }
+
+    #[test]
+    fn writes_flight_levels_above_the_transition_altitude() {
+        let write = |altitude, transition| {
+            let mut waypoint = Waypoint::new();
+            waypoint.ident = String::from("GESOL");
+            waypoint.altitude = altitude;
+
+            let mut output = Vec::new();
+            let mut writer = crate::EmitterConfig::new().create_writer(&mut output);
+            write_restrictions(&mut writer, &waypoint, transition).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        let feet = |altitude| format!("<altitude-ft type=\"double\">{altitude}</altitude-ft>");
+        let level = |level| format!("<flight-level type=\"int\">{level}</flight-level>");
+
+        assert!(write(3500.0, Some(3000.0)).contains(&level(35)));
+        assert!(write(35000.0, Some(18000.0)).contains(&level(350)));
+        assert!(!write(35000.0, Some(18000.0)).contains("altitude-ft"));
+        // At the transition altitude, it's still an altitude.
+        assert!(write(18000.0, Some(18000.0)).contains(&feet(18000)));
+        assert!(write(2500.0, Some(3000.0)).contains(&feet(2500)));
+        assert!(write(35000.0, None).contains(&feet(35000)));
+    }
+
+    #[test]
+    fn uses_the_transition_altitude_of_the_departure_country() {
+        let kml = kml(&[fix("AAA", "-58,-34,3000"), fix("BBB", "-57,-33,3000")]);
+        let transition = |departure: &str, destination: &str, options: &RouteOptions| {
+            let mut output = Vec::new();
+            let mut writer = crate::EmitterConfig::new().create_writer(&mut output);
+            transform_route(
+                EventReader::new(kml.as_bytes()),
+                &mut writer,
+                &Some(departure.parse().unwrap()),
+                &Some(destination.parse().unwrap()),
+                options,
+            )
+            .unwrap()
+            .transition_altitude
+        };
+        let default = RouteOptions::default();
+
+        assert_eq!(transition("SAEZ", "KJFK", &default), Some(3000.0));
+        // The destination's, when the departure's country isn't known.
+        assert_eq!(transition("SUMU", "KJFK", &default), Some(18000.0));
+        assert_eq!(transition("SUMU", "LEMD", &default), None);
+
+        let options = RouteOptions {
+            transition_altitude: Some(6000.0),
+            ..Default::default()
+        };
+        assert_eq!(transition("SAEZ", "KJFK", &options), Some(6000.0));
+    }
+
+    #[test]
+    fn infers_restrictions_with_the_cruise_altitude_within_a_foot() {
+        let mut route = read(
+            &kml(&[
+                fix("AAA", "-58,-34,1000"),
+                fix("BBB", "-57,-33,3000"),
+                fix("CCC", "-56,-32,3000"),
+                fix("DDD", "-55,-31,1000"),
+            ]),
+            false,
+        );
+        route[1].altitude = 9999.7;
+        route[2].altitude = 10000.0;
+        infer_alt_restrictions(&mut route, None, Some(10000.0));
+
+        let restrictions: Vec<AltRestrict> = route.iter().map(|w| w.alt_restrict).collect();
+        assert_eq!(
+            restrictions,
+            [
+                AltRestrict::Above,
+                AltRestrict::At,
+                AltRestrict::At,
+                AltRestrict::Below
+            ]
+        );
+    }
+
+    /// The profile `AAA` 1000 ft, `BBB` and `CCC` 3000 ft, `DDD` 1000 ft, with absolute altitudes.
+    fn climb_and_descent() -> Vec<Waypoint> {
+        let mut route = read(
+            &kml(&[
+                fix("AAA", "-58,-34,1000"),
+                fix("BBB", "-57,-33,3000"),
+                fix("CCC", "-56,-32,3000"),
+                fix("DDD", "-55,-31,1000"),
+            ]),
+            false,
+        );
+        apply_altitude_modes(&mut route);
+
+        route
+    }
+
+    fn restrictions(route: &[Waypoint]) -> Vec<AltRestrict> {
+        route.iter().map(|w| w.alt_restrict).collect()
+    }
+
+    #[test]
+    fn forces_the_altitude_restriction() {
+        let mut route = climb_and_descent();
+        infer_alt_restrictions(&mut route, Some(AltRestrict::Below), None);
+
+        assert_eq!(restrictions(&route), [AltRestrict::Below; 4]);
+    }
+
+    #[test]
+    fn gives_priority_to_the_restrictions_of_the_extended_data() {
+        let mut route = climb_and_descent();
+        route[0]
+            .data
+            .push((String::from("alt-restrict"), String::from("at")));
+        route[2]
+            .data
+            .push((String::from("alt-restrict"), String::from("none")));
+        route[3]
+            .data
+            .push((String::from("alt-restrict"), String::from("sideways")));
+
+        infer_alt_restrictions(&mut route, None, None);
+        assert_eq!(
+            restrictions(&route),
+            [
+                AltRestrict::At,
+                AltRestrict::At,
+                AltRestrict::None,
+                // The invalid restriction is ignored.
+                AltRestrict::Below
+            ]
+        );
+
+        // Even over a forced restriction.
+        infer_alt_restrictions(&mut route, Some(AltRestrict::Above), None);
+        assert_eq!(
+            restrictions(&route),
+            [
+                AltRestrict::At,
+                AltRestrict::Above,
+                AltRestrict::None,
+                AltRestrict::Above
+            ]
+        );
+    }
+
+    #[test]
+    fn doesnt_restrict_altitudes_that_arent_absolute() {
+        let mut route = climb_and_descent();
+        route[1].altitude_mode = AltitudeMode::RelativeToGround;
+        route[2].altitude_mode = AltitudeMode::ClampToGround;
+        route[2]
+            .data
+            .push((String::from("alt-restrict"), String::from("at")));
+
+        infer_alt_restrictions(&mut route, None, None);
+        assert_eq!(
+            restrictions(&route),
+            [
+                AltRestrict::At,
+                AltRestrict::None,
+                AltRestrict::None,
+                AltRestrict::At
+            ]
+        );
+
+        infer_alt_restrictions(&mut route, Some(AltRestrict::At), None);
+        assert_eq!(route[1].alt_restrict, AltRestrict::None);
+    }
+
+    /// A route with a `via`, a hold, a discontinuity and restrictions.
+    fn route_with_every_feature() -> Vec<Waypoint> {
+        let mut route = read(
+            &kml(&[
+                fix("AAA", "-58,-34,1000"),
+                fix("BBB", "-57,-33,3000"),
+                fix("CCC", "-56,-32,3000"),
+                fix("DDD", "-55,-31,1000"),
+            ]),
+            false,
+        );
+        route[0].alt_restrict = AltRestrict::Above;
+        route[0].speed_restrict = Some(SpeedRestrict::Below(250));
+        route[1].kind = WaypointType::Via;
+        route[1].airway = Some(String::from("UA1"));
+        route[2].kind = WaypointType::Hold("270/R/1MIN".parse().unwrap());
+        route[2].alt_restrict = AltRestrict::At;
+        let mut discontinuity = Waypoint::new();
+        discontinuity.kind = WaypointType::Discontinuity;
+        route.insert(3, discontinuity);
+
+        route
+    }
+
+    /// The parts of the waypoints that the targets adapt.
+    fn features(route: &[Waypoint]) -> Vec<(WaypointType, AltRestrict, Option<SpeedRestrict>)> {
+        route
+            .iter()
+            .map(|w| (w.kind.clone(), w.alt_restrict, w.speed_restrict))
+            .collect()
+    }
+
+    #[test]
+    fn adapts_the_route_to_each_target() {
+        let is_hold = |w: &Waypoint| matches!(w.kind, WaypointType::Hold(_));
+        let is_discontinuity = |w: &Waypoint| w.kind == WaypointType::Discontinuity;
+
+        for target in [FgTarget::V2020_3, FgTarget::V2024_1] {
+            let mut route = route_with_every_feature();
+            adapt_to_target(&mut route, target);
+
+            assert_eq!(
+                features(&route),
+                features(&route_with_every_feature()),
+                "{target}"
+            );
+        }
+
+        // 2018.3 has no discontinuities.
+        let mut route = route_with_every_feature();
+        adapt_to_target(&mut route, FgTarget::V2018_3);
+        assert_eq!(route.len(), 4);
+        assert!(!route.iter().any(is_discontinuity));
+        assert_eq!(route[1].kind, WaypointType::Via);
+        assert!(is_hold(&route[2]));
+        assert_eq!(route[0].alt_restrict, AltRestrict::Above);
+        assert_eq!(route[0].speed_restrict, Some(SpeedRestrict::Below(250)));
+
+        // The legacy format only has basic waypoints, at their altitudes.
+        let mut route = route_with_every_feature();
+        adapt_to_target(&mut route, FgTarget::Legacy);
+        assert_eq!(route.len(), 4);
+        assert!(route.iter().all(|w| w.kind == WaypointType::Basic));
+        assert_eq!(route[1].airway.as_deref(), Some("UA1"));
+        assert_eq!(route[0].alt_restrict, AltRestrict::At);
+        assert_eq!(route[2].alt_restrict, AltRestrict::At);
+        assert!(route.iter().all(|w| w.speed_restrict.is_none()));
+    }
+
+    #[test]
+    fn parses_speed_restrictions() {
+        let cases = [
+            ("250", SpeedRestrict::At(250)),
+            ("250kt", SpeedRestrict::At(250)),
+            ("at 280 kt", SpeedRestrict::At(280)),
+            ("Below 250", SpeedRestrict::Below(250)),
+            ("above  210", SpeedRestrict::Above(210)),
+            ("M0.78", SpeedRestrict::Mach(0.78)),
+            ("at m.82", SpeedRestrict::Mach(0.82)),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(text.parse::<SpeedRestrict>(), Ok(expected), "{text}");
+        }
+
+        for text in ["", "fast", "below M0.78", "around 250", "-250", "M"] {
+            assert!(text.parse::<SpeedRestrict>().is_err(), "{text}");
+        }
+    }
+
+    /// Builds a fix with the `speed` field in its `ExtendedData`.
+    fn fix_with_speed(name: &str, speed: &str, coordinates: &str) -> String {
+        format!(
+            "<Placemark><name>{name}</name><styleUrl>#FixMark</styleUrl>\
+            <ExtendedData><Data name=\"speed\"><value>{speed}</value></Data></ExtendedData>\
+            <Point><coordinates>{coordinates}</coordinates></Point></Placemark>"
+        )
+    }
+
+    #[test]
+    fn gives_priority_to_the_restrictions_of_each_waypoint() {
+        let mut route = read(
+            &kml(&[
+                fix("AAA", "-58,-34,1000"),
+                fix_with_speed("BBB", "below 220", "-57,-33,1000"),
+                fix_with_speed("CCC", "below 220", "-56,-32,1000"),
+                fix("DDD", "-55,-31,1000"),
+            ]),
+            false,
+        );
+        let options = RouteOptions {
+            speed_limit: Some((SpeedRestrict::Below(250), 10000.0)),
+            speed_rules: vec![
+                (String::from("CCC"), SpeedRestrict::At(200)),
+                (String::from("DDD"), SpeedRestrict::Mach(0.5)),
+            ],
+            ..Default::default()
+        };
+        apply_speed_restrictions(&mut route, &options);
+
+        // The rules come first, then the ExtendedData, then the limit.
+        let speeds: Vec<Option<SpeedRestrict>> = route.iter().map(|w| w.speed_restrict).collect();
+        assert_eq!(
+            speeds,
+            [
+                Some(SpeedRestrict::Below(250)),
+                Some(SpeedRestrict::Below(220)),
+                Some(SpeedRestrict::At(200)),
+                Some(SpeedRestrict::Mach(0.5)),
+            ]
+        );
+    }
+
+    #[test]
+    fn limits_the_speed_below_fl100() {
+        let mut route = read(
+            &kml(&[
+                fix("AAA", "-58,-34,0"),
+                fix("BBB", "-57,-33,0"),
+                fix("CCC", "-56,-32,0"),
+                fix_with_mode("DDD", "relativeToGround", "-55,-31,0"),
+            ]),
+            false,
+        );
+        apply_altitude_modes(&mut route);
+        route[0].altitude = 9999.0;
+        route[1].altitude = 10000.0;
+        route[2].altitude = 24000.0;
+
+        let options = RouteOptions {
+            speed_limit: Some((SpeedRestrict::Below(250), 10000.0)),
+            ..Default::default()
+        };
+        apply_speed_restrictions(&mut route, &options);
+
+        // Altitudes relative to the ground aren't known to be below the limit.
+        let speeds: Vec<Option<SpeedRestrict>> = route.iter().map(|w| w.speed_restrict).collect();
+        assert_eq!(speeds, [Some(SpeedRestrict::Below(250)), None, None, None]);
+    }
+
+    #[test]
+    fn takes_only_the_airports_at_the_ends_of_the_route() {
+        let mut route = read(
+            &kml(&[
+                placemark(
+                    "SAEZ Ministro Pistarini",
+                    "#AirportMark",
+                    "Point",
+                    "-58.5,-34.8,20",
+                ),
+                fix("ABCD", "-58.0,-34.6,3000"),
+                placemark("EFGH", "#OtherMark", "Point", "-57.0,-34.4,3000"),
+                fix("WXYZ", "-56.5,-34.6,3000"),
+                placemark("SUMU", "#AirportMark", "Point", "-56.0,-34.8,30"),
+            ]),
+            false,
+        );
+        let (departure, destination) = take_airports(&mut route);
+
+        assert_eq!(departure.unwrap().ident, "SAEZ");
+        assert_eq!(destination.unwrap().ident, "SUMU");
+
+        // A 4 letter fix is a waypoint, and styled like an airport it's dropped like the other
+        // Placemarks without the #FixMark style.
+        let idents: Vec<&str> = route.iter().map(|w| w.ident.as_str()).collect();
+        assert_eq!(idents, ["ABCD", "WXYZ"]);
+    }
+
+    #[test]
+    fn doesnt_take_4_letter_fixes_before_the_destination_as_airports() {
+        let mut route = read(
+            &kml(&[
+                fix("ABCD", "-58.0,-34.6,3000"),
+                placemark("EFGH", "#OtherMark", "Point", "-57.0,-34.4,3000"),
+                fix("WXYZ", "-56.5,-34.6,3000"),
+            ]),
+            false,
+        );
+
+        assert_eq!(take_airports(&mut route), (None, None));
+        assert_eq!(route.len(), 2);
+    }
+
+    #[test]
+    fn reads_paths_named_like_airports() {
+        let path = "-58.0,-34.7,500 -57.0,-34.0,600";
+        let track = placemark("TRAK", "#PathMark", "LineString", path);
+
+        let mut route = read(&kml(&[fix("ABCD", "-58.5,-34.8,300"), track.clone()]), true);
+        assert_eq!(take_airports(&mut route), (None, None));
+        let idents: Vec<&str> = route.iter().map(|w| w.ident.as_str()).collect();
+        assert_eq!(idents, ["ABCD", "TRAK001", "TRAK002"]);
+
+        let mut route = read(&kml(&[fix("ABCD", "-58.5,-34.8,300"), track]), false);
+        assert_eq!(take_airports(&mut route), (None, None));
+        assert_eq!(route.len(), 1);
+    }
+
+    #[test]
+    fn only_sets_the_runway_of_the_airports_of_the_kml() {
+        let found = Airport {
+            ident: String::from("SAEZ"),
+            coordinates: Some(Coordinates {
+                lon: -58.5,
+                lat: -34.8,
+            }),
+            ..Default::default()
+        };
+        let mut given: Airport = "eze/11/ando1a".parse().unwrap();
+        given.coordinates = Some(Coordinates {
+            lon: -58.54,
+            lat: -34.82,
+        });
+
+        let merged = merge_airport(&Some(given), Some(found.clone()), "departure")
+            .unwrap()
+            .unwrap();
+        assert_eq!(merged.ident, "SAEZ");
+        assert_eq!(merged.coordinates, found.coordinates);
+        assert_eq!(merged.runway.as_deref(), Some("11"));
+        assert_eq!(merged.procedure.as_deref(), Some("ANDO1A"));
+
+        let other: Airport = "SABE/13".parse().unwrap();
+        assert_eq!(
+            merge_airport(&Some(other.clone()), Some(found.clone()), "departure"),
+            Err(String::from(
+                "The departure airport of the .kml file is SAEZ, not SABE"
+            ))
+        );
+
+        // Without an airport in the .kml file, the given one is used.
+        assert_eq!(
+            merge_airport(&Some(other.clone()), None, "departure"),
+            Ok(Some(other))
+        );
+        assert_eq!(
+            merge_airport(&None, Some(found.clone()), "departure"),
+            Ok(Some(found))
+        );
+    }
+}
@@ -0,0 +1,400 @@
+use super::{geodesy, user, Waypoint, WaypointType};
+use crate::navdata::{AirwayDatabase, AirwayLevel, UserWaypoints};
+
+/// Airways flown at or above this altitude in feet are looked up as high airways.
+const HIGH_AIRWAYS_FT: f64 = 18000.0;
+
+/// Internal function that sets the airway used to reach each waypoint. The airways come from the
+/// `airway` field of the Placemarks' `ExtendedData` and, if given, from a route string like
+/// `SAEZ DCT EZE11 UM540 ARSOT UW10 ESL`, which takes priority.
+///
+/// With an airway database, the fixes along the airways of the route string are inserted between
+/// the entry and exit fixes, and the exit fixes don't need to be in the .kml file. Fixes of the
+/// user's `library` don't need to be in the .kml file either, they're inserted where the route
+/// string has them.
+///
+/// # Errors
+/// This function fails if an airway of the route string can't be flown from its entry to its exit.
+pub fn apply_airways(
+    route: &mut Vec<Waypoint>,
+    route_string: &Option<String>,
+    airways: Option<&AirwayDatabase>,
+    library: Option<&UserWaypoints>,
+) -> Result<(), String> {
+    for waypoint in route.iter_mut() {
+        if let Some(airway) = waypoint.data("airway") {
+            waypoint.airway = Some(String::from(airway));
+        }
+    }
+
+    let route_string = match route_string {
+        Some(route_string) => route_string,
+        None => return Ok(()),
+    };
+
+    // Walk the route along with the route string, so repeated idents are matched in order.
+    let mut cursor = 0;
+    let mut entry = None;
+    for (exit, airway) in decode_route_string(route_string, route, airways, library) {
+        let found = route[cursor..]
+            .iter()
+            .position(|w| w.ident.eq_ignore_ascii_case(&exit))
+            .map(|i| cursor + i);
+
+        entry = match (airways, airway, entry) {
+            (Some(database), Some(airway), Some(entry)) => Some(expand_airway(
+                route, database, &airway, entry, found, &exit,
+            )?),
+            (_, airway, entry) => match found {
+                Some(i) => {
+                    // The waypoints of the .kml between the entry and the exit are along the
+                    // airway too, so they're collapsed with it.
+                    if entry.is_some() {
+                        if airway.is_some() {
+                            for waypoint in &mut route[cursor..i] {
+                                waypoint.airway = airway.clone();
+                            }
+                        }
+
+                        route[i].airway = airway;
+                    }
+                    Some(i)
+                }
+                None => match library.and_then(|l| l.get(&exit)) {
+                    // Fixes of the user's library are inserted after the entry.
+                    Some(fix) => {
+                        let altitude = route
+                            .get(cursor.saturating_sub(1))
+                            .map_or(0.0, |w| w.altitude);
+
+                        let mut waypoint = user::to_waypoint(fix, altitude);
+                        waypoint.airway = airway;
+                        route.insert(cursor, waypoint);
+                        Some(cursor)
+                    }
+                    None => {
+                        eprintln!(
+                            "\x1B[01;33mIgnoring\x1B[00;01m {exit}\x1B[00m from the route string: not in the route"
+                        );
+                        None
+                    }
+                },
+            },
+        };
+
+        if let Some(i) = entry {
+            cursor = i + 1;
+        }
+    }
+
+    Ok(())
+}
+
+/// Internal function that replaces the waypoints between the `entry` and the `exit` with the fixes
+/// along the `airway`. Waypoints of the .kml along the airway are kept, the rest are dropped with
+/// a warning. When the `exit` isn't in the route, it's inserted after the other fixes.
+///
+/// Returns the position of the exit in the route.
+fn expand_airway(
+    route: &mut Vec<Waypoint>,
+    database: &AirwayDatabase,
+    airway: &str,
+    entry: usize,
+    exit: Option<usize>,
+    exit_ident: &str,
+) -> Result<usize, String> {
+    let from = &route[entry];
+    let highest = exit.map_or(from.altitude, |i| from.altitude.max(route[i].altitude));
+    let level = match highest >= HIGH_AIRWAYS_FT {
+        true => AirwayLevel::High,
+        false => AirwayLevel::Low,
+    };
+
+    let fixes = database.expand(airway, &from.ident, from.coordinates(), exit_ident, level)?;
+    let (from_position, from_altitude, altitude_mode) =
+        (from.coordinates(), from.altitude, from.altitude_mode);
+    let (to_position, to_altitude) = match exit {
+        Some(i) => (route[i].coordinates(), route[i].altitude),
+        None => (fixes[fixes.len() - 1].coordinates, from_altitude),
+    };
+
+    // The exit, when it's in the route, stays where it is.
+    let mut between: Vec<Waypoint> = route.drain(entry + 1..exit.unwrap_or(entry + 1)).collect();
+    let along = match exit {
+        Some(_) => &fixes[..fixes.len() - 1],
+        None => &fixes[..],
+    };
+
+    let mut expanded = Vec::with_capacity(along.len());
+    for fix in along {
+        let mut waypoint = match between
+            .iter()
+            .position(|w| w.ident.eq_ignore_ascii_case(&fix.ident))
+        {
+            Some(i) => between.remove(i),
+            None => {
+                let before = geodesy::distance_nm(from_position, fix.coordinates);
+                let after = geodesy::distance_nm(fix.coordinates, to_position);
+                let fraction = match before + after {
+                    total if total > 0.0 => before / total,
+                    _ => 0.0,
+                };
+
+                let mut waypoint = Waypoint::new();
+                waypoint.ident = fix.ident.clone();
+                waypoint.lon = fix.coordinates.lon;
+                waypoint.lat = fix.coordinates.lat;
+                waypoint.altitude = from_altitude + (to_altitude - from_altitude) * fraction;
+                waypoint.altitude_mode = altitude_mode;
+                waypoint.generated = true;
+                waypoint.navaid = true;
+                waypoint
+            }
+        };
+
+        waypoint.airway = Some(airway.to_uppercase());
+        expanded.push(waypoint);
+    }
+
+    for waypoint in between {
+        eprintln!(
+            "\x1B[01;33mDropping\x1B[00;01m {}\x1B[00m waypoint: it isn't on the airway {}",
+            waypoint.ident, airway
+        );
+    }
+
+    let inserted = expanded.len();
+    route.splice(entry + 1..entry + 1, expanded);
+
+    match exit {
+        Some(_) => {
+            route[entry + inserted + 1].airway = Some(airway.to_uppercase());
+            Ok(entry + inserted + 1)
+        }
+        None => Ok(entry + inserted),
+    }
+}
+
+/// Internal function that splits a route string into the fixes of the route, each with the airway
+/// used to reach it. A token is a fix when it's the ident of a waypoint in the route, `DCT` means
+/// a direct leg, and anything else between two fixes is an airway.
+///
+/// With an airway database, the token after an airway is always its exit fix. The fixes of the
+/// user's `library` are fixes even when they aren't in the route.
+fn decode_route_string(
+    route_string: &str,
+    route: &[Waypoint],
+    airways: Option<&AirwayDatabase>,
+    library: Option<&UserWaypoints>,
+) -> Vec<(String, Option<String>)> {
+    let is_fix = |token: &str| {
+        route.iter().any(|w| w.ident.eq_ignore_ascii_case(token))
+            || library.is_some_and(|l| l.get(token).is_some())
+    };
+
+    let mut fixes = Vec::new();
+    let mut previous_is_fix = false;
+    let mut airway = None;
+
+    for token in route_string.split_whitespace() {
+        let token = token.to_uppercase();
+        let is_exit = airways.is_some() && airway.is_some() && !previous_is_fix;
+
+        if is_fix(&token) || is_exit {
+            fixes.push((token, airway.take()));
+            previous_is_fix = true;
+        } else {
+            airway = match token.as_str() {
+                "DCT" => None,
+                _ if previous_is_fix => Some(token),
+                _ => None,
+            };
+            previous_is_fix = false;
+        }
+    }
+
+    fixes
+}
+
+/// Internal function that replaces every run of waypoints along the same airway with a single
+/// [`WaypointType::Via`] waypoint at the exit fix. Only basic waypoints are collapsed, so holds
+/// and discontinuities are kept.
+pub fn collapse_airways(route: Vec<Waypoint>) -> Vec<Waypoint> {
+    let mut collapsed: Vec<Waypoint> = Vec::with_capacity(route.len());
+
+    for mut waypoint in route {
+        if waypoint.airway.is_none() || waypoint.kind != WaypointType::Basic {
+            collapsed.push(waypoint);
+            continue;
+        }
+
+        waypoint.kind = WaypointType::Via;
+
+        // The previous waypoint is on the same airway, so this one is a later exit fix.
+        match collapsed.last_mut() {
+            Some(last) if last.kind == WaypointType::Via && last.airway == waypoint.airway => {
+                *last = waypoint;
+            }
+            _ => collapsed.push(waypoint),
+        }
+    }
+
+    collapsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::navdata::FixDatabase;
+
+    const AWY_DAT: &str = "I\n640 Version\n\
+        EZE11 -34.80 -58.30 GESOL -34.60 -57.50 2 245 600 UM540\n\
+        GESOL -34.60 -57.50 ARSOT -34.40 -56.70 2 245 600 UM540\n\
+        99\n";
+
+    fn database() -> AirwayDatabase {
+        let mut database = AirwayDatabase::default();
+        database
+            .read_awy_dat(AWY_DAT.as_bytes(), &FixDatabase::default())
+            .unwrap();
+        database
+    }
+
+    fn waypoint(ident: &str, lon: f64, lat: f64, altitude: f64) -> Waypoint {
+        let mut waypoint = Waypoint::new();
+        waypoint.ident = String::from(ident);
+        waypoint.lon = lon;
+        waypoint.lat = lat;
+        waypoint.altitude = altitude;
+        waypoint
+    }
+
+    fn idents(route: &[Waypoint]) -> Vec<&str> {
+        route.iter().map(|w| w.ident.as_str()).collect()
+    }
+
+    fn apply(route: &mut Vec<Waypoint>, route_string: &str) -> Result<(), String> {
+        let route_string = Some(String::from(route_string));
+        apply_airways(route, &route_string, Some(&database()), None)
+    }
+
+    #[test]
+    fn inserts_the_fixes_along_the_airway() {
+        let mut route = vec![
+            waypoint("EZE11", -58.30, -34.80, 20000.0),
+            waypoint("ARSOT", -56.70, -34.40, 30000.0),
+        ];
+        apply(&mut route, "EZE11 UM540 ARSOT").unwrap();
+
+        assert_eq!(idents(&route), ["EZE11", "GESOL", "ARSOT"]);
+        assert!(route[1].generated && route[1].navaid);
+        assert!(route[1].altitude > 20000.0 && route[1].altitude < 30000.0);
+
+        let airways: Vec<Option<&str>> = route.iter().map(|w| w.airway.as_deref()).collect();
+        assert_eq!(airways, [None, Some("UM540"), Some("UM540")]);
+    }
+
+    #[test]
+    fn inserts_the_exit_when_it_isnt_in_the_route() {
+        let mut route = vec![
+            waypoint("EZE11", -58.30, -34.80, 30000.0),
+            waypoint("ESL", -55.0, -34.0, 30000.0),
+        ];
+        apply(&mut route, "eze11 um540 arsot dct esl").unwrap();
+
+        assert_eq!(idents(&route), ["EZE11", "GESOL", "ARSOT", "ESL"]);
+        assert_eq!(route[3].airway, None);
+    }
+
+    #[test]
+    fn flies_the_airway_backwards() {
+        let mut route = vec![
+            waypoint("ARSOT", -56.70, -34.40, 30000.0),
+            waypoint("GESOL", -57.50, -34.60, 30000.0),
+            waypoint("TANGO", -57.0, -34.0, 30000.0),
+            waypoint("EZE11", -58.30, -34.80, 30000.0),
+        ];
+        apply(&mut route, "ARSOT UM540 EZE11").unwrap();
+
+        // GESOL is kept, with what the .kml file had, while TANGO isn't along the airway.
+        assert_eq!(idents(&route), ["ARSOT", "GESOL", "EZE11"]);
+        assert!(!route[1].generated);
+    }
+
+    #[test]
+    fn matches_the_route_string_regardless_of_case() {
+        let mut route = vec![
+            waypoint("eze11", -58.30, -34.80, 30000.0),
+            waypoint("Arsot", -56.70, -34.40, 30000.0),
+        ];
+        apply(&mut route, "EZE11 UM540 ARSOT").unwrap();
+
+        assert_eq!(idents(&route), ["eze11", "GESOL", "Arsot"]);
+        assert_eq!(route[2].airway.as_deref(), Some("UM540"));
+    }
+
+    #[test]
+    fn fails_for_airways_that_cant_be_flown() {
+        let route = || {
+            vec![
+                waypoint("EZE11", -58.30, -34.80, 30000.0),
+                waypoint("TANGO", -57.0, -34.0, 30000.0),
+                waypoint("ARSOT", -56.70, -34.40, 30000.0),
+            ]
+        };
+
+        let cases = [
+            ("EZE11 UM999 ARSOT", "Unknown airway UM999"),
+            ("TANGO UM540 ARSOT", "TANGO isn't on the airway UM540"),
+            ("EZE11 UM540 ZULU", "ZULU isn't on the airway UM540"),
+        ];
+
+        for (route_string, error) in cases {
+            assert_eq!(apply(&mut route(), route_string), Err(String::from(error)));
+        }
+    }
+
+    #[test]
+    fn fails_for_one_way_airways_flown_backwards() {
+        let awy_dat = "I\n1100 Version\n\
+            GESOL SA 11 ARSOT SU 11 F 2 245 600 UW10\n\
+            99\n";
+        let fix_dat = "I\n1101 Version\n\
+            -34.60 -57.50 GESOL ENRT SA\n\
+            -34.40 -56.70 ARSOT ENRT SU\n\
+            99\n";
+
+        let mut fixes = FixDatabase::default();
+        fixes.read_fix_dat(fix_dat.as_bytes()).unwrap();
+        let mut database = AirwayDatabase::default();
+        database.read_awy_dat(awy_dat.as_bytes(), &fixes).unwrap();
+
+        let mut route = vec![
+            waypoint("ARSOT", -56.70, -34.40, 30000.0),
+            waypoint("GESOL", -57.50, -34.60, 30000.0),
+        ];
+        let route_string = Some(String::from("ARSOT UW10 GESOL"));
+
+        assert_eq!(
+            apply_airways(&mut route, &route_string, Some(&database), None),
+            Err(String::from(
+                "The airway UW10 is one way, it can't be flown from ARSOT to GESOL"
+            ))
+        );
+    }
+
+    #[test]
+    fn doesnt_set_the_airway_of_an_exit_without_entry() {
+        let mut route = vec![
+            waypoint("ALPHA", -58.0, -34.0, 3000.0),
+            waypoint("BRAVO", -57.0, -33.0, 3000.0),
+            waypoint("CHARL", -56.0, -32.0, 3000.0),
+            waypoint("DELTA", -55.0, -31.0, 3000.0),
+        ];
+        // BRAVO is already behind, so it's ignored and DELTA has no entry.
+        let route_string = Some(String::from("ALPHA CHARL BRAVO UA1 DELTA"));
+        apply_airways(&mut route, &route_string, None, None).unwrap();
+
+        assert!(route.iter().all(|w| w.airway.is_none()));
+    }
+}
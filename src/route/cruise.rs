@@ -0,0 +1,212 @@
+use super::{geodesy, AltitudeMode, Coordinates, StepAt, StepClimb, Waypoint, WaypointType};
+
+/// Internal function that overrides the en-route altitudes with the `cruise` altitude, and applies
+/// the `steps` climbs. Returns the cruise altitude the route starts the en-route part at.
+///
+/// The en-route part goes from the first to the last waypoint at the highest altitude of the .kml.
+/// Waypoints of the climb and the descent are kept, but never above the new cruise altitude. Only
+/// absolute altitudes are used, and without a `cruise` the highest one has to be above sea level.
+pub fn apply_cruise(
+    route: &mut [Waypoint],
+    cruise: Option<f64>,
+    steps: &[StepClimb],
+    origin: Option<Coordinates>,
+) -> Option<f64> {
+    let is_absolute = |w: &Waypoint| w.altitude_mode == AltitudeMode::Absolute;
+
+    let highest = route
+        .iter()
+        .filter(|w| is_absolute(w))
+        .map(|w| w.altitude)
+        .reduce(f64::max)?;
+
+    // Routes below sea level, or with only ground altitudes, have no cruise.
+    if highest <= 0.0 && cruise.is_none() {
+        eprintln!(
+            "\x1B[01;33mWarning\x1B[00m: no waypoint is above sea level, the route has no cruise altitude"
+        );
+        return None;
+    }
+    let first = route
+        .iter()
+        .position(|w| is_absolute(w) && w.is_at(highest))?;
+    let last = route
+        .iter()
+        .rposition(|w| is_absolute(w) && w.is_at(highest))?;
+
+    if let Some(cruise) = cruise {
+        for (i, waypoint) in route.iter_mut().enumerate() {
+            if !is_absolute(waypoint) {
+                continue;
+            }
+
+            waypoint.altitude = match i {
+                _ if (first..=last).contains(&i) => cruise,
+                _ => waypoint.altitude.min(cruise),
+            };
+        }
+    }
+
+    // Every step applies from where it starts to the end of the en-route part, so they're applied
+    // in the order they're flown.
+    let along = along_track(route, origin);
+    let mut starts = Vec::with_capacity(steps.len());
+
+    for step in steps {
+        let start = match &step.at {
+            StepAt::Waypoint(ident) => route[first..=last]
+                .iter()
+                .position(|w| w.ident == *ident)
+                .map(|i| first + i + 1),
+            StepAt::Distance(distance) => (first..=last).find(|i| along[*i] >= *distance),
+        };
+
+        match start {
+            Some(start) => starts.push((start, step.altitude)),
+            None => eprintln!(
+                "\x1B[01;33mIgnoring\x1B[00;01m {}\x1B[00m step climb: it isn't along the cruise",
+                step.at
+            ),
+        }
+    }
+    starts.sort_by_key(|(start, _)| *start);
+
+    for (start, altitude) in starts {
+        for waypoint in route.iter_mut().take(last + 1).skip(start) {
+            if is_absolute(waypoint) {
+                waypoint.altitude = altitude;
+            }
+        }
+    }
+
+    Some(cruise.unwrap_or(highest))
+}
+
+/// Internal function that calculates how far every waypoint is from the origin along the route, in
+/// nautical miles. The origin is the first waypoint when its coordinates aren't known.
+fn along_track(route: &[Waypoint], origin: Option<Coordinates>) -> Vec<f64> {
+    let mut along = Vec::with_capacity(route.len());
+    let mut previous = origin;
+    let mut total = 0.0;
+
+    for waypoint in route {
+        if waypoint.kind != WaypointType::Discontinuity {
+            let position = waypoint.coordinates();
+            if let Some(previous) = previous {
+                total += geodesy::distance_nm(previous, position);
+            }
+            previous = Some(position);
+        }
+
+        along.push(total);
+    }
+
+    along
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn waypoint(ident: &str, lon: f64, altitude: f64, altitude_mode: AltitudeMode) -> Waypoint {
+        let mut waypoint = Waypoint::new();
+        waypoint.ident = String::from(ident);
+        waypoint.lon = lon;
+        waypoint.altitude = altitude;
+        waypoint.altitude_mode = altitude_mode;
+        waypoint
+    }
+
+    fn route(altitudes: &[f64]) -> Vec<Waypoint> {
+        altitudes
+            .iter()
+            .enumerate()
+            .map(|(i, a)| waypoint(&format!("WP{i}"), i as f64, *a, AltitudeMode::Absolute))
+            .collect()
+    }
+
+    fn altitudes(route: &[Waypoint]) -> Vec<f64> {
+        route.iter().map(|w| w.altitude).collect()
+    }
+
+    #[test]
+    fn cruises_at_the_highest_altitude() {
+        let mut route = route(&[3000.0, 35000.0, 35000.0, 8000.0]);
+
+        assert_eq!(apply_cruise(&mut route, None, &[], None), Some(35000.0));
+        assert_eq!(altitudes(&route), [3000.0, 35000.0, 35000.0, 8000.0]);
+    }
+
+    #[test]
+    fn overrides_the_en_route_altitudes() {
+        let mut route = route(&[3000.0, 35000.0, 33000.0, 35000.0, 34000.0]);
+
+        assert_eq!(
+            apply_cruise(&mut route, Some(31000.0), &[], None),
+            Some(31000.0)
+        );
+        // The descent is kept below the new cruise altitude.
+        assert_eq!(
+            altitudes(&route),
+            [3000.0, 31000.0, 31000.0, 31000.0, 31000.0]
+        );
+    }
+
+    #[test]
+    fn ignores_altitudes_relative_to_the_ground() {
+        let mut route = route(&[3000.0, 12000.0, 4000.0]);
+        route.insert(
+            2,
+            waypoint("HILL", 1.5, 20000.0, AltitudeMode::RelativeToGround),
+        );
+        route.insert(
+            0,
+            waypoint("FIELD", -1.0, 25000.0, AltitudeMode::ClampToGround),
+        );
+
+        assert_eq!(
+            apply_cruise(&mut route, Some(10000.0), &[], None),
+            Some(10000.0)
+        );
+        assert_eq!(
+            altitudes(&route),
+            [25000.0, 3000.0, 10000.0, 20000.0, 4000.0]
+        );
+    }
+
+    #[test]
+    fn has_no_cruise_below_sea_level() {
+        let mut below = route(&[-1300.0, -1250.0]);
+        assert_eq!(apply_cruise(&mut below, None, &[], None), None);
+        assert_eq!(altitudes(&below), [-1300.0, -1250.0]);
+
+        let mut relative = vec![waypoint(
+            "HILL",
+            0.0,
+            2000.0,
+            AltitudeMode::RelativeToGround,
+        )];
+        assert_eq!(apply_cruise(&mut relative, None, &[], None), None);
+    }
+
+    #[test]
+    fn applies_step_climbs_in_order() {
+        let mut route = route(&[3000.0, 35000.0, 35000.0, 35000.0, 35000.0, 8000.0]);
+        let steps = [
+            StepClimb {
+                at: StepAt::Waypoint(String::from("WP3")),
+                altitude: 39000.0,
+            },
+            StepClimb {
+                at: StepAt::Waypoint(String::from("WP1")),
+                altitude: 37000.0,
+            },
+        ];
+
+        assert_eq!(apply_cruise(&mut route, None, &steps, None), Some(35000.0));
+        assert_eq!(
+            altitudes(&route),
+            [3000.0, 35000.0, 37000.0, 37000.0, 39000.0, 8000.0]
+        );
+    }
+}
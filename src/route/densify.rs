@@ -0,0 +1,168 @@
+use super::{geodesy, Densify, Waypoint, WaypointType};
+
+/// Internal function that inserts basic waypoints along the great circle of the direct legs that
+/// are longer than `densify.max_leg_nm`, or whose course changes more than
+/// `densify.max_track_change` degrees. Legs along airways and around discontinuities are kept.
+///
+/// The new waypoints are named `GC001`, `GC002`, ... their altitude is interpolated between the
+/// ends of the leg, and they're marked as generated.
+pub fn densify(route: Vec<Waypoint>, densify: &Densify) -> Vec<Waypoint> {
+    let mut result: Vec<Waypoint> = Vec::with_capacity(route.len());
+    let mut generated = 0;
+
+    for waypoint in route {
+        let previous = match result.last() {
+            Some(previous) => previous,
+            None => {
+                result.push(waypoint);
+                continue;
+            }
+        };
+
+        let is_direct = waypoint.airway.is_none()
+            && previous.kind != WaypointType::Discontinuity
+            && waypoint.kind != WaypointType::Discontinuity;
+
+        let (from, to) = (previous.coordinates(), waypoint.coordinates());
+        let distance = geodesy::distance_nm(from, to);
+        let track_change = course_difference(
+            geodesy::initial_course(from, to),
+            geodesy::final_course(from, to),
+        );
+
+        let mut segments = (distance / densify.max_leg_nm).ceil();
+        if let Some(max_change) = densify.max_track_change {
+            segments = segments.max((track_change / max_change).ceil());
+        }
+
+        if is_direct && segments > 1.0 {
+            let (from_altitude, to_altitude) = (previous.altitude, waypoint.altitude);
+
+            for i in 1..segments as usize {
+                let fraction = i as f64 / segments;
+                let position = geodesy::intermediate(from, to, fraction);
+                generated += 1;
+
+                let mut point = Waypoint::new();
+                point.ident = format!("GC{generated:03}");
+                point.lon = position.lon;
+                point.lat = position.lat;
+                point.altitude = from_altitude + (to_altitude - from_altitude) * fraction;
+                point.altitude_mode = waypoint.altitude_mode;
+                point.generated = true;
+
+                result.push(point);
+            }
+        }
+
+        result.push(waypoint);
+    }
+
+    result
+}
+
+/// Internal function that calculates the smallest difference between two courses, in degrees.
+fn course_difference(a: f64, b: f64) -> f64 {
+    let difference = (a - b).rem_euclid(360.0);
+    difference.min(360.0 - difference)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn waypoint(ident: &str, lon: f64, lat: f64, altitude: f64) -> Waypoint {
+        let mut waypoint = Waypoint::new();
+        waypoint.ident = String::from(ident);
+        waypoint.lon = lon;
+        waypoint.lat = lat;
+        waypoint.altitude = altitude;
+        waypoint
+    }
+
+    fn rule(max_leg_nm: f64, max_track_change: Option<f64>) -> Densify {
+        Densify {
+            max_leg_nm,
+            max_track_change,
+        }
+    }
+
+    #[test]
+    fn splits_long_legs_evenly() {
+        // About 600 NM along the equator.
+        let route = vec![
+            waypoint("AAA", 0.0, 0.0, 10000.0),
+            waypoint("BBB", 10.0, 0.0, 30000.0),
+        ];
+        let length = geodesy::distance_nm(route[0].coordinates(), route[1].coordinates());
+
+        let route = densify(route, &rule(200.0, None));
+        let idents: Vec<&str> = route.iter().map(|w| w.ident.as_str()).collect();
+        assert_eq!(idents, ["AAA", "GC001", "GC002", "GC003", "BBB"]);
+
+        for pair in route.windows(2) {
+            let leg = geodesy::distance_nm(pair[0].coordinates(), pair[1].coordinates());
+            assert!((leg - length / 4.0).abs() < 0.01, "{leg}");
+        }
+        assert!(route[1..4]
+            .iter()
+            .all(|w| w.generated && w.lat.abs() < 1e-9));
+        assert_eq!(route[2].altitude, 20000.0);
+    }
+
+    #[test]
+    fn numbers_the_waypoints_along_the_route() {
+        let route = vec![
+            waypoint("AAA", 0.0, 0.0, 0.0),
+            waypoint("BBB", 5.0, 0.0, 0.0),
+            waypoint("CCC", 10.0, 0.0, 0.0),
+        ];
+
+        let route = densify(route, &rule(200.0, None));
+        let idents: Vec<&str> = route.iter().map(|w| w.ident.as_str()).collect();
+        assert_eq!(idents, ["AAA", "GC001", "BBB", "GC002", "CCC"]);
+    }
+
+    #[test]
+    fn keeps_short_legs_airways_and_discontinuities() {
+        let mut route = vec![
+            waypoint("AAA", 0.0, 0.0, 0.0),
+            waypoint("BBB", 1.0, 0.0, 0.0),
+            waypoint("CCC", 10.0, 0.0, 0.0),
+            waypoint("DISCON", 10.0, 0.0, 0.0),
+            waypoint("DDD", 20.0, 0.0, 0.0),
+        ];
+        route[2].airway = Some(String::from("UA1"));
+        route[3].kind = WaypointType::Discontinuity;
+
+        assert_eq!(densify(route, &rule(200.0, None)).len(), 5);
+    }
+
+    #[test]
+    fn splits_legs_whose_course_changes() {
+        // Short but far north, so the great circle's course changes a lot along it.
+        let route = vec![
+            waypoint("AAA", 0.0, 70.0, 0.0),
+            waypoint("BBB", 20.0, 70.0, 0.0),
+        ];
+        let change = course_difference(
+            geodesy::initial_course(route[0].coordinates(), route[1].coordinates()),
+            geodesy::final_course(route[0].coordinates(), route[1].coordinates()),
+        );
+        assert!(change > 15.0);
+
+        let route = densify(route, &rule(1000.0, Some(5.0)));
+        assert_eq!(route.len() as f64, (change / 5.0).ceil() + 1.0);
+
+        let middle = route[route.len() / 2].coordinates();
+        // The great circle goes north of the parallel.
+        assert!(middle.lat > 70.2, "{middle:?}");
+    }
+
+    #[test]
+    fn measures_course_differences() {
+        assert_eq!(course_difference(350.0, 10.0), 20.0);
+        assert_eq!(course_difference(10.0, 350.0), 20.0);
+        assert_eq!(course_difference(90.0, 270.0), 180.0);
+    }
+}
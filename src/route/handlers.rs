@@ -1,16 +1,84 @@
-use std::{error::Error, io::Write};
+use xml::attribute::OwnedAttribute;
 
-use xml::writer::EventWriter;
-
-use super::Airport;
+use super::{Airport, AltRestrict, AltitudeMode, Coordinates, SpeedRestrict, WaypointType};
 
 /// Used to write a waypoint to the .fgfp file.
 pub struct Waypoint {
-    pub number: usize,
+    pub kind: WaypointType,
     pub ident: String,
     pub lon: f64,
     pub lat: f64,
-    pub altitude: usize,
+    /// The altitude in feet.
+    pub altitude: f64,
+    pub altitude_mode: AltitudeMode,
+    /// The magnetic variation at the waypoint in degrees, positive when it's east.
+    pub variation: f64,
+    pub alt_restrict: AltRestrict,
+    pub speed_restrict: Option<SpeedRestrict>,
+    /// The airway used to reach this waypoint from the previous one.
+    pub airway: Option<String>,
+    /// Whether the waypoint was generated, instead of read from the .kml file.
+    pub generated: bool,
+    /// Whether the waypoint is a point of a path, instead of a named fix.
+    pub track_point: bool,
+    /// Whether the waypoint is a fix or navaid of FlightGear's navigation data.
+    pub navaid: bool,
+    /// Whether the Placemark is an airport, which is taken out of the route.
+    pub airport: bool,
+    /// Whether the Placemark is styled like an airport, like SimBrief's, which is only an airport
+    /// at the ends of the route.
+    pub airport_mark: bool,
+    /// The points of the path, when the Placemark is a path.
+    pub path: Vec<(Coordinates, f64)>,
+    /// The `name`/value pairs found in the Placemark's `ExtendedData`.
+    pub data: Vec<(String, String)>,
+}
+
+impl Waypoint {
+    /// Creates an empty waypoint, ready to be filled in by the handlers.
+    pub fn new() -> Waypoint {
+        Waypoint {
+            kind: WaypointType::Basic,
+            ident: String::new(),
+            lon: 0f64,
+            lat: 0f64,
+            altitude: 0f64,
+            altitude_mode: AltitudeMode::Absolute,
+            variation: 0f64,
+            alt_restrict: AltRestrict::At,
+            speed_restrict: None,
+            airway: None,
+            generated: false,
+            track_point: false,
+            navaid: false,
+            airport: false,
+            airport_mark: false,
+            path: Vec::new(),
+            data: Vec::new(),
+        }
+    }
+
+    /// Returns the position of the waypoint.
+    pub fn coordinates(&self) -> Coordinates {
+        Coordinates {
+            lon: self.lon,
+            lat: self.lat,
+        }
+    }
+
+    /// Returns `true` if the waypoint is at `altitude`, within a foot, so rounding and interpolated
+    /// altitudes don't break the comparison.
+    pub fn is_at(&self, altitude: f64) -> bool {
+        (self.altitude - altitude).abs() < 1.0
+    }
+
+    /// Returns the value of the `ExtendedData` field called `name`, if the Placemark had one.
+    pub fn data(&self, name: &str) -> Option<&str> {
+        self.data
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
 }
 
 /// An example of the sequence to look for in the .kml's xml is:
@@ -36,17 +104,103 @@ pub enum LookingFor {
     ClosingPlacemark,
 }
 
+/// The `ExtendedData` field being read. Placemarks can carry their data as either of:
+///
+/// ```text
+/// <Data name="alt-restrict"><value>above</value></Data>
+/// <SimpleData name="alt-restrict">above</SimpleData>
+/// ```
+///
+/// The data is read independently of the [`LookingFor`] sequence, so it doesn't matter where in
+/// the Placemark it's found.
+pub struct DataField {
+    name: String,
+    reading: bool,
+}
+
+/// Internal function to handle the start events of a Placemark's `ExtendedData`.
+pub fn handle_data_start(
+    mut field: Option<DataField>,
+    current_search: &LookingFor,
+    name: &str,
+    attributes: &[OwnedAttribute],
+) -> Option<DataField> {
+    if matches!(current_search, LookingFor::OpeningPlacemark) {
+        return None;
+    }
+
+    let data_name = attributes
+        .iter()
+        .find(|a| a.name.local_name == "name")
+        .map(|a| a.value.trim().to_string());
+
+    match (name, data_name) {
+        ("Data", Some(data_name)) => {
+            field = Some(DataField {
+                name: data_name,
+                reading: false,
+            })
+        }
+        ("SimpleData", Some(data_name)) => {
+            field = Some(DataField {
+                name: data_name,
+                reading: true,
+            })
+        }
+        ("altitudeMode", _) => {
+            field = Some(DataField {
+                name: String::from("altitudeMode"),
+                reading: true,
+            })
+        }
+        ("value", _) => {
+            if let Some(f) = field.as_mut() {
+                f.reading = true;
+            }
+        }
+        _ => {}
+    }
+
+    field
+}
+
+/// Internal function to store the contents of an `ExtendedData` field in the waypoint. Returns
+/// `true` if the characters were consumed.
+pub fn handle_data_characters(
+    waypoint: &mut Waypoint,
+    field: &mut Option<DataField>,
+    line: &str,
+) -> bool {
+    match field {
+        Some(f) if f.reading => {
+            waypoint
+                .data
+                .push((f.name.clone(), String::from(line.trim())));
+            f.reading = false;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Internal function to handle the end events of a Placemark's `ExtendedData`.
+pub fn handle_data_end(field: Option<DataField>, name: &str) -> Option<DataField> {
+    match name {
+        "Data" | "SimpleData" | "altitudeMode" | "Placemark" => None,
+        _ => field,
+    }
+}
+
 /// Internal function to handle start events from the `transform_route` function.
 pub fn handle_start_event(
     mut waypoint: Waypoint,
     mut current_search: LookingFor,
     mut drop: bool,
-    wp: usize,
     name: &str,
 ) -> (Waypoint, LookingFor, bool) {
     // 1. Find opening of `Placemark`
     if matches!(current_search, LookingFor::OpeningPlacemark) && name == "Placemark" {
-        waypoint.number = wp;
+        waypoint = Waypoint::new();
         current_search = LookingFor::OpeningName;
         drop = false;
     }
@@ -77,31 +231,33 @@ pub fn handle_characters_event(
     line: String,
     departure_airport: &Option<Airport>,
     destination_airport: &Option<Airport>,
+    read_paths: bool,
 ) -> (Waypoint, LookingFor, bool) {
     // 3. Find contents of `name`
     if matches!(current_search, LookingFor::ContentName) {
         waypoint.ident = String::from(&line);
         current_search = LookingFor::ClosingName;
 
-        // Handle the waypoints that reference airports by dropping them.
-        if let Some(airport) = departure_airport {
-            if line == airport.ident {
-                current_search = LookingFor::ClosingPlacemark;
-                drop = true;
-            }
-        }
-
-        if let Some(airport) = destination_airport {
-            if line == airport.ident {
-                current_search = LookingFor::ClosingPlacemark;
-                drop = true;
-            }
-        }
+        // The waypoints that reference the airports aren't part of the route, even when they're
+        // named like `SAEZ Ministro Pistarini`.
+        waypoint.airport = [departure_airport, destination_airport]
+            .iter()
+            .filter_map(|ap| ap.as_ref())
+            .any(|ap| first_word(&line).eq_ignore_ascii_case(&ap.ident));
     }
 
     // 6. Find contents of `styleUrl`
     if matches!(current_search, LookingFor::ContentStyleUrl) {
-        if line != "#FixMark" {
+        // SimBrief marks the airports with their own style.
+        if line != "#FixMark" && is_airport_code(first_word(&waypoint.ident)) {
+            waypoint.airport_mark = true;
+        }
+        let is_airport = waypoint.airport || waypoint.airport_mark;
+
+        if line != "#FixMark" && !is_airport && read_paths {
+            // This Placemark can only be part of the route if it's a path.
+            waypoint.track_point = true;
+        } else if line != "#FixMark" && !is_airport {
             drop = true;
 
             // We found that this Placemark is not part of the route, so we avoid
@@ -115,39 +271,35 @@ pub fn handle_characters_event(
 
     // 9. Find contents of `coordinates`
     if matches!(current_search, LookingFor::ContentCoordinates) {
-        let data: Vec<&str> = line.split(',').map(|l| l.trim()).collect();
+        let tuples = split_tuples(&line);
 
         let mut message = String::new();
 
-        waypoint.lon = match data[0].parse() {
-            Ok(d) => d,
-            Err(e) => {
-                message = e.to_string();
-                0f64
-            }
-        };
-        waypoint.lat = match data[1].parse() {
-            Ok(d) => d,
-            Err(e) => {
-                message = e.to_string();
-                0f64
+        if tuples.len() > 1 && waypoint.airport_mark && !read_paths {
+            // A path named like an airport isn't one.
+            drop = true;
+        } else if tuples.len() > 1 {
+            // A path, every tuple is a point of the track.
+            waypoint.airport_mark = false;
+            for tuple in &tuples {
+                match parse_coordinates(tuple) {
+                    Ok(point) => waypoint.path.push(point),
+                    Err(e) => message = e,
+                }
             }
-        };
-        waypoint.altitude = {
-            let meters: f64 = match data[2].parse() {
-                Ok(d) => d,
-                Err(e) => {
-                    message = e.to_string();
-                    0f64
+        } else if waypoint.track_point {
+            // Only paths are read from Placemarks without the `#FixMark` style.
+            drop = true;
+        } else {
+            match parse_coordinates(tuples.first().map_or("", |t| t.as_str())) {
+                Ok((coordinates, altitude)) => {
+                    waypoint.lon = coordinates.lon;
+                    waypoint.lat = coordinates.lat;
+                    waypoint.altitude = altitude;
                 }
-            };
-
-            // We don't want exact precision, we need to be precise up to one hundred feet. Example:
-            // If the real altitude is 12478.64 feet, we interpret that as 12500 feet. We divide by
-            // one hundred and multiply by one hundred to let the round function do this for us.
-            let feet = (meters * 3.280839895 / 100.0).round() * 100.0;
-            feet as usize
-        };
+                Err(e) => message = e,
+            }
+        }
 
         if !message.is_empty() {
             eprintln!(
@@ -163,15 +315,70 @@ pub fn handle_characters_event(
     (waypoint, current_search, drop)
 }
 
-/// Internal function to handle end events from the `transform_route` function.
-pub fn handle_end_event<W: Write>(
-    writer: &mut EventWriter<W>,
-    waypoint: Waypoint,
+/// Internal function that checks if a Placemark's name looks like an ICAO airport code, like `SAEZ`.
+fn is_airport_code(name: &str) -> bool {
+    name.len() == 4
+        && name.starts_with(|c: char| c.is_ascii_uppercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+}
+
+/// Internal function that returns the first word of a Placemark's name, which is the code of an
+/// airport named like `SAEZ Ministro Pistarini`.
+pub fn first_word(name: &str) -> &str {
+    name.split_whitespace().next().unwrap_or_default()
+}
+
+/// Internal function that splits the contents of `coordinates` into its `lon,lat,altitude` tuples.
+/// The tuples are separated by whitespace, but some tools also write spaces after the commas, like
+/// `-58.0, -34.7, 500`, so the whitespace around the commas is removed first.
+fn split_tuples(line: &str) -> Vec<String> {
+    let joined = line
+        .split(',')
+        .map(|part| part.trim())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    joined.split_whitespace().map(String::from).collect()
+}
+
+/// Internal function that reads a `lon,lat,altitude` tuple from the .kml, with the altitude in
+/// meters. Returns the coordinates and the altitude in feet.
+fn parse_coordinates(tuple: &str) -> Result<(Coordinates, f64), String> {
+    let data: Vec<&str> = tuple.split(',').map(|l| l.trim()).collect();
+    let value = |i: usize| -> Result<f64, String> {
+        data.get(i)
+            .unwrap_or(&"")
+            .parse()
+            .map_err(|e: std::num::ParseFloatError| e.to_string())
+    };
+
+    let coordinates = Coordinates {
+        lon: value(0)?,
+        lat: value(1)?,
+    }
+    .validate()?;
+
+    // The altitude is optional, clampToGround and relativeToGround tuples often leave it out. It's
+    // rounded later, as the options say.
+    let altitude = match data.get(2) {
+        None | Some(&"") => 0.0,
+        Some(_) => value(2)? * 3.280839895,
+    };
+
+    Ok((coordinates, altitude))
+}
+
+/// Internal function to handle end events from the `transform_route` function. Finished waypoints
+/// are pushed to `route`.
+pub fn handle_end_event(
+    route: &mut Vec<Waypoint>,
+    mut waypoint: Waypoint,
     mut current_search: LookingFor,
     drop: bool,
-    mut wp: usize,
     name: &str,
-) -> Result<(Waypoint, LookingFor, usize), Box<dyn Error>> {
+) -> (Waypoint, LookingFor) {
     // 4. Find closing of `name`
     if matches!(current_search, LookingFor::ClosingName) && name == "name" {
         current_search = LookingFor::OpeningStyleUrl;
@@ -189,12 +396,24 @@ pub fn handle_end_event<W: Write>(
 
     // 11. Find closing of `Placemark`
     if matches!(current_search, LookingFor::ClosingPlacemark) && name == "Placemark" {
-        if !drop {
-            super::write_waypoint(writer, &waypoint)?;
-            wp += 1;
+        if !drop && !waypoint.path.is_empty() {
+            for (i, (coordinates, altitude)) in waypoint.path.iter().enumerate() {
+                let mut point = Waypoint::new();
+                point.ident = format!("{}{:03}", waypoint.ident, i + 1);
+                point.lon = coordinates.lon;
+                point.lat = coordinates.lat;
+                point.altitude = *altitude;
+                point.track_point = true;
+                point.data = waypoint.data.clone();
+                route.push(point);
+            }
+            waypoint = Waypoint::new();
+        } else if !drop {
+            route.push(waypoint);
+            waypoint = Waypoint::new();
         }
         current_search = LookingFor::OpeningPlacemark;
     }
 
-    Ok((waypoint, current_search, wp))
+    (waypoint, current_search)
 }
@@ -0,0 +1,133 @@
+use super::{RouteOptions, Waypoint, WaypointType};
+
+/// Internal function that turns waypoints into holds and inserts the discontinuities of the route.
+///
+/// A waypoint becomes a hold when it has a `hold` field in its `ExtendedData` or a hold in the
+/// options, which take priority. Placemarks named `DISCON` or `DISCONTINUITY` become
+/// discontinuities, and the options can add a discontinuity after any waypoint.
+pub fn apply_holds_and_discontinuities(
+    route: Vec<Waypoint>,
+    options: &RouteOptions,
+) -> Vec<Waypoint> {
+    let mut result = Vec::with_capacity(route.len());
+
+    for mut waypoint in route {
+        if matches!(waypoint.ident.as_str(), "DISCON" | "DISCONTINUITY") {
+            waypoint.kind = WaypointType::Discontinuity;
+            result.push(waypoint);
+            continue;
+        }
+
+        if let Some(value) = waypoint.data("hold") {
+            match value.parse() {
+                Ok(hold) => waypoint.kind = WaypointType::Hold(hold),
+                Err(e) => eprintln!(
+                    "\x1B[01;33mIgnoring\x1B[00;01m {}\x1B[00m hold: {}",
+                    waypoint.ident, e
+                ),
+            }
+        }
+
+        if let Some((_, hold)) = options.holds.iter().find(|(i, _)| *i == waypoint.ident) {
+            waypoint.kind = WaypointType::Hold(hold.clone());
+        }
+
+        let discontinuity = options.discontinuities.contains(&waypoint.ident);
+        let position = (waypoint.lon, waypoint.lat, waypoint.altitude);
+
+        result.push(waypoint);
+
+        if discontinuity {
+            let mut waypoint = Waypoint::new();
+            waypoint.kind = WaypointType::Discontinuity;
+            (waypoint.lon, waypoint.lat, waypoint.altitude) = position;
+            result.push(waypoint);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Hold, HoldLeg};
+
+    fn waypoint(ident: &str, hold: Option<&str>) -> Waypoint {
+        let mut waypoint = Waypoint::new();
+        waypoint.ident = String::from(ident);
+        waypoint.altitude = 5000.0;
+        if let Some(hold) = hold {
+            waypoint
+                .data
+                .push((String::from("hold"), String::from(hold)));
+        }
+        waypoint
+    }
+
+    fn kinds(route: &[Waypoint]) -> Vec<(&str, WaypointType)> {
+        route
+            .iter()
+            .map(|w| (w.ident.as_str(), w.kind.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn inserts_the_discontinuities() {
+        let route = vec![
+            waypoint("ALPHA", None),
+            waypoint("DISCON", None),
+            waypoint("BRAVO", None),
+            waypoint("DISCONTINUITY", None),
+            waypoint("CHARL", None),
+        ];
+        let options = RouteOptions {
+            discontinuities: vec![String::from("BRAVO")],
+            ..Default::default()
+        };
+
+        let route = apply_holds_and_discontinuities(route, &options);
+        assert_eq!(
+            kinds(&route),
+            [
+                ("ALPHA", WaypointType::Basic),
+                ("DISCON", WaypointType::Discontinuity),
+                ("BRAVO", WaypointType::Basic),
+                ("", WaypointType::Discontinuity),
+                ("DISCONTINUITY", WaypointType::Discontinuity),
+                ("CHARL", WaypointType::Basic),
+            ]
+        );
+        // The discontinuity after a waypoint is where the waypoint is.
+        assert_eq!(route[3].altitude, 5000.0);
+    }
+
+    #[test]
+    fn gives_priority_to_the_holds_of_the_options() {
+        let route = vec![
+            waypoint("ALPHA", Some("090/L/4NM")),
+            waypoint("BRAVO", Some("180/R/2MIN")),
+            waypoint("CHARL", Some("400/R")),
+        ];
+        let hold = Hold {
+            inbound_course: 270.0,
+            right_turns: true,
+            leg: HoldLeg::Minutes(1.0),
+        };
+        let options = RouteOptions {
+            holds: vec![(String::from("BRAVO"), hold.clone())],
+            ..Default::default()
+        };
+
+        let route = apply_holds_and_discontinuities(route, &options);
+        assert_eq!(
+            kinds(&route),
+            [
+                ("ALPHA", WaypointType::Hold("090/L/4NM".parse().unwrap())),
+                ("BRAVO", WaypointType::Hold(hold)),
+                // An invalid hold is ignored.
+                ("CHARL", WaypointType::Basic),
+            ]
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use super::{ProcedureFix, Waypoint};
+
+/// Internal function that replaces the start of the route with the fixes of the `departure`
+/// procedure, and its end with the fixes of the `arrival` procedures, for FMSs that can't load
+/// procedures.
+///
+/// The route is joined at the last fix of the departure it has, and at the first fix of the arrival
+/// it has, which replace the waypoints of the .kml file from there on. When the route has none of
+/// the fixes, the procedure is added before (or after) it.
+pub fn insert_procedures(
+    route: &mut Vec<Waypoint>,
+    departure: &[ProcedureFix],
+    arrival: &[ProcedureFix],
+) {
+    if !departure.is_empty() {
+        let joined = departure
+            .iter()
+            .rev()
+            .find_map(|fix| route.iter().position(|w| w.ident == fix.ident));
+        let first = joined.map_or(0, |i| i + 1);
+        let altitude = route.get(first).map_or(0.0, |w| w.altitude);
+
+        route.splice(..first, to_waypoints(departure, altitude));
+    }
+
+    if !arrival.is_empty() {
+        let joined = arrival
+            .iter()
+            .find_map(|fix| route.iter().rposition(|w| w.ident == fix.ident));
+        let last = joined.unwrap_or(route.len());
+        let altitude = last
+            .checked_sub(1)
+            .and_then(|i| route.get(i))
+            .map_or(0.0, |w| w.altitude);
+
+        route.truncate(last);
+        route.extend(to_waypoints(arrival, altitude));
+    }
+}
+
+/// Internal function that builds the waypoints of the fixes of a procedure. The fixes without an
+/// altitude get the altitude of the next one that has it (or `altitude`, for the last ones), and
+/// aren't restricted.
+fn to_waypoints(fixes: &[ProcedureFix], altitude: f64) -> Vec<Waypoint> {
+    let mut next_altitude = altitude;
+    let mut waypoints = Vec::with_capacity(fixes.len());
+
+    for fix in fixes.iter().rev() {
+        let mut waypoint = Waypoint::new();
+        waypoint.ident = fix.ident.clone();
+        waypoint.lon = fix.coordinates.lon;
+        waypoint.lat = fix.coordinates.lat;
+        waypoint.generated = true;
+
+        // The restrictions are read like the ones of the `ExtendedData`.
+        let restriction = match fix.altitude {
+            Some((restriction, altitude)) => {
+                next_altitude = altitude;
+                restriction.as_str()
+            }
+            None => "none",
+        };
+        waypoint.altitude = next_altitude;
+        waypoint
+            .data
+            .push((String::from("alt-restrict"), String::from(restriction)));
+
+        if let Some(speed) = fix.speed {
+            waypoint
+                .data
+                .push((String::from("speed"), format!("below {speed}")));
+        }
+
+        waypoints.push(waypoint);
+    }
+
+    waypoints.reverse();
+    waypoints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{geodesy::Coordinates, AltRestrict};
+
+    fn fix(ident: &str, lon: f64, altitude: Option<f64>) -> ProcedureFix {
+        ProcedureFix {
+            ident: String::from(ident),
+            coordinates: Coordinates { lon, lat: -34.0 },
+            altitude: altitude.map(|a| (AltRestrict::Above, a)),
+            speed: None,
+        }
+    }
+
+    fn waypoint(ident: &str, lon: f64, altitude: f64) -> Waypoint {
+        let mut waypoint = Waypoint::new();
+        waypoint.ident = String::from(ident);
+        waypoint.lon = lon;
+        waypoint.lat = -34.0;
+        waypoint.altitude = altitude;
+        waypoint
+    }
+
+    fn route() -> Vec<Waypoint> {
+        vec![
+            waypoint("EZE", -58.5, 1000.0),
+            waypoint("ANDOR", -58.0, 6000.0),
+            waypoint("CRUZE", -57.0, 30000.0),
+            waypoint("DORVO", -56.0, 9000.0),
+            waypoint("SUMU", -55.5, 100.0),
+        ]
+    }
+
+    fn idents(route: &[Waypoint]) -> Vec<&str> {
+        route.iter().map(|w| w.ident.as_str()).collect()
+    }
+
+    #[test]
+    fn joins_the_procedures_at_their_shared_fixes() {
+        let mut route = route();
+        let departure = [fix("RW11", -58.6, None), fix("ANDOR", -58.1, Some(5000.0))];
+        let arrival = [fix("DORVO", -56.1, Some(8000.0)), fix("FAP29", -55.6, None)];
+        insert_procedures(&mut route, &departure, &arrival);
+
+        // The joining fixes are only once, from the procedures.
+        assert_eq!(idents(&route), ["RW11", "ANDOR", "CRUZE", "DORVO", "FAP29"]);
+        assert!(route[1].generated && route[3].generated);
+        assert_eq!(route[1].lon, -58.1);
+        assert!(!route[2].generated);
+
+        // The fixes without an altitude get the next one, or the route's at the ends.
+        let altitudes: Vec<f64> = route.iter().map(|w| w.altitude).collect();
+        assert_eq!(altitudes, [5000.0, 5000.0, 30000.0, 8000.0, 30000.0]);
+        assert_eq!(route[0].data("alt-restrict"), Some("none"));
+        assert_eq!(route[1].data("alt-restrict"), Some("above"));
+    }
+
+    #[test]
+    fn joins_at_the_last_departure_fix_and_the_first_arrival_fix() {
+        let mut route = route();
+        let departure = [fix("EZE", -58.5, None), fix("ANDOR", -58.0, None)];
+        let arrival = [fix("DORVO", -56.0, None), fix("SUMU", -55.5, None)];
+        insert_procedures(&mut route, &departure, &arrival);
+
+        assert_eq!(idents(&route), ["EZE", "ANDOR", "CRUZE", "DORVO", "SUMU"]);
+        assert!(route.iter().all(|w| w.generated || w.ident == "CRUZE"));
+    }
+
+    #[test]
+    fn adds_the_procedures_the_route_doesnt_join() {
+        let mut route = route();
+        let departure = [fix("PUNTA", -59.0, Some(4000.0))];
+        let arrival = [fix("LAPAZ", -55.0, Some(2000.0))];
+        insert_procedures(&mut route, &departure, &arrival);
+
+        assert_eq!(
+            idents(&route),
+            ["PUNTA", "EZE", "ANDOR", "CRUZE", "DORVO", "SUMU", "LAPAZ"]
+        );
+    }
+}
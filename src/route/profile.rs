@@ -0,0 +1,266 @@
+use super::{geodesy, AltitudeMode, Coordinates, VerticalProfile, Waypoint, WaypointType};
+
+/// Where the climb ends or the descent starts: `along_nm` nautical miles after the waypoint
+/// `after`, towards the next one.
+pub struct Top {
+    pub after: usize,
+    pub along_nm: f64,
+}
+
+/// Internal function that finds the top of climb, where the route reaches its `cruise` altitude
+/// climbing at `profile.climb_gradient` from the waypoint before it. The cruise altitude is the
+/// highest of the route when it isn't given.
+pub fn top_of_climb(
+    route: &[Waypoint],
+    cruise: Option<f64>,
+    profile: &VerticalProfile,
+) -> Option<Top> {
+    let cruise = cruise.or_else(|| cruise_altitude(route))?;
+    let reached = route
+        .iter()
+        .position(|w| is_absolute(w) && w.is_at(cruise))?;
+
+    let previous = match reached.checked_sub(1).map(|i| &route[i]) {
+        Some(previous) if is_absolute(previous) && previous.altitude < cruise => previous,
+        _ => {
+            return Some(Top {
+                after: reached,
+                along_nm: 0.0,
+            })
+        }
+    };
+
+    let distance = geodesy::distance_nm(previous.coordinates(), route[reached].coordinates());
+    let needed = (cruise - previous.altitude) / profile.climb_gradient;
+
+    // The climb can't be shallower than the .kml says, it ends at the waypoint at the latest.
+    Some(if needed < distance {
+        Top {
+            after: reached - 1,
+            along_nm: needed,
+        }
+    } else {
+        Top {
+            after: reached,
+            along_nm: 0.0,
+        }
+    })
+}
+
+/// Internal function that finds the top of descent, where the route leaves its highest altitude
+/// to descend at `profile.descent_gradient` to the waypoint after it.
+pub fn top_of_descent(route: &[Waypoint], profile: &VerticalProfile) -> Option<Top> {
+    let cruise = cruise_altitude(route)?;
+    let left = route
+        .iter()
+        .rposition(|w| is_absolute(w) && w.is_at(cruise))?;
+
+    let next = match route.get(left + 1) {
+        Some(next) if is_absolute(next) && next.altitude < cruise => next,
+        _ => {
+            return Some(Top {
+                after: left,
+                along_nm: 0.0,
+            })
+        }
+    };
+
+    let distance = geodesy::distance_nm(route[left].coordinates(), next.coordinates());
+    let needed = (cruise - next.altitude) / profile.descent_gradient;
+
+    Some(Top {
+        after: left,
+        along_nm: (distance - needed).max(0.0),
+    })
+}
+
+/// Internal function that inserts a generated waypoint called `ident` at the cruise altitude where
+/// the `top` is. Tops at a waypoint, or along an airway, don't need one.
+pub fn insert_top(route: &mut Vec<Waypoint>, top: &Top, ident: &str) {
+    if top.along_nm <= 0.0 {
+        return;
+    }
+
+    let (previous, next) = (&route[top.after], &route[top.after + 1]);
+    if let Some(airway) = &next.airway {
+        eprintln!(
+            "\x1B[01;33mIgnoring\x1B[00;01m {}\x1B[00m waypoint: it's along the airway {}",
+            ident, airway
+        );
+        return;
+    }
+
+    let (from, to) = (previous.coordinates(), next.coordinates());
+    let fraction = top.along_nm / geodesy::distance_nm(from, to);
+    let position = geodesy::intermediate(from, to, fraction);
+
+    let mut point = Waypoint::new();
+    point.ident = String::from(ident);
+    point.lon = position.lon;
+    point.lat = position.lat;
+    point.altitude = previous.altitude.max(next.altitude);
+    point.generated = true;
+
+    route.insert(top.after + 1, point);
+}
+
+/// Internal function that calculates how far the `top` is from the origin of the route, and from
+/// its destination. The origin and destination are the airports when their coordinates are known,
+/// or else the first and last waypoints.
+pub fn distances(
+    route: &[Waypoint],
+    top: &Top,
+    origin: Option<Coordinates>,
+    destination: Option<Coordinates>,
+) -> (f64, f64) {
+    let mut points: Vec<(usize, Coordinates)> = Vec::with_capacity(route.len() + 2);
+    points.extend(origin.map(|c| (usize::MAX, c)));
+    points.extend(
+        route
+            .iter()
+            .enumerate()
+            .filter(|(_, w)| w.kind != WaypointType::Discontinuity)
+            .map(|(i, w)| (i, w.coordinates())),
+    );
+    points.extend(destination.map(|c| (usize::MAX, c)));
+
+    let mut total = 0.0;
+    let mut from_origin = 0.0;
+    for (i, pair) in points.windows(2).enumerate() {
+        if points[i].0 == top.after {
+            from_origin = total + top.along_nm;
+        }
+        total += geodesy::distance_nm(pair[0].1, pair[1].1);
+    }
+
+    if points.last().is_some_and(|(i, _)| *i == top.after) {
+        from_origin = total;
+    }
+
+    (from_origin, total - from_origin)
+}
+
+/// Internal function that returns the highest absolute altitude of the route.
+fn cruise_altitude(route: &[Waypoint]) -> Option<f64> {
+    route
+        .iter()
+        .filter(|w| is_absolute(w))
+        .map(|w| w.altitude)
+        .reduce(f64::max)
+}
+
+fn is_absolute(waypoint: &Waypoint) -> bool {
+    waypoint.altitude_mode == AltitudeMode::Absolute && waypoint.kind != WaypointType::Discontinuity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A route east along the equator, with a waypoint every degree at these `altitudes`.
+    fn route(altitudes: &[f64]) -> Vec<Waypoint> {
+        altitudes
+            .iter()
+            .enumerate()
+            .map(|(i, altitude)| {
+                let mut waypoint = Waypoint::new();
+                waypoint.ident = format!("WP{i}");
+                waypoint.lon = i as f64;
+                waypoint.altitude = *altitude;
+                waypoint
+            })
+            .collect()
+    }
+
+    const PROFILE: VerticalProfile = VerticalProfile {
+        climb_gradient: 1000.0,
+        descent_gradient: 500.0,
+    };
+
+    /// The length of the legs of [`route`], in nautical miles.
+    fn leg() -> f64 {
+        geodesy::distance_nm(
+            Coordinates { lon: 0.0, lat: 0.0 },
+            Coordinates { lon: 1.0, lat: 0.0 },
+        )
+    }
+
+    #[test]
+    fn places_the_top_of_climb_at_the_climb_gradient() {
+        let route = route(&[5000.0, 35000.0, 35000.0, 5000.0]);
+
+        let top = top_of_climb(&route, None, &PROFILE).unwrap();
+        assert_eq!(top.after, 0);
+        assert!((top.along_nm - 30.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn keeps_the_top_of_climb_at_the_waypoint_when_it_climbs_steeper() {
+        let route = route(&[0.0, 35000.0, 35000.0, 5000.0]);
+
+        // 35000 ft at 500 ft/NM needs 70 NM, more than the leg.
+        let profile = VerticalProfile {
+            climb_gradient: 500.0,
+            ..PROFILE
+        };
+        let top = top_of_climb(&route, None, &profile).unwrap();
+        assert_eq!((top.after, top.along_nm), (1, 0.0));
+    }
+
+    #[test]
+    fn places_the_top_of_descent_at_the_descent_gradient() {
+        let route = route(&[5000.0, 35000.0, 35000.0, 15000.0]);
+
+        // 20000 ft at 500 ft/NM needs 40 NM before the waypoint.
+        let top = top_of_descent(&route, &PROFILE).unwrap();
+        assert_eq!(top.after, 2);
+        assert!((top.along_nm - (leg() - 40.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn finds_the_cruise_altitude_within_a_foot() {
+        let route = route(&[5000.0, 34999.6, 35000.0, 5000.0]);
+
+        let top = top_of_climb(&route, Some(35000.0), &PROFILE).unwrap();
+        assert_eq!(top.after, 0);
+        let top = top_of_descent(&route, &PROFILE).unwrap();
+        assert_eq!(top.after, 2);
+    }
+
+    #[test]
+    fn inserts_the_tops_at_the_cruise_altitude() {
+        let mut route = route(&[5000.0, 35000.0, 35000.0, 15000.0]);
+        let climb = top_of_climb(&route, None, &PROFILE).unwrap();
+        let descent = top_of_descent(&route, &PROFILE).unwrap();
+
+        // As transform_route does, the top of descent goes first.
+        insert_top(&mut route, &descent, "T/D");
+        insert_top(&mut route, &climb, "T/C");
+
+        let idents: Vec<&str> = route.iter().map(|w| w.ident.as_str()).collect();
+        assert_eq!(idents, ["WP0", "T/C", "WP1", "WP2", "T/D", "WP3"]);
+        assert_eq!((route[1].altitude, route[4].altitude), (35000.0, 35000.0));
+        assert!((route[1].lon - 30.0 / leg()).abs() < 1e-6);
+        assert!(route[1].generated && route[4].generated);
+    }
+
+    #[test]
+    fn measures_the_tops_from_the_airports() {
+        let route = route(&[5000.0, 35000.0, 35000.0, 5000.0]);
+        let top = Top {
+            after: 0,
+            along_nm: 30.0,
+        };
+
+        let (from_origin, to_destination) = distances(&route, &top, None, None);
+        assert!((from_origin - 30.0).abs() < 1e-9);
+        assert!((to_destination - (3.0 * leg() - 30.0)).abs() < 1e-9);
+
+        let origin = Coordinates {
+            lon: -1.0,
+            lat: 0.0,
+        };
+        let (from_origin, _) = distances(&route, &top, Some(origin), None);
+        assert!((from_origin - (leg() + 30.0)).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,178 @@
+use super::{geodesy, AltitudeMode, Simplify, Waypoint, WaypointType};
+
+/// Internal function that removes the points of paths that don't change the shape of the route,
+/// using the Douglas-Peucker algorithm. Returns the simplified route and how many points were
+/// removed.
+///
+/// A point is kept when it's further than `simplify.tolerance_nm` from the great circle between
+/// the points kept around it, or more than `simplify.tolerance_ft` above or below the altitude
+/// interpolated along it. Named fixes, holds and discontinuities are always kept.
+pub fn simplify(route: Vec<Waypoint>, simplify: &Simplify) -> (Vec<Waypoint>, usize) {
+    let is_anchor =
+        |w: &Waypoint| !w.track_point || w.kind != WaypointType::Basic || w.airway.is_some();
+
+    let mut keep = vec![false; route.len()];
+
+    // Simplify every run of points between anchors, keeping both ends of the run.
+    let mut start = 0;
+    for end in 1..route.len() {
+        if is_anchor(&route[end]) || end == route.len() - 1 {
+            douglas_peucker(&route, start, end, simplify, &mut keep);
+            start = end;
+        }
+    }
+
+    for (i, waypoint) in route.iter().enumerate() {
+        if is_anchor(waypoint) || i == 0 || i == route.len() - 1 {
+            keep[i] = true;
+        }
+    }
+
+    let removed = keep.iter().filter(|k| !**k).count();
+    let route = route
+        .into_iter()
+        .zip(keep)
+        .filter(|(_, keep)| *keep)
+        .map(|(w, _)| w)
+        .collect();
+
+    (route, removed)
+}
+
+/// Internal function that marks the points between `start` and `end` that have to be kept.
+fn douglas_peucker(
+    route: &[Waypoint],
+    start: usize,
+    end: usize,
+    simplify: &Simplify,
+    keep: &mut [bool],
+) {
+    keep[start] = true;
+    keep[end] = true;
+
+    if end <= start + 1 {
+        return;
+    }
+
+    let (from, to) = (route[start].coordinates(), route[end].coordinates());
+    let length = geodesy::distance_nm(from, to);
+    let (from_altitude, to_altitude) = (route[start].altitude, route[end].altitude);
+
+    // Find the point furthest from the simplified leg, relative to the tolerances.
+    let mut furthest = None;
+    let mut max_deviation = 1.0;
+
+    for (i, waypoint) in route.iter().enumerate().take(end).skip(start + 1) {
+        let point = waypoint.coordinates();
+        let cross_track = geodesy::cross_track_nm(from, to, point).abs();
+
+        let fraction = if length > 0.0 {
+            (geodesy::along_track_nm(from, to, point) / length).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let altitude = from_altitude + (to_altitude - from_altitude) * fraction;
+        // Altitudes relative to the ground can't be compared.
+        let vertical = match waypoint.altitude_mode {
+            AltitudeMode::Absolute => (waypoint.altitude - altitude).abs(),
+            _ => 0.0,
+        };
+
+        let deviation = (cross_track / simplify.tolerance_nm).max(vertical / simplify.tolerance_ft);
+
+        if deviation > max_deviation {
+            max_deviation = deviation;
+            furthest = Some(i);
+        }
+    }
+
+    if let Some(i) = furthest {
+        douglas_peucker(route, start, i, simplify, keep);
+        douglas_peucker(route, i, end, simplify, keep);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geodesy::Coordinates;
+
+    fn point(ident: &str, position: Coordinates, altitude: f64) -> Waypoint {
+        let mut waypoint = Waypoint::new();
+        waypoint.ident = String::from(ident);
+        waypoint.lon = position.lon;
+        waypoint.lat = position.lat;
+        waypoint.altitude = altitude;
+        waypoint.track_point = true;
+        waypoint
+    }
+
+    /// A path east along the equator, whose middle point is `offset_nm` north of it and
+    /// `climb_ft` above the rest.
+    fn path(offset_nm: f64, climb_ft: f64) -> Vec<Waypoint> {
+        let along = |lon| Coordinates { lon, lat: 0.0 };
+        let middle = geodesy::destination(along(1.0), 0.0, offset_nm);
+
+        vec![
+            point("Path001", along(0.0), 5000.0),
+            point("Path002", along(0.5), 5000.0),
+            point("Path003", middle, 5000.0 + climb_ft),
+            point("Path004", along(1.5), 5000.0),
+            point("Path005", along(2.0), 5000.0),
+        ]
+    }
+
+    fn idents(route: &[Waypoint]) -> Vec<&str> {
+        route.iter().map(|w| w.ident.as_str()).collect()
+    }
+
+    fn tolerance(tolerance_nm: f64, tolerance_ft: f64) -> Simplify {
+        Simplify {
+            tolerance_nm,
+            tolerance_ft,
+        }
+    }
+
+    #[test]
+    fn removes_the_points_within_the_tolerance() {
+        let (route, removed) = simplify(path(0.9, 0.0), &tolerance(1.0, f64::INFINITY));
+
+        assert_eq!(removed, 3);
+        assert_eq!(idents(&route), ["Path001", "Path005"]);
+    }
+
+    #[test]
+    fn keeps_the_points_beyond_the_tolerance() {
+        let (route, removed) = simplify(path(1.1, 0.0), &tolerance(1.0, f64::INFINITY));
+
+        // The points next to the corner are on the new legs.
+        assert_eq!(removed, 2);
+        assert_eq!(idents(&route), ["Path001", "Path003", "Path005"]);
+    }
+
+    #[test]
+    fn keeps_the_points_beyond_the_vertical_tolerance() {
+        let (route, _) = simplify(path(0.0, 290.0), &tolerance(1.0, 300.0));
+        assert_eq!(idents(&route), ["Path001", "Path005"]);
+
+        let (route, _) = simplify(path(0.0, 310.0), &tolerance(1.0, 300.0));
+        assert_eq!(idents(&route), ["Path001", "Path003", "Path005"]);
+
+        // Altitudes relative to the ground aren't compared.
+        let mut relative = path(0.0, 310.0);
+        relative[2].altitude_mode = AltitudeMode::RelativeToGround;
+        let (route, _) = simplify(relative, &tolerance(1.0, 300.0));
+        assert_eq!(idents(&route), ["Path001", "Path005"]);
+    }
+
+    #[test]
+    fn keeps_named_fixes() {
+        let mut route = path(0.0, 0.0);
+        route[1].track_point = false;
+        route[3].airway = Some(String::from("UA1"));
+
+        let (route, removed) = simplify(route, &tolerance(1.0, f64::INFINITY));
+        assert_eq!(removed, 1);
+        assert_eq!(idents(&route), ["Path001", "Path002", "Path004", "Path005"]);
+    }
+}
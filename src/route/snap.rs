@@ -0,0 +1,169 @@
+use super::{Diagnostic, FixLookup, Problem, Waypoint, WaypointType};
+use crate::navdata::FixKind;
+
+/// Internal function that looks up every named waypoint of the route in the fix database. Returns
+/// the waypoints that aren't in the database, or that are further than `lookup.tolerance_nm`
+/// from it.
+///
+/// When `lookup.snap` is set, the waypoints found within the tolerance are moved to the position of
+/// the database, and written as navaids so FlightGear uses its own fix. The user's waypoints aren't
+/// written as navaids, since FlightGear doesn't know them.
+pub fn snap_to_fixes(route: &mut [Waypoint], lookup: &FixLookup) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    for waypoint in route.iter_mut() {
+        // Only the named fixes of the .kml can be in the database.
+        if waypoint.generated
+            || waypoint.track_point
+            || waypoint.kind == WaypointType::Discontinuity
+        {
+            continue;
+        }
+
+        let (fix, distance) = match lookup
+            .database
+            .nearest(&waypoint.ident, waypoint.coordinates())
+        {
+            Some(nearest) => nearest,
+            None => {
+                diagnostics.push(Diagnostic {
+                    ident: waypoint.ident.clone(),
+                    problem: Problem::UnknownFix,
+                    fixed: false,
+                });
+                continue;
+            }
+        };
+
+        if distance > lookup.tolerance_nm {
+            diagnostics.push(Diagnostic {
+                ident: waypoint.ident.clone(),
+                problem: Problem::FixMoved(distance),
+                fixed: false,
+            });
+            continue;
+        }
+
+        if lookup.snap {
+            waypoint.lon = fix.coordinates.lon;
+            waypoint.lat = fix.coordinates.lat;
+            waypoint.navaid = fix.kind != FixKind::User;
+        }
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fgfp, navdata::FixDatabase, transform_route, EmitterConfig, EventReader, RouteOptions,
+    };
+
+    const FIX_DAT: &str = "I\n1101 Version\n\
+        -34.60 -57.50 GESOL ENRT SA\n\
+        45.60 2.50 GESOL ENRT LF\n\
+        -34.40 -56.70 ARSOT ENRT SU\n\
+        99\n";
+
+    fn lookup(tolerance_nm: f64, snap: bool) -> FixLookup {
+        let mut database = FixDatabase::default();
+        database.read_fix_dat(FIX_DAT.as_bytes()).unwrap();
+
+        FixLookup {
+            database,
+            tolerance_nm,
+            snap,
+        }
+    }
+
+    fn waypoint(ident: &str, lon: f64, lat: f64) -> Waypoint {
+        let mut waypoint = Waypoint::new();
+        waypoint.ident = String::from(ident);
+        waypoint.lon = lon;
+        waypoint.lat = lat;
+        waypoint
+    }
+
+    #[test]
+    fn snaps_to_the_nearest_fix_within_the_tolerance() {
+        let mut route = vec![waypoint("GESOL", -57.52, -34.61)];
+
+        assert_eq!(snap_to_fixes(&mut route, &lookup(2.0, true)), []);
+        // The GESOL of the SA region, not the one in France.
+        assert_eq!((route[0].lon, route[0].lat), (-57.50, -34.60));
+        assert!(route[0].navaid);
+    }
+
+    #[test]
+    fn only_checks_the_fixes_without_snap() {
+        let mut route = vec![waypoint("GESOL", -57.52, -34.61)];
+
+        assert_eq!(snap_to_fixes(&mut route, &lookup(2.0, false)), []);
+        assert_eq!((route[0].lon, route[0].lat), (-57.52, -34.61));
+        assert!(!route[0].navaid);
+    }
+
+    #[test]
+    fn reports_unknown_and_moved_fixes() {
+        let mut route = vec![
+            waypoint("ZULU", -57.0, -34.0),
+            waypoint("ARSOT", -56.50, -34.40),
+            waypoint("GESOL", -57.50, -34.60),
+        ];
+
+        let diagnostics = snap_to_fixes(&mut route, &lookup(5.0, true));
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].ident, "ZULU");
+        assert_eq!(diagnostics[0].problem, Problem::UnknownFix);
+        assert_eq!(diagnostics[1].ident, "ARSOT");
+        assert!(matches!(diagnostics[1].problem, Problem::FixMoved(d) if d > 9.0 && d < 10.0));
+        assert!(diagnostics.iter().all(|d| !d.fixed));
+
+        // The moved fix stays where it was.
+        assert_eq!((route[1].lon, route[1].lat), (-56.50, -34.40));
+        assert!(!route[1].navaid && route[2].navaid);
+    }
+
+    #[test]
+    fn skips_the_generated_waypoints_and_track_points() {
+        let mut route = vec![waypoint("WP1", -57.0, -34.0), waypoint("WP2", -56.0, -34.0)];
+        route[0].generated = true;
+        route[1].track_point = true;
+
+        assert_eq!(snap_to_fixes(&mut route, &lookup(2.0, true)), []);
+    }
+
+    #[test]
+    fn writes_the_snapped_fixes_as_navaids() {
+        let kml = "<?xml version=\"1.0\"?><kml><Document>\
+            <Placemark><name>GESOL</name><styleUrl>#FixMark</styleUrl>\
+            <Point><coordinates>-57.52,-34.61,3048</coordinates></Point></Placemark>\
+            <Placemark><name>BRAVO</name><styleUrl>#FixMark</styleUrl>\
+            <Point><coordinates>-57.0,-34.5,3048</coordinates></Point></Placemark>\
+            </Document></kml>";
+        let options = RouteOptions {
+            fixes: Some(lookup(2.0, true)),
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        let mut writer = EmitterConfig::new().create_writer(&mut output);
+        crate::write_start_of_tree(&mut writer, options.target).unwrap();
+        transform_route(
+            EventReader::new(kml.as_bytes()),
+            &mut writer,
+            &None,
+            &None,
+            &options,
+        )
+        .unwrap();
+        crate::close_tree(&mut writer).unwrap();
+
+        let route = fgfp::read_route(EventReader::new(output.as_slice())).unwrap();
+        assert_eq!(route[0].kind.as_deref(), Some("navaid"));
+        assert_eq!((route[0].lon, route[0].lat), (Some(-57.5), Some(-34.6)));
+        assert_eq!(route[1].kind.as_deref(), Some("basic"));
+    }
+}
@@ -0,0 +1,125 @@
+use super::{AltitudeMode, Waypoint, WaypointType};
+use crate::navdata::{UserWaypoint, UserWaypoints};
+
+/// Internal function that moves the named waypoints of the .kml file that are in the user's
+/// `library` to its position, since it's more exact than a Placemark dropped by hand. Waypoints
+/// get the altitude of the library when it has one.
+pub fn apply_user_waypoints(route: &mut [Waypoint], library: &UserWaypoints) {
+    for waypoint in route.iter_mut() {
+        if waypoint.generated
+            || waypoint.track_point
+            || waypoint.kind == WaypointType::Discontinuity
+        {
+            continue;
+        }
+
+        if let Some(user) = library.get(&waypoint.ident) {
+            waypoint.lon = user.coordinates.lon;
+            waypoint.lat = user.coordinates.lat;
+
+            if let Some(altitude) = user.altitude_ft {
+                waypoint.altitude = altitude;
+                waypoint.altitude_mode = AltitudeMode::Absolute;
+            }
+        }
+    }
+}
+
+/// Internal function that builds the waypoint of a fix of the user's library that isn't in the
+/// .kml file. Without an altitude in the library, it flies at `altitude`.
+pub fn to_waypoint(user: &UserWaypoint, altitude: f64) -> Waypoint {
+    let mut waypoint = Waypoint::new();
+    waypoint.ident = user.ident.clone();
+    waypoint.lon = user.coordinates.lon;
+    waypoint.lat = user.coordinates.lat;
+    waypoint.altitude = user.altitude_ft.unwrap_or(altitude);
+    waypoint.generated = true;
+
+    waypoint
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::route::airways;
+
+    fn library() -> UserWaypoints {
+        UserWaypoints::from_reader("PUMA,-34.62,-58.71,1500\nCIUDA,-34.58,-58.40\n".as_bytes())
+            .unwrap()
+    }
+
+    fn waypoint(ident: &str, lon: f64, lat: f64, altitude: f64) -> Waypoint {
+        let mut waypoint = Waypoint::new();
+        waypoint.ident = String::from(ident);
+        waypoint.lon = lon;
+        waypoint.lat = lat;
+        waypoint.altitude = altitude;
+        waypoint
+    }
+
+    fn idents(route: &[Waypoint]) -> Vec<&str> {
+        route.iter().map(|w| w.ident.as_str()).collect()
+    }
+
+    #[test]
+    fn moves_the_waypoints_to_the_library() {
+        let mut route = vec![
+            waypoint("PUMA", -58.70, -34.60, 3000.0),
+            waypoint("CIUDA", -58.41, -34.59, 3000.0),
+            waypoint("CIUDA", -58.0, -34.0, 3000.0),
+        ];
+        route[0].altitude_mode = AltitudeMode::RelativeToGround;
+        route[2].generated = true;
+
+        let library = library();
+        apply_user_waypoints(&mut route, &library);
+
+        assert_eq!(
+            route[0].coordinates(),
+            library.get("PUMA").unwrap().coordinates
+        );
+        assert_eq!(route[0].altitude, 1500.0);
+        assert_eq!(route[0].altitude_mode, AltitudeMode::Absolute);
+        // Without an altitude in the library, the .kml's is kept.
+        assert_eq!(
+            route[1].coordinates(),
+            library.get("CIUDA").unwrap().coordinates
+        );
+        assert_eq!(route[1].altitude, 3000.0);
+        // The generated waypoints aren't the user's.
+        assert_eq!((route[2].lon, route[2].lat), (-58.0, -34.0));
+    }
+
+    #[test]
+    fn doesnt_move_the_removed_waypoints() {
+        let mut library = library();
+        library.remove("PUMA");
+
+        let mut route = vec![waypoint("PUMA", -58.70, -34.60, 3000.0)];
+        apply_user_waypoints(&mut route, &library);
+
+        assert_eq!((route[0].lon, route[0].lat), (-58.70, -34.60));
+    }
+
+    #[test]
+    fn inserts_the_waypoints_of_the_route_string() {
+        let mut route = vec![
+            waypoint("SAEZ", -58.53, -34.82, 100.0),
+            waypoint("SADP", -58.61, -34.61, 100.0),
+        ];
+        route[0].altitude = 2000.0;
+
+        let route_string = Some(String::from("SAEZ DCT ciuda DCT PUMA DCT SADP"));
+        airways::apply_airways(&mut route, &route_string, None, Some(&library())).unwrap();
+
+        assert_eq!(idents(&route), ["SAEZ", "CIUDA", "PUMA", "SADP"]);
+        assert!(route[1].generated && !route[1].navaid);
+        // CIUDA flies at the altitude of the previous waypoint, PUMA at its own.
+        assert_eq!(route[1].altitude, 2000.0);
+        assert_eq!(route[2].altitude, 1500.0);
+        assert_eq!(
+            route[2].coordinates(),
+            library().get("PUMA").unwrap().coordinates
+        );
+    }
+}
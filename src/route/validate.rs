@@ -0,0 +1,336 @@
+use super::{geodesy, AltitudeMode, Diagnostic, Problem, Validation, Waypoint, WaypointType};
+
+/// Waypoints closer than this, in nautical miles, are at the same position.
+const SAME_POSITION_NM: f64 = 0.05;
+/// Turns sharper than this, in degrees, send the route back the way it came.
+const MAX_TURN: f64 = 150.0;
+/// Steeper climbs and descents than this, in feet per nautical mile, are impossible. It's close to
+/// a 10° flight path, twice as steep as what airliners usually climb.
+const MAX_GRADIENT: f64 = 1000.0;
+
+/// Internal function that looks for the usual mistakes of hand-edited .kml files: duplicated
+/// waypoints, loops, spikes and impossible climbs or descents. Returns the route, fixed as the
+/// `validation` options say, and the problems found.
+pub fn validate(
+    mut route: Vec<Waypoint>,
+    validation: &Validation,
+) -> (Vec<Waypoint>, Vec<Diagnostic>) {
+    let mut diagnostics = Vec::new();
+
+    // Removing the spikes first lets the duplicates they leave behind be merged.
+    remove_spikes(&mut route, validation.remove_spikes, &mut diagnostics);
+    merge_duplicates(&mut route, validation.merge_duplicates, &mut diagnostics);
+    find_loops(&route, &mut diagnostics);
+    find_gradients(&route, &mut diagnostics);
+
+    (route, diagnostics)
+}
+
+/// Internal function that finds the waypoints where the route turns back on itself, removing them
+/// when `fix` is set. Only basic waypoints are removed.
+fn remove_spikes(route: &mut Vec<Waypoint>, fix: bool, diagnostics: &mut Vec<Diagnostic>) {
+    let mut i = 1;
+
+    while i + 1 < route.len() {
+        let turn = match turn_at(route, i) {
+            Some(turn) if turn > MAX_TURN => turn,
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+
+        let fixed = fix && route[i].kind == WaypointType::Basic;
+        diagnostics.push(Diagnostic {
+            ident: route[i].ident.clone(),
+            problem: Problem::Spike(turn),
+            fixed,
+        });
+
+        if fixed {
+            route.remove(i);
+            // The previous waypoint has a new leg after it, which can be a spike too.
+            i = (i - 1).max(1);
+        } else {
+            i += 1;
+        }
+    }
+}
+
+/// Internal function that returns how many degrees the route turns at the waypoint `i`, if it
+/// makes sense to measure it.
+fn turn_at(route: &[Waypoint], i: usize) -> Option<f64> {
+    let (previous, current, next) = (&route[i - 1], &route[i], &route[i + 1]);
+
+    // Holds turn back by design, and there's no leg across a discontinuity.
+    if matches!(current.kind, WaypointType::Hold(_))
+        || [previous, current, next]
+            .iter()
+            .any(|w| w.kind == WaypointType::Discontinuity)
+    {
+        return None;
+    }
+
+    let (from, at, to) = (
+        previous.coordinates(),
+        current.coordinates(),
+        next.coordinates(),
+    );
+    if geodesy::distance_nm(from, at) < SAME_POSITION_NM
+        || geodesy::distance_nm(at, to) < SAME_POSITION_NM
+    {
+        return None;
+    }
+
+    let inbound = geodesy::final_course(from, at);
+    let outbound = geodesy::initial_course(at, to);
+
+    let turn = (outbound - inbound).rem_euclid(360.0);
+
+    Some(turn.min(360.0 - turn))
+}
+
+/// Internal function that finds the waypoints at the same position as the previous one, merging
+/// them into the previous one when `fix` is set.
+fn merge_duplicates(route: &mut Vec<Waypoint>, fix: bool, diagnostics: &mut Vec<Diagnostic>) {
+    let mut i = 1;
+
+    while i < route.len() {
+        let (previous, current) = (&route[i - 1], &route[i]);
+
+        let is_duplicate = previous.kind != WaypointType::Discontinuity
+            && current.kind != WaypointType::Discontinuity
+            && geodesy::distance_nm(previous.coordinates(), current.coordinates())
+                < SAME_POSITION_NM;
+
+        if !is_duplicate {
+            i += 1;
+            continue;
+        }
+
+        diagnostics.push(Diagnostic {
+            ident: current.ident.clone(),
+            problem: Problem::Duplicate,
+            fixed: fix,
+        });
+
+        if fix {
+            let duplicate = route.remove(i);
+            let kept = &mut route[i - 1];
+
+            // Keep a hold from either of them, and the data only the duplicate had.
+            if kept.kind == WaypointType::Basic {
+                kept.kind = duplicate.kind;
+            }
+            for (name, value) in duplicate.data {
+                if kept.data(&name).is_none() {
+                    kept.data.push((name, value));
+                }
+            }
+        } else {
+            i += 1;
+        }
+    }
+}
+
+/// Internal function that finds the named fixes the route goes through more than once.
+fn find_loops(route: &[Waypoint], diagnostics: &mut Vec<Diagnostic>) {
+    let is_fix =
+        |w: &Waypoint| !w.generated && !w.track_point && w.kind != WaypointType::Discontinuity;
+
+    for (i, waypoint) in route.iter().enumerate().filter(|(_, w)| is_fix(w)) {
+        // The previous waypoint is a duplicate, not a loop.
+        let is_loop = route[..i.saturating_sub(1)]
+            .iter()
+            .any(|w| is_fix(w) && w.ident == waypoint.ident);
+
+        if is_loop {
+            diagnostics.push(Diagnostic {
+                ident: waypoint.ident.clone(),
+                problem: Problem::Loop,
+                fixed: false,
+            });
+        }
+    }
+}
+
+/// Internal function that finds the legs that climb or descend more than any aircraft can.
+fn find_gradients(route: &[Waypoint], diagnostics: &mut Vec<Diagnostic>) {
+    for pair in route.windows(2) {
+        let (previous, current) = (&pair[0], &pair[1]);
+
+        // Altitudes relative to the ground can't be compared, and there's no leg across a
+        // discontinuity.
+        if [previous, current].iter().any(|w| {
+            w.altitude_mode != AltitudeMode::Absolute || w.kind == WaypointType::Discontinuity
+        }) {
+            continue;
+        }
+
+        let distance = geodesy::distance_nm(previous.coordinates(), current.coordinates());
+        if distance < SAME_POSITION_NM {
+            continue;
+        }
+
+        let gradient = (current.altitude - previous.altitude) / distance;
+        if gradient.abs() > MAX_GRADIENT {
+            diagnostics.push(Diagnostic {
+                ident: current.ident.clone(),
+                problem: Problem::Gradient(gradient),
+                fixed: false,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geodesy::Coordinates;
+
+    fn waypoint(ident: &str, position: Coordinates, altitude: f64) -> Waypoint {
+        let mut waypoint = Waypoint::new();
+        waypoint.ident = String::from(ident);
+        waypoint.lon = position.lon;
+        waypoint.lat = position.lat;
+        waypoint.altitude = altitude;
+        waypoint
+    }
+
+    fn point(lon: f64, lat: f64) -> Coordinates {
+        Coordinates { lon, lat }
+    }
+
+    /// A route that turns `turn` degrees to the right at `BBB`, after flying east.
+    fn turning(turn: f64) -> Vec<Waypoint> {
+        let at = point(1.0, 0.0);
+        vec![
+            waypoint("AAA", point(0.0, 0.0), 5000.0),
+            waypoint("BBB", at, 5000.0),
+            waypoint("CCC", geodesy::destination(at, 90.0 + turn, 30.0), 5000.0),
+        ]
+    }
+
+    fn problems(diagnostics: &[Diagnostic]) -> Vec<(&str, Problem, bool)> {
+        diagnostics
+            .iter()
+            .map(|d| (d.ident.as_str(), d.problem, d.fixed))
+            .collect()
+    }
+
+    #[test]
+    fn finds_duplicates_closer_than_the_same_position() {
+        let start = point(0.0, 0.0);
+        let route = |distance| {
+            vec![
+                waypoint("AAA", start, 5000.0),
+                waypoint("BBB", geodesy::destination(start, 90.0, distance), 5000.0),
+            ]
+        };
+
+        let (_, diagnostics) = validate(route(SAME_POSITION_NM * 0.8), &Validation::default());
+        assert_eq!(problems(&diagnostics), [("BBB", Problem::Duplicate, false)]);
+
+        let (_, diagnostics) = validate(route(SAME_POSITION_NM * 1.2), &Validation::default());
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn merges_duplicates_when_asked() {
+        let start = point(0.0, 0.0);
+        let mut duplicate = waypoint("AAA2", start, 5000.0);
+        duplicate
+            .data
+            .push((String::from("speed"), String::from("250")));
+        let route = vec![waypoint("AAA", start, 5000.0), duplicate];
+        let validation = Validation {
+            merge_duplicates: true,
+            ..Default::default()
+        };
+
+        let (route, diagnostics) = validate(route, &validation);
+        assert_eq!(problems(&diagnostics), [("AAA2", Problem::Duplicate, true)]);
+        assert_eq!(route.len(), 1);
+        assert_eq!(route[0].ident, "AAA");
+        assert_eq!(route[0].data("speed"), Some("250"));
+    }
+
+    #[test]
+    fn finds_spikes_sharper_than_the_max_turn() {
+        let (_, diagnostics) = validate(turning(MAX_TURN - 1.0), &Validation::default());
+        assert!(diagnostics.is_empty());
+
+        let (route, diagnostics) = validate(turning(MAX_TURN + 1.0), &Validation::default());
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].ident, "BBB");
+        assert!(
+            matches!(diagnostics[0].problem, Problem::Spike(turn) if (turn - 151.0).abs() < 0.1)
+        );
+        assert_eq!(route.len(), 3);
+    }
+
+    #[test]
+    fn removes_spikes_when_asked() {
+        let validation = Validation {
+            remove_spikes: true,
+            ..Default::default()
+        };
+
+        let (route, diagnostics) = validate(turning(175.0), &validation);
+        assert!(diagnostics[0].fixed);
+        let idents: Vec<&str> = route.iter().map(|w| w.ident.as_str()).collect();
+        assert_eq!(idents, ["AAA", "CCC"]);
+    }
+
+    #[test]
+    fn finds_loops_through_named_fixes() {
+        let route = vec![
+            waypoint("AAA", point(0.0, 0.0), 5000.0),
+            waypoint("BBB", point(1.0, 0.0), 5000.0),
+            waypoint("CCC", point(1.0, 1.0), 5000.0),
+            waypoint("AAA", point(0.0, 0.0), 5000.0),
+        ];
+
+        let (_, diagnostics) = validate(route, &Validation::default());
+        assert_eq!(problems(&diagnostics), [("AAA", Problem::Loop, false)]);
+    }
+
+    #[test]
+    fn finds_gradients_steeper_than_the_max_gradient() {
+        let route = |climb: f64, altitude_mode| {
+            let mut route = vec![
+                waypoint("AAA", point(0.0, 0.0), 1000.0),
+                waypoint(
+                    "BBB",
+                    geodesy::destination(point(0.0, 0.0), 90.0, 10.0),
+                    1000.0,
+                ),
+            ];
+            route[1].altitude += climb * 10.0;
+            route[1].altitude_mode = altitude_mode;
+            route
+        };
+
+        let (_, diagnostics) = validate(
+            route(MAX_GRADIENT * 0.99, AltitudeMode::Absolute),
+            &Validation::default(),
+        );
+        assert!(diagnostics.is_empty());
+
+        for climb in [MAX_GRADIENT * 1.01, -MAX_GRADIENT * 1.01] {
+            let (_, diagnostics) =
+                validate(route(climb, AltitudeMode::Absolute), &Validation::default());
+            assert_eq!(diagnostics.len(), 1);
+            assert!(
+                matches!(diagnostics[0].problem, Problem::Gradient(g) if (g - climb).abs() < 1.0)
+            );
+        }
+
+        // Altitudes relative to the ground can't be compared.
+        let (_, diagnostics) = validate(
+            route(MAX_GRADIENT * 2.0, AltitudeMode::RelativeToGround),
+            &Validation::default(),
+        );
+        assert!(diagnostics.is_empty());
+    }
+}
@@ -1,17 +1,65 @@
-use std::{error::Error, fs::File, io::BufReader, path::PathBuf};
+use std::{
+    error::Error,
+    fs,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
 
 /// The library crate to perform the actual operations
-use kml_to_fgfp::{self, Airport, EmitterConfig, EventReader};
+use kml_to_fgfp::{
+    self,
+    magnetic::{self, MagneticModel},
+    metar::{self, Metar},
+    navdata::{
+        AirportDatabase, AirportProcedures, AirwayDatabase, FixDatabase, ProcedureFix,
+        ProcedureKind, RunwayThresholds, UserWaypoint, UserWaypoints,
+    },
+    Airport, Densify, EmitterConfig, EventReader, FixLookup, Report, RouteOptions, Simplify,
+    SpeedRestrict, StepAt, StepClimb,
+};
 
 /// The config for the transformation of the .kml file into .fgfp. Taken as an argument by the
 /// [`run`](run) function.
 ///
 /// You can create an instance of this struct using [`Config::build()`](Config::build).
 pub struct Config {
-    input: PathBuf,
-    output: PathBuf,
-    departure: Option<String>,
-    destination: Option<String>,
+    /// The input and output files, which aren't needed to edit the user's waypoints.
+    files: Option<(PathBuf, PathBuf)>,
+    departure: Option<Airport>,
+    destination: Option<Airport>,
+    options: RouteOptions,
+    print_legs: bool,
+    /// FlightGear's `$FG_ROOT`, to check the airports with its navigation data.
+    fg_root: Option<PathBuf>,
+    /// FlightGear's scenery directories, to find the runway thresholds.
+    scenery: Vec<PathBuf>,
+    /// The METARs of the airports, to choose their runways.
+    metars: Vec<Metar>,
+    /// How far the waypoints can be from FlightGear's fixes, when they're looked up.
+    fix_tolerance: Option<f64>,
+    /// Move the waypoints to FlightGear's fixes.
+    snap: bool,
+    /// Insert the fixes along the airways of the route string.
+    airways: bool,
+    /// The SID, STAR and approach, with their transitions, like `ANDO1A.ARSOT`.
+    procedures: Vec<(ProcedureKind, String)>,
+    /// Print the procedures of the airports.
+    list_procedures: bool,
+    /// Write the fixes of the procedures as waypoints.
+    expand_procedures: bool,
+    /// The user's library of waypoints.
+    user_waypoints: Option<PathBuf>,
+    /// The changes to the user's library of waypoints, in order.
+    waypoint_edits: Vec<WaypointEdit>,
+    /// Print the user's library of waypoints.
+    list_waypoints: bool,
+}
+
+/// A change to the user's library of waypoints.
+enum WaypointEdit {
+    Add(UserWaypoint),
+    Remove(String),
 }
 
 impl Config {
@@ -28,25 +76,269 @@ impl Config {
     pub fn build(mut args: impl Iterator<Item = String>) -> Result<Config, Box<dyn Error>> {
         args.next();
 
-        let input = match args.next() {
-            Some(path) => PathBuf::from(path),
-            _ => return Err("Didn't get an input file".into()),
-        };
+        let mut positional = Vec::new();
+        let mut options = RouteOptions::default();
+        let mut print_legs = false;
+        let mut fg_root = None;
+        let mut scenery = Vec::new();
+        let mut metars = Vec::new();
+        let mut fix_tolerance = None;
+        let mut snap = false;
+        let mut airways = false;
+        let mut procedures = Vec::new();
+        let mut list_procedures = false;
+        let mut expand_procedures = false;
+        let mut user_waypoints = None;
+        let mut waypoint_edits = Vec::new();
+        let mut list_waypoints = false;
+        let mut wmm = None;
+
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                positional.push(arg);
+                continue;
+            }
+
+            // Options can be given as `--option=value` or `--option value`.
+            let (option, inline_value) = match arg.split_once('=') {
+                Some((option, value)) => (option.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+
+            match option.as_str() {
+                "--alt-restrict" => {
+                    let value = option_value(&option, inline_value, &mut args)?;
+                    options.alt_restrict = match value.as_str() {
+                        "auto" => None,
+                        value => Some(value.parse()?),
+                    };
+                }
+                "--speed-limit" => {
+                    let value = option_value(&option, inline_value, &mut args)?;
+                    options.speed_limit = Some(speed_limit_decoder(&value)?);
+                }
+                "--speed" => {
+                    let value = option_value(&option, inline_value, &mut args)?;
+                    let (ident, restriction) = match value.split_once(':') {
+                        Some((ident, restriction)) => (ident.trim(), restriction.parse()?),
+                        None => return Err(format!("Invalid speed rule `{value}`").into()),
+                    };
+                    options.speed_rules.push((String::from(ident), restriction));
+                }
+                "--route" => {
+                    options.route_string = Some(option_value(&option, inline_value, &mut args)?);
+                }
+                "--expand-airways" => options.expand_airways = true,
+                "--hold" => {
+                    let value = option_value(&option, inline_value, &mut args)?;
+                    let (ident, hold) = match value.split_once(':') {
+                        Some((ident, hold)) => (ident.trim(), hold.parse()?),
+                        None => return Err(format!("Invalid hold `{value}`").into()),
+                    };
+                    options.holds.push((String::from(ident), hold));
+                }
+                "--discontinuity" => {
+                    let value = option_value(&option, inline_value, &mut args)?;
+                    options.discontinuities.push(value);
+                }
+                "--target-fg" => {
+                    options.target = option_value(&option, inline_value, &mut args)?.parse()?;
+                }
+                "--legs" => print_legs = true,
+                "--paths" => options.read_paths = true,
+                "--airport-radius" => {
+                    let value = option_value(&option, inline_value, &mut args)?;
+                    options.airport_radius_nm = Some(value.parse()?);
+                }
+                "--merge-duplicates" => options.validation.merge_duplicates = true,
+                "--remove-spikes" => options.validation.remove_spikes = true,
+                "--strict" => options.validation.strict = true,
+                "--tops" => options.insert_tops = true,
+                "--cruise" => {
+                    let value = option_value(&option, inline_value, &mut args)?;
+                    let altitude = altitude_decoder(&value)?;
+                    if altitude <= 0.0 {
+                        return Err(format!(
+                            "Invalid cruise altitude `{value}`, it has to be above sea level"
+                        )
+                        .into());
+                    }
+                    options.cruise_altitude = Some(altitude);
+                }
+                "--transition" => {
+                    let value = option_value(&option, inline_value, &mut args)?;
+                    let altitude = altitude_decoder(&value)?;
+                    if !(1000.0..=20000.0).contains(&altitude) {
+                        return Err(format!(
+                            "Invalid transition altitude `{value}`, it has to be between 1000 and 20000 ft"
+                        )
+                        .into());
+                    }
+                    options.transition_altitude = Some(altitude);
+                }
+                "--step" => {
+                    let value = option_value(&option, inline_value, &mut args)?;
+                    options.step_climbs.push(step_climb_decoder(&value)?);
+                }
+                "--profile" => {
+                    let value = option_value(&option, inline_value, &mut args)?;
+                    options.vertical_profile = value.parse()?;
+                }
+                "--simplify" => {
+                    let value = option_value(&option, inline_value, &mut args)?;
+                    options.simplify = Some(simplify_decoder(&value)?);
+                }
+                "--round" => {
+                    let value = option_value(&option, inline_value, &mut args)?;
+                    options.altitude_rounding = value.parse()?;
+                }
+                "--densify" => {
+                    let value = option_value(&option, inline_value, &mut args)?;
+                    options.densify = Some(densify_decoder(&value)?);
+                }
+                "--date" => {
+                    let value = option_value(&option, inline_value, &mut args)?;
+                    options.date = Some(magnetic::decimal_year(&value)?);
+                }
+                "--fg-root" => {
+                    let value = option_value(&option, inline_value, &mut args)?;
+                    fg_root = Some(PathBuf::from(value));
+                }
+                "--scenery" => {
+                    let value = option_value(&option, inline_value, &mut args)?;
+                    scenery.push(PathBuf::from(value));
+                }
+                "--metar" => {
+                    let value = option_value(&option, inline_value, &mut args)?;
+                    metars.extend(metar_decoder(&value)?);
+                }
+                "--fixes" => {
+                    let value = option_value(&option, inline_value, &mut args)?;
+                    fix_tolerance = Some(value.parse()?);
+                }
+                "--snap" => snap = true,
+                "--airways" => airways = true,
+                "--sid" => {
+                    let value = option_value(&option, inline_value, &mut args)?;
+                    procedures.push((ProcedureKind::Sid, value));
+                }
+                "--star" => {
+                    let value = option_value(&option, inline_value, &mut args)?;
+                    procedures.push((ProcedureKind::Star, value));
+                }
+                "--approach" => {
+                    let value = option_value(&option, inline_value, &mut args)?;
+                    procedures.push((ProcedureKind::Approach, value));
+                }
+                "--procedures" => list_procedures = true,
+                "--expand-procedures" => expand_procedures = true,
+                "--user-waypoints" => {
+                    let value = option_value(&option, inline_value, &mut args)?;
+                    user_waypoints = Some(PathBuf::from(value));
+                }
+                "--list-waypoints" => list_waypoints = true,
+                "--add-waypoint" => {
+                    let value = option_value(&option, inline_value, &mut args)?;
+                    waypoint_edits.push(WaypointEdit::Add(value.parse()?));
+                }
+                "--remove-waypoint" => {
+                    let value = option_value(&option, inline_value, &mut args)?;
+                    waypoint_edits.push(WaypointEdit::Remove(value));
+                }
+                "--wmm" => {
+                    let value = option_value(&option, inline_value, &mut args)?;
+                    wmm = Some(MagneticModel::from_cof(&fs::read_to_string(value)?)?);
+                }
+                _ => return Err(format!("Unknown option `{option}`").into()),
+            }
+        }
 
-        let output = match args.next() {
-            Some(path) => PathBuf::from(path),
-            _ => return Err("Didn't get a destination directory".into()),
+        // Without a coefficient file, use the embedded model for the date of the flight.
+        options.magnetic_model = wmm.unwrap_or_else(|| {
+            MagneticModel::embedded(options.date.unwrap_or_else(magnetic::current_year))
+        });
+
+        let editing = list_waypoints || !waypoint_edits.is_empty();
+        // The step climbs are climbs from the cruise altitude.
+        if let Some(cruise) = options.cruise_altitude {
+            if let Some(step) = options.step_climbs.iter().find(|s| s.altitude < cruise) {
+                return Err(format!(
+                    "The step climb to {} ft is below the cruise altitude of {cruise} ft",
+                    step.altitude
+                )
+                .into());
+            }
+        }
+
+        if editing && user_waypoints.is_none() {
+            return Err(
+                "Editing the waypoints needs their library, given with --user-waypoints".into(),
+            );
+        }
+
+        let mut positional = positional.into_iter();
+
+        let files = match (positional.next(), positional.next()) {
+            (Some(input), Some(output)) => Some((PathBuf::from(input), PathBuf::from(output))),
+            (None, _) if editing => None,
+            (None, _) => return Err("Didn't get an input file".into()),
+            (Some(_), None) => return Err("Didn't get a destination directory".into()),
         };
 
-        let departure = args.next();
+        let mut departure: Option<Airport> = positional.next().map(|ap| ap.parse()).transpose()?;
+
+        let mut destination: Option<Airport> =
+            positional.next().map(|ap| ap.parse()).transpose()?;
 
-        let destination = args.next();
+        // The procedures can also be given with the airports, like `SAEZ/11/ANDO1A.ARSOT`.
+        if let Some(airport) = departure.as_mut() {
+            if airport.approach.is_some() {
+                return Err(format!(
+                    "The departure airport {} can't have an approach",
+                    airport.ident
+                )
+                .into());
+            }
+            if let Some(name) = airport.procedure.take() {
+                let sid = match airport.transition.take() {
+                    Some(transition) => format!("{name}.{transition}"),
+                    None => name,
+                };
+                add_procedure(&mut procedures, ProcedureKind::Sid, sid)?;
+            }
+        }
+
+        if let Some(airport) = destination.as_mut() {
+            if let Some(name) = airport.procedure.take() {
+                let star = match airport.transition.take() {
+                    Some(transition) => format!("{transition}.{name}"),
+                    None => name,
+                };
+                add_procedure(&mut procedures, ProcedureKind::Star, star)?;
+            }
+            if let Some(approach) = airport.approach.take() {
+                add_procedure(&mut procedures, ProcedureKind::Approach, approach)?;
+            }
+        }
 
         Ok(Config {
-            input,
-            output,
+            files,
             departure,
             destination,
+            options,
+            print_legs,
+            fg_root,
+            scenery,
+            metars,
+            fix_tolerance,
+            snap,
+            airways,
+            procedures,
+            list_procedures,
+            expand_procedures,
+            user_waypoints,
+            waypoint_edits,
+            list_waypoints,
         })
     }
 
@@ -62,15 +354,155 @@ impl Config {
         eprint!(
             "\
 Usage:
-      \x1B[01m{} INPUT OUTPUT [DEPARTURE_AIRPORT] [DESTINATION AIRPORT]\x1B[00m\n
+      \x1B[01m{} [OPTIONS] INPUT OUTPUT [DEPARTURE_AIRPORT] [DESTINATION AIRPORT]\x1B[00m\n
 INPUT is the Google Earth (.kml) file.\n
 OUTPUT is the name of the generated FlightGear flight plan (.fgfp) file.\n
 [DEPARTURE_AIRPORT] is an optional argument detailing the departure airport's
 ICAO designation. It would look something like `SAEZ`. You can also type a `/`
-to add a specific runway, so it would look like `SAEZ/11`.\n
+to add a specific runway, so it would look like `SAEZ/11`, and another to add
+the SID, like `SAEZ/11/ANDO1A.ARSOT`. Without it, the airport is found in the
+.kml file when SimBrief marks it.\n
 [DESTINATION_AIRPORT] is an optional argument detailing the destination
 airport's ICAO designation. It would look something like `YSSY`. You can also
-type a `/` to add a specific runway, so it would look like `YSSY/34L`.\n
+type a `/` to add a specific runway, so it would look like `YSSY/34L`, and more
+to add the STAR and the approach, like `YSSY/34L/ARDO1B.GESOL/ILS34L`. Without
+it, the airport is found in the .kml file when SimBrief marks it.\n
+When the .kml file has the airports, the ones given must be the same, and they
+only set the runways and procedures.\n
+Options:
+  --alt-restrict=RESTRICTION
+      The altitude restriction for every waypoint: `at`, `above`, `below` or
+      `none`. The default, `auto`, uses `above` while climbing, `below` while
+      descending and `at` while cruising. A Placemark's ExtendedData can set its
+      own with an `alt-restrict` field.\n
+  --speed-limit=SPEED/ALTITUDE
+      Limit the speed of every waypoint below ALTITUDE to SPEED knots. For
+      example, `250/FL100` or `250/10000`.\n
+  --speed=IDENT:RESTRICTION
+      Set the speed restriction of the IDENT waypoint, it can be used many
+      times. For example, `ARSOT:250`, `ARSOT:below 250` or `ARSOT:M0.78`. A
+      Placemark's ExtendedData can set its own with a `speed` field.\n
+  --route=ROUTE
+      The route string of the flight plan, like `SAEZ DCT EZE11 UM540 ARSOT`.
+      The fixes reached along an airway are written as FlightGear `via`
+      waypoints. A Placemark's ExtendedData can also set the airway used to
+      reach it with an `airway` field.\n
+  --expand-airways
+      Write every fix along the airways as a basic waypoint instead.\n
+  --hold=IDENT:HOLD
+      Hold at the IDENT waypoint, it can be used many times. HOLD is the
+      inbound course, the direction of the turns and the length of the legs, like
+      `270/R/1MIN` or `090/L/4NM`. A Placemark's ExtendedData can set its own
+      with a `hold` field.\n
+  --discontinuity=IDENT
+      Add a route discontinuity after the IDENT waypoint, it can be used many
+      times. Placemarks named `DISCON` are also discontinuities.\n
+  --target-fg=VERSION
+      The FlightGear release the flight plan is for: `2018.3`, `2020.3`, `2024.1`
+      (the default) or `legacy`, for the version-1 route format. A warning is
+      printed for features the release can't represent.\n
+  --legs
+      Print a table with the distance (NM), true and magnetic courses of every
+      leg of the route, and the total distance flown.\n
+  --date=YYYY-MM-DD
+      The date of the flight, used for the magnetic variation. The default is
+      today.\n
+  --wmm=FILE
+      A `WMM.COF` coefficient file from NOAA, to use instead of the embedded
+      WMM2020 and WMM2025 models.\n
+  --densify=NM[/DEGREES]
+      Insert waypoints along the great circle of direct legs longer than NM
+      nautical miles or, if given, whose true course changes more than DEGREES.\n
+  --paths
+      Read the paths in the .kml file, like hand-drawn paths or recorded tracks,
+      as waypoints named after the path: `Path001`, `Path002`, ...\n
+  --airport-radius=NM
+      Drop the placemarks within NM nautical miles of the departure or
+      destination airport, like a fix on the runway. Placemarks named like the
+      airport, by its ICAO or IATA code, are always dropped.\n
+  --simplify=NM[/FT]
+      Remove the points of paths that don't move the route more than NM nautical
+      miles sideways or, if given, FT feet up or down. Named fixes are always
+      kept.\n
+  --round=none|10|100|500|fl
+      How the altitudes are rounded, to 10, 100 or 500 feet, to whole flight
+      levels, or not at all. The default is 100 feet.\n
+  --merge-duplicates
+      Merge consecutive waypoints at the same position into one.\n
+  --remove-spikes
+      Remove the waypoints where the route turns back on itself.\n
+  --strict
+      Don't write the route if it has duplicated waypoints, loops, spikes or
+      impossible climbs and descents, even if they were fixed.\n
+  --tops
+      Insert the top of climb and the top of descent in the route, as the
+      waypoints `T/C` and `T/D`.\n
+  --profile=CLIMB[/DESCENT]
+      How steep the route climbs and descends, in feet per nautical mile or,
+      with the `deg` suffix, as the angle of the path. The default is 3deg.\n
+  --cruise=ALT
+      Fly the en-route part of the route at ALT, in feet or as a flight level
+      like FL370, instead of the altitudes of the .kml file.\n
+  --step=IDENT/ALT or --step=NM/ALT
+      Step climb to ALT after the waypoint IDENT, or from NM nautical miles of
+      the origin, like `ARSOT/FL390` or `1200NM/FL390`. It can be used many
+      times, and the steps can't be below --cruise.\n
+  --transition=ALT
+      The transition altitude, above which altitudes are flight levels, between
+      1000 and 20000 ft. The default is the usual one in the country of the
+      departure airport.\n
+  --fg-root=DIR
+      FlightGear's data directory, to check the airports and runways in
+      `Airports/apt.dat.gz` and find their positions.\n
+  --scenery=DIR
+      A FlightGear scenery directory, like TerraSync's, to find the exact runway
+      thresholds and headings in `Airports/S/A/E/SAEZ.threshold.xml`, and the
+      procedures in `SAEZ.procedures.xml`. It can be used many times, and
+      --fg-root is searched after it.\n
+  --metar=METAR
+      The METAR of the departure or destination, or a file with METARs, like
+      `SAEZ 011200Z 25015KT CAVOK`. The airports given without a runway get the
+      one with the most headwind, from --scenery or --fg-root. It can be used
+      many times.\n
+  --fixes=NM
+      Look up the waypoints in FlightGear's `Navaids/fix.dat.gz` and
+      `nav.dat.gz`, warning about the ones that aren't there or are further than
+      NM nautical miles. It needs --fg-root.\n
+  --snap
+      Move the waypoints found by --fixes to FlightGear's position, and write
+      them as navaids. Without --fixes, they can be 1 nautical mile away.\n
+  --airways
+      Insert the fixes along the airways of --route, found in FlightGear's
+      `Navaids/awy.dat.gz`. The exit fixes don't need to be in the .kml file.
+      It needs --fg-root.\n
+  --sid=NAME[.TRANSITION]
+      The SID of the departure, like `ANDO1A` or `ANDO1A.ARSOT`, checked in
+      `Airports/S/A/E/SAEZ.procedures.xml` of --scenery or --fg-root.\n
+  --star=[TRANSITION.]NAME
+      The STAR of the destination, like `ARDO1B` or `GESOL.ARDO1B`. It needs
+      --scenery or --fg-root.\n
+  --approach=[TRANSITION.]NAME
+      The approach to the destination, like `ILS11`. It needs --scenery or
+      --fg-root.\n
+  --procedures
+      Print the SIDs, STARs and approaches of the airports, with their runways
+      and transitions. It needs --scenery or --fg-root.\n
+  --expand-procedures
+      Write the fixes of --sid, --star and --approach as waypoints, for FMSs
+      that can't load procedures, instead of naming them in the flight plan.\n
+  --user-waypoints=FILE
+      A CSV library of your own waypoints, with the columns
+      `ident,lat,lon,altitude,notes`. Placemarks named like them are moved to
+      their position, --route can use them without a Placemark, and they take
+      priority over FlightGear's fixes for --fixes and --snap.\n
+  --list-waypoints
+      Print the waypoints of --user-waypoints. Without INPUT and OUTPUT, nothing
+      else is done.\n
+  --add-waypoint=IDENT,LAT,LON[,ALTITUDE[,NOTES]]
+      Add a waypoint to --user-waypoints, or replace the one with the same
+      ident. It can be used many times.\n
+  --remove-waypoint=IDENT
+      Remove a waypoint from --user-waypoints. It can be used many times.\n
 Version: {}, {} License
 ",
             env!("CARGO_PKG_NAME"),
@@ -85,50 +517,760 @@ Version: {}, {} License
 ///
 /// # Errors
 /// This function can fail if some part of the process tries to write invalid xml or for io errors.
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
+pub fn run(mut config: Config) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = &config.user_waypoints {
+        let library = edit_user_waypoints(path, &config.waypoint_edits, config.list_waypoints)?;
+        config.options.user_waypoints = Some(library);
+    }
+
+    let (input, output) = match config.files {
+        Some(files) => files,
+        None => return Ok(()),
+    };
+
+    // Check the airports before creating the output file, so a typo doesn't leave it empty. The
+    // ones that are only in the .kml file are checked too.
+    let parser = EventReader::new(BufReader::new(File::open(&input)?));
+    let (mut departure, mut destination) = kml_to_fgfp::find_airports(
+        parser,
+        &config.departure,
+        &config.destination,
+        config.options.read_paths,
+    )?;
+    let from_kml = [
+        config.departure.is_none() && departure.is_some(),
+        config.destination.is_none() && destination.is_some(),
+    ];
+
+    let airports = match &config.fg_root {
+        Some(fg_root) => Some(AirportDatabase::from_apt_dat(
+            &fg_root.join("Airports/apt.dat.gz"),
+        )?),
+        None => None,
+    };
+
+    if let Some(airports) = &airports {
+        for (airport, from_kml) in [
+            (&mut departure, from_kml[0]),
+            (&mut destination, from_kml[1]),
+        ] {
+            let airport = match airport {
+                Some(airport) => airport,
+                None => continue,
+            };
+
+            match airports.resolve(airport) {
+                // The .kml file can have airports FlightGear doesn't know, at their Placemark.
+                Err(e) if from_kml => eprintln!("\x1B[01;33mWarning\x1B[00m: {e}"),
+                result => result?,
+            }
+        }
+    }
+
+    // The thresholds of the scenery are more exact than the runways of `apt.dat`.
+    let scenery: Vec<PathBuf> = config
+        .scenery
+        .iter()
+        .chain(&config.fg_root)
+        .cloned()
+        .collect();
+
+    for airport in departure.iter_mut().chain(destination.iter_mut()) {
+        if airport.runway.is_some() {
+            continue;
+        }
+
+        if let Some(metar) = config.metars.iter().find(|m| m.station == airport.ident) {
+            choose_runway(airport, metar, airports.as_ref(), &scenery)?;
+        }
+    }
+
+    for (airport, landing) in [(&mut departure, false), (&mut destination, true)] {
+        let airport = match airport.as_mut().filter(|ap| ap.runway.is_some()) {
+            Some(airport) => airport,
+            None => continue,
+        };
+
+        match RunwayThresholds::find_in(&scenery, &airport.ident)? {
+            Some(thresholds) => {
+                if let Err(e) = thresholds.resolve(airport, landing) {
+                    eprintln!("\x1B[01;33mWarning\x1B[00m: {e}");
+                }
+            }
+            None if !config.scenery.is_empty() => eprintln!(
+                "\x1B[01;33mWarning\x1B[00m: the scenery has no runway thresholds for {}",
+                airport.ident
+            ),
+            None => (),
+        }
+    }
+
+    if !config.procedures.is_empty() || config.list_procedures {
+        if scenery.is_empty() {
+            return Err(
+                "Checking the procedures needs FlightGear's data, given with --fg-root or --scenery"
+                    .into(),
+            );
+        }
+
+        let (sid, arrival): (Vec<_>, Vec<_>) = config
+            .procedures
+            .iter()
+            .partition(|(kind, _)| *kind == ProcedureKind::Sid);
+
+        let (list, expand) = (config.list_procedures, config.expand_procedures);
+        if list {
+            println!(
+                "{:<8} {:<8} {:<8} {:<16} TRANSITIONS",
+                "AIRPORT", "KIND", "NAME", "RUNWAYS"
+            );
+        }
+
+        config.options.departure_procedure =
+            apply_procedures(&scenery, &mut departure, "departure", &sid, list, expand)?;
+        config.options.arrival_procedure = apply_procedures(
+            &scenery,
+            &mut destination,
+            "destination",
+            &arrival,
+            list,
+            expand,
+        )?;
+    }
+
+    if config.fix_tolerance.is_some() || config.snap || config.airways {
+        // The user's waypoints can be looked up without FlightGear's data, but the airways can't.
+        let mut database = match (&config.fg_root, &config.options.user_waypoints) {
+            (Some(fg_root), _) => FixDatabase::from_fg_root(fg_root)?,
+            (None, Some(_)) if !config.airways => FixDatabase::default(),
+            (None, _) => {
+                return Err(
+                    "Looking up the fixes needs FlightGear's data, given with --fg-root".into(),
+                )
+            }
+        };
+
+        if let (true, Some(fg_root)) = (config.airways, &config.fg_root) {
+            config.options.airways = Some(AirwayDatabase::from_fg_root(fg_root, &database)?);
+        }
+
+        if let Some(library) = &config.options.user_waypoints {
+            database.add_user_waypoints(library);
+        }
+
+        if config.fix_tolerance.is_some() || config.snap {
+            config.options.fixes = Some(FixLookup {
+                database,
+                tolerance_nm: config.fix_tolerance.unwrap_or(1.0),
+                snap: config.snap,
+            });
+        }
+    }
+
     // Create the writer object.
-    let mut output_file = File::create(config.output)?;
+    let mut output_file = File::create(&output)?;
     let mut writer = EmitterConfig::new()
         .perform_indent(true)
         .indent_string("\t")
         .create_writer(&mut output_file);
 
     // 1. Write the beginning of the tree.
-    kml_to_fgfp::write_start_of_tree(&mut writer)?;
-
-    // 2. Write the destination and arrival airports.
-    let departure = config.departure.map(|ap| airport_decoder(&ap));
-
-    let destination = config.destination.map(|ap| airport_decoder(&ap));
-
-    kml_to_fgfp::write_airports(&mut writer, &departure, &destination)?;
+    kml_to_fgfp::write_start_of_tree(&mut writer, config.options.target)?;
 
     // Create the reader object.
-    let input_file = File::open(config.input)?;
+    let input_file = File::open(input)?;
     let input_file = BufReader::new(input_file);
     let parser = EventReader::new(input_file);
 
-    // 3. Transform the route in the .kml to .fgfp.
-    kml_to_fgfp::transform_route(parser, &mut writer, &departure, &destination)?;
+    // 2. Transform the route in the .kml to .fgfp.
+    let report = match kml_to_fgfp::transform_route(
+        parser,
+        &mut writer,
+        &departure,
+        &destination,
+        &config.options,
+    ) {
+        Ok(report) => report,
+        Err(e) => {
+            // Don't leave half a route behind.
+            drop(writer);
+            fs::remove_file(&output)?;
+            return Err(e);
+        }
+    };
 
-    // 4. Close the xml tree.
+    // 3. Write the departure and destination airports, which can come from the .kml.
+    kml_to_fgfp::write_airports(&mut writer, &report.departure, &report.destination)?;
+
+    // 4. Write the cruise altitude and close the xml tree.
+    kml_to_fgfp::write_cruise(&mut writer, &report, config.options.target)?;
     kml_to_fgfp::close_tree(&mut writer)?;
 
+    for airport in report.departure.iter().chain(&report.destination) {
+        if let Some(wind) = &airport.wind {
+            eprintln!(
+                "\x1B[01mRunway {}\x1B[00m at {} for the wind: {}",
+                wind.runway, airport.ident, wind
+            );
+        }
+    }
+
+    if config.options.simplify.is_some() {
+        eprintln!(
+            "\x1B[01mSimplified\x1B[00m the route: removed {} points",
+            report.simplified
+        );
+    }
+
+    if config.print_legs {
+        print_legs(&report);
+    }
+
+    Ok(())
+}
+
+/// Prints a table with the legs of the route to stdout. Generated waypoints are marked with a `*`,
+/// and legs crossing the antimeridian with `±180`.
+fn print_legs(report: &Report) {
+    println!(
+        "{:<8} {:<8} {:>8} {:>6} {:>6} {:>6} {:>6} {:>8}",
+        "FROM", "TO", "DIST", "TC", "TC-END", "MC", "MC-END", "TOTAL"
+    );
+
+    let mark = |ident: &str| {
+        if report.generated.iter().any(|g| g == ident) {
+            format!("{ident}*")
+        } else {
+            String::from(ident)
+        }
+    };
+
+    for leg in &report.legs {
+        println!(
+            "{:<8} {:<8} {:>8.1} {:>6.0} {:>6.0} {:>6.0} {:>6.0} {:>8.1}{}",
+            mark(&leg.from),
+            mark(&leg.to),
+            leg.distance_nm,
+            leg.initial_course,
+            leg.final_course,
+            leg.initial_magnetic_course,
+            leg.final_magnetic_course,
+            leg.cumulative_nm,
+            if leg.crosses_antimeridian {
+                " ±180"
+            } else {
+                ""
+            }
+        );
+    }
+
+    if !report.generated.is_empty() {
+        println!("* Generated waypoint ({} in total)", report.generated.len());
+    }
+    if report.legs.iter().any(|leg| leg.crosses_antimeridian) {
+        println!("±180 The leg crosses the antimeridian");
+    }
+
+    if let Some(altitude) = report.cruise_altitude {
+        println!("Cruise at {}", report.format_altitude(altitude));
+    }
+    if let Some(distance) = report.top_of_climb_nm {
+        println!("T/C {distance:.1} NM from the origin");
+    }
+    if let Some(distance) = report.top_of_descent_nm {
+        println!("T/D {distance:.1} NM to the destination");
+    }
+}
+
+/// Gets the value of an option, either from the `--option=value` form or from the next argument.
+fn option_value(
+    option: &str,
+    inline_value: Option<String>,
+    args: &mut impl Iterator<Item = String>,
+) -> Result<String, Box<dyn Error>> {
+    match inline_value.or_else(|| args.next()) {
+        Some(value) => Ok(value),
+        None => Err(format!("Didn't get a value for `{option}`").into()),
+    }
+}
+
+/// Decodes simplification tolerances such as `0.5` or `0.5/300` into the horizontal tolerance in
+/// nautical miles and, optionally, the vertical tolerance in feet.
+fn simplify_decoder(code: &str) -> Result<Simplify, Box<dyn Error>> {
+    let (tolerance_nm, tolerance_ft) = match code.split_once('/') {
+        Some((nm, ft)) => (nm.trim().parse()?, ft.trim().parse()?),
+        None => (code.trim().parse()?, f64::INFINITY),
+    };
+
+    if tolerance_nm <= 0.0 || tolerance_ft <= 0.0 {
+        return Err(format!("Invalid simplification tolerances `{code}`").into());
+    }
+
+    Ok(Simplify {
+        tolerance_nm,
+        tolerance_ft,
+    })
+}
+
+/// Decodes a densification rule such as `200` or `200/5` into the longest leg in nautical miles
+/// and, optionally, the largest track change in degrees.
+fn densify_decoder(code: &str) -> Result<Densify, Box<dyn Error>> {
+    let (max_leg_nm, max_track_change) = match code.split_once('/') {
+        Some((nm, degrees)) => (nm.trim().parse()?, Some(degrees.trim().parse()?)),
+        None => (code.trim().parse()?, None),
+    };
+
+    if max_leg_nm <= 0.0 || max_track_change.is_some_and(|d: f64| d <= 0.0) {
+        return Err(format!("Invalid densification rule `{code}`").into());
+    }
+
+    Ok(Densify {
+        max_leg_nm,
+        max_track_change,
+    })
+}
+
+/// Decodes a speed limit such as `250/FL100` into a speed restriction and an altitude in feet.
+fn speed_limit_decoder(code: &str) -> Result<(SpeedRestrict, f64), Box<dyn Error>> {
+    let (speed, altitude) = match code.split_once('/') {
+        Some(data) => data,
+        None => return Err(format!("Invalid speed limit `{code}`").into()),
+    };
+
+    let speed = SpeedRestrict::Below(speed.trim().parse()?);
+
+    Ok((speed, altitude_decoder(altitude)?))
+}
+
+/// Decodes an altitude given in feet or as a flight level, such as `FL370`, into feet.
+fn altitude_decoder(code: &str) -> Result<f64, Box<dyn Error>> {
+    let altitude = code.trim().to_uppercase();
+
+    let altitude = match altitude.strip_prefix("FL") {
+        Some(level) => level.parse::<f64>()? * 100.0,
+        None => altitude.parse()?,
+    };
+
+    Ok(altitude)
+}
+
+/// Decodes a step climb such as `ARSOT/FL390` or `1200NM/FL390` into a
+/// [`StepClimb`](kml_to_fgfp::StepClimb).
+fn step_climb_decoder(code: &str) -> Result<StepClimb, Box<dyn Error>> {
+    let (at, altitude) = match code.split_once('/') {
+        Some(data) => data,
+        None => return Err(format!("Invalid step climb `{code}`").into()),
+    };
+
+    let at = at.trim();
+    let at = match at.to_uppercase().strip_suffix("NM") {
+        Some(distance) => StepAt::Distance(distance.trim().parse()?),
+        None => StepAt::Waypoint(at.to_string()),
+    };
+
+    let altitude = match altitude_decoder(altitude)? {
+        altitude if altitude > 0.0 => altitude,
+        _ => {
+            return Err(format!(
+                "Invalid step climb `{code}`, its altitude has to be above sea level"
+            )
+            .into())
+        }
+    };
+
+    Ok(StepClimb { at, altitude })
+}
+
+/// Chooses the runway of an `airport` with the most headwind for the wind of its `metar`. The
+/// runways come from the thresholds of the `scenery` or, if it doesn't have them, from `apt.dat`.
+fn choose_runway(
+    airport: &mut Airport,
+    metar: &Metar,
+    airports: Option<&AirportDatabase>,
+    scenery: &[PathBuf],
+) -> Result<(), Box<dyn Error>> {
+    let runways: Vec<(String, f64)> = match RunwayThresholds::find_in(scenery, &airport.ident)? {
+        Some(thresholds) => thresholds
+            .thresholds
+            .iter()
+            .map(|t| (t.runway.clone(), t.heading))
+            .collect(),
+        None => airports
+            .and_then(|db| db.get(&airport.ident))
+            .map(|data| {
+                data.runways
+                    .iter()
+                    .filter(|r| !r.is_helipad())
+                    .map(|r| (r.ident.clone(), r.heading))
+                    .collect()
+            })
+            .unwrap_or_default(),
+    };
+
+    let wind = match metar::choose_runway(&runways, &metar.wind) {
+        Some(wind) => wind,
+        None => {
+            eprintln!(
+                "\x1B[01;33mIgnoring\x1B[00m the METAR of \x1B[00;01m{}\x1B[00m: its runways aren't known, they're found with --scenery or --fg-root",
+                airport.ident
+            );
+            return Ok(());
+        }
+    };
+
+    airport.runway = Some(wind.runway.clone());
+    airport.wind = Some(wind);
+
+    // Fill in the threshold of the chosen runway.
+    if let Some(airports) = airports {
+        airports.resolve(airport)?;
+    }
+
+    Ok(())
+}
+
+/// Decodes the METARs of a `--metar` option, which can be a METAR or a file with one per line.
+/// The lines of the file that aren't METARs, like the dates of NOAA's files, are skipped.
+fn metar_decoder(code: &str) -> Result<Vec<Metar>, Box<dyn Error>> {
+    let path = Path::new(code);
+
+    if path.is_file() {
+        let metars = fs::read_to_string(path)?
+            .lines()
+            .filter_map(|line| line.parse().ok())
+            .collect();
+        return Ok(metars);
+    }
+
+    Ok(vec![code.parse()?])
+}
+
+/// Applies the `edits` to the user's library of waypoints at `path`, saving it when it changes, and
+/// prints it when it's `listed`. Returns the library.
+fn edit_user_waypoints(
+    path: &Path,
+    edits: &[WaypointEdit],
+    listed: bool,
+) -> Result<UserWaypoints, Box<dyn Error>> {
+    let mut library = UserWaypoints::load(path)?;
+
+    for edit in edits {
+        match edit {
+            WaypointEdit::Add(waypoint) => {
+                let action = match library.insert(waypoint.clone()) {
+                    Some(_) => "Replaced",
+                    None => "Added",
+                };
+                eprintln!(
+                    "\x1B[01m{action}\x1B[00m the waypoint {} in {}",
+                    waypoint.ident,
+                    path.display()
+                );
+            }
+            WaypointEdit::Remove(ident) => match library.remove(ident) {
+                Some(waypoint) => eprintln!(
+                    "\x1B[01mRemoved\x1B[00m the waypoint {} from {}",
+                    waypoint.ident,
+                    path.display()
+                ),
+                None => {
+                    return Err(format!("There's no waypoint {ident} in {}", path.display()).into())
+                }
+            },
+        }
+    }
+
+    if !edits.is_empty() {
+        library.save(path)?;
+    }
+
+    if listed {
+        println!(
+            "{:<8} {:>10} {:>11} {:>8} NOTES",
+            "IDENT", "LAT", "LON", "ALT"
+        );
+
+        for waypoint in library.iter() {
+            let altitude = waypoint
+                .altitude_ft
+                .map(|a| format!("{a:.0}"))
+                .unwrap_or_default();
+
+            println!(
+                "{:<8} {:>10.5} {:>11.5} {:>8} {}",
+                waypoint.ident,
+                waypoint.coordinates.lat,
+                waypoint.coordinates.lon,
+                altitude,
+                waypoint.notes
+            );
+        }
+    }
+
+    Ok(library)
+}
+
+/// Checks the `requested` procedures of an `airport` in the first `procedures.xml` file of the
+/// `directories`, printing its
+/// procedures when they're `listed`. Returns the fixes of the procedures when they're `expanded`,
+/// otherwise they're set in the airport.
+fn apply_procedures(
+    directories: &[PathBuf],
+    airport: &mut Option<Airport>,
+    role: &str,
+    requested: &[&(ProcedureKind, String)],
+    listed: bool,
+    expanded: bool,
+) -> Result<Vec<ProcedureFix>, Box<dyn Error>> {
+    let airport = match (airport, requested.first()) {
+        (Some(airport), _) => airport,
+        (None, None) => return Ok(Vec::new()),
+        (None, Some((kind, _))) => {
+            return Err(format!("The {kind} needs the {role} airport").into());
+        }
+    };
+
+    let procedures = match AirportProcedures::find_in(directories, &airport.ident) {
+        Ok(procedures) => procedures,
+        // Only listing them isn't worth failing.
+        Err(e) if requested.is_empty() => {
+            eprintln!("\x1B[01;33mWarning\x1B[00m: {e}");
+            return Ok(Vec::new());
+        }
+        Err(e) => return Err(e),
+    };
+
+    if listed {
+        print_procedures(&procedures);
+    }
+
+    let mut procedure_fixes: Vec<ProcedureFix> = Vec::new();
+    for (kind, value) in requested {
+        let (name, transition) = procedure_decoder(*kind, value);
+        let fixes = procedures.find(*kind, name, transition, airport.runway.as_deref())?;
+
+        if expanded {
+            // The STAR and the approach share the fix where they meet.
+            let shared = match (procedure_fixes.last(), fixes.first()) {
+                (Some(last), Some(first)) => last.ident == first.ident,
+                _ => false,
+            };
+            procedure_fixes.extend(fixes.into_iter().skip(shared as usize));
+            continue;
+        }
+
+        match kind {
+            ProcedureKind::Approach => airport.approach = Some(name.to_uppercase()),
+            _ => {
+                airport.procedure = Some(name.to_uppercase());
+                airport.transition = transition.map(|t| t.to_uppercase());
+            }
+        }
+    }
+
+    Ok(procedure_fixes)
+}
+
+/// Prints the procedures of an airport, with their runways and transitions.
+fn print_procedures(procedures: &AirportProcedures) {
+    for kind in [
+        ProcedureKind::Sid,
+        ProcedureKind::Star,
+        ProcedureKind::Approach,
+    ] {
+        for procedure in procedures.list(kind) {
+            let transitions: Vec<&str> = procedure
+                .transitions
+                .iter()
+                .map(|t| t.name.as_str())
+                .collect();
+
+            println!(
+                "{:<8} {:<8} {:<8} {:<16} {}",
+                procedures.ident,
+                kind.to_string(),
+                procedure.name,
+                match procedure.runways[..] {
+                    [] => String::from("all"),
+                    _ => procedure.runways.join(" "),
+                },
+                transitions.join(" ")
+            );
+        }
+    }
+}
+
+/// Decodes a procedure into its name and transition. The transition of a SID comes after it, like
+/// `ANDO1A.ARSOT`, and the transition of an arrival comes before it, like `GESOL.ARDO1B`.
+fn procedure_decoder(kind: ProcedureKind, code: &str) -> (&str, Option<&str>) {
+    match (kind, code.split_once('.')) {
+        (_, None) => (code.trim(), None),
+        (ProcedureKind::Sid, Some((name, transition))) => (name.trim(), Some(transition.trim())),
+        (_, Some((transition, name))) => (name.trim(), Some(transition.trim())),
+    }
+}
+
+/// Adds a procedure given with an airport to the `procedures`, unless its kind was already given
+/// with an option.
+fn add_procedure(
+    procedures: &mut Vec<(ProcedureKind, String)>,
+    kind: ProcedureKind,
+    name: String,
+) -> Result<(), Box<dyn Error>> {
+    if procedures.iter().any(|(k, _)| *k == kind) {
+        return Err(format!("The {kind} is given twice, with the airport and as an option").into());
+    }
+
+    procedures.push((kind, name));
     Ok(())
 }
 
-/// Decodes a string into an [`Airport`](kml_to_fgfp::Airport). Such that, for example, the string
-/// `SAEZ/11` refers to the airport SAEZ and runway 11.
-fn airport_decoder(code: &str) -> Airport {
-    let mut ident = String::from("ICAO");
-    let mut runway = None;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kml_to_fgfp::geodesy::Coordinates;
+
+    const APT_DAT: &str = "I\n1100 Version\n\n\
+        1 20 0 0 SAEZ Ministro Pistarini\n\
+        100 45 1 0 0.25 0 2 1 11 -34.81340 -58.56280 0 0 3 0 0 0 29 -34.82857 -58.52217 0 0 3 0 0 0\n\
+        102 H1 -34.81900 -58.54000 45.00 20.00 20.00 1 0 0 0.25 0\n\
+        99\n";
+
+    fn choose(metar: &str) -> Airport {
+        let airports = AirportDatabase::from_reader(APT_DAT.as_bytes()).unwrap();
+        let mut airport: Airport = "SAEZ".parse().unwrap();
+        choose_runway(&mut airport, &metar.parse().unwrap(), Some(&airports), &[]).unwrap();
+
+        airport
+    }
+
+    #[test]
+    fn chooses_the_runway_with_the_most_headwind() {
+        let airport = choose("SAEZ 011200Z 29015KT 9999");
+
+        assert_eq!(airport.runway.as_deref(), Some("29"));
+        assert!(airport.wind.unwrap().headwind_kt > 14.0);
+        // The threshold of the chosen runway is filled in.
+        assert_eq!(
+            airport.threshold,
+            Some(Coordinates {
+                lon: -58.52217,
+                lat: -34.82857
+            })
+        );
+
+        // The helipad is into the wind, but it isn't a runway.
+        let airport = choose("SAEZ 011200Z 04515KT 9999");
+        assert_eq!(airport.runway.as_deref(), Some("11"));
+    }
+
+    #[test]
+    fn chooses_a_runway_with_tailwind_on_every_runway() {
+        // A variable wind is a tailwind on every runway, so they tie and the first one is kept.
+        let airport = choose("SAEZ 011200Z VRB08KT 9999");
+
+        assert_eq!(airport.runway.as_deref(), Some("11"));
+        assert_eq!(airport.wind.unwrap().headwind_kt, -8.0);
+    }
+
+    #[test]
+    fn doesnt_choose_a_runway_without_runways() {
+        let mut airport: Airport = "SUMU".parse().unwrap();
+        let metar = "SUMU 011200Z 27010KT".parse().unwrap();
+        choose_runway(&mut airport, &metar, None, &[]).unwrap();
+
+        assert_eq!(airport.runway, None);
+        assert_eq!(airport.wind, None);
+    }
 
-    if code.contains('/') {
-        let data: Vec<&str> = code.split('/').map(|d| d.trim()).collect();
+    /// Builds the config of the command line `args`, with the input and output files.
+    fn build(args: &[&str]) -> Result<Config, Box<dyn Error>> {
+        let args = ["kml_to_fgfp"]
+            .iter()
+            .chain(args)
+            .chain(&["route.kml", "route.fgfp"])
+            .map(|a| a.to_string());
 
-        ident = String::from(data[0]);
-        runway = Some(String::from(data[1]));
+        Config::build(args)
     }
 
-    Airport { ident, runway }
+    #[test]
+    fn decodes_step_climbs() {
+        let step = step_climb_decoder("arsot/FL390").unwrap();
+        assert_eq!(step.at, StepAt::Waypoint(String::from("arsot")));
+        assert_eq!(step.altitude, 39000.0);
+
+        let step = step_climb_decoder("1200NM/41000").unwrap();
+        assert_eq!(step.at, StepAt::Distance(1200.0));
+        assert_eq!(step.altitude, 41000.0);
+
+        for code in [
+            "ARSOT/0",
+            "ARSOT/-2000",
+            "ARSOT/FL000",
+            "ARSOT",
+            "ARSOT/HIGH",
+        ] {
+            assert!(step_climb_decoder(code).is_err(), "{code}");
+        }
+    }
+
+    #[test]
+    fn rejects_step_climbs_below_the_cruise_altitude() {
+        let config = build(&["--step=ARSOT/FL390", "--cruise=FL370"]).unwrap();
+        assert_eq!(config.options.step_climbs.len(), 1);
+
+        // However the options are ordered.
+        for args in [
+            ["--step=ARSOT/FL350", "--cruise=FL370"],
+            ["--cruise=FL370", "--step=ARSOT/FL350"],
+        ] {
+            match build(&args) {
+                Ok(_) => panic!("{args:?} was accepted"),
+                Err(e) => assert_eq!(
+                    e.to_string(),
+                    "The step climb to 35000 ft is below the cruise altitude of 37000 ft"
+                ),
+            }
+        }
+
+        // Without --cruise, there is nothing to compare the steps with.
+        assert!(build(&["--step=ARSOT/FL350", "--step=1200NM/FL330"]).is_ok());
+    }
+
+    #[test]
+    fn checks_the_transition_altitude() {
+        let config = build(&["--transition=FL180"]).unwrap();
+        assert_eq!(config.options.transition_altitude, Some(18000.0));
+        assert!(build(&["--transition=1000"]).is_ok());
+
+        for value in ["0", "-3000", "999", "FL250", "TA"] {
+            assert!(
+                build(&[&format!("--transition={value}")]).is_err(),
+                "{value}"
+            );
+        }
+    }
+
+    #[test]
+    fn decodes_the_transitions_of_the_procedures() {
+        assert_eq!(
+            procedure_decoder(ProcedureKind::Sid, "ANDO1A.ARSOT"),
+            ("ANDO1A", Some("ARSOT"))
+        );
+        // STARs and approaches are written the other way around, in the order they're flown.
+        assert_eq!(
+            procedure_decoder(ProcedureKind::Star, "ESL.DORV2B"),
+            ("DORV2B", Some("ESL"))
+        );
+        assert_eq!(
+            procedure_decoder(ProcedureKind::Approach, "DORVO.ILS11"),
+            ("ILS11", Some("DORVO"))
+        );
+
+        assert_eq!(
+            procedure_decoder(ProcedureKind::Star, " DORV2B "),
+            ("DORV2B", None)
+        );
+    }
 }
@@ -0,0 +1,171 @@
+use std::{fmt, str::FromStr};
+
+/// The FlightGear release the .fgfp is written for. Each profile controls which properties and
+/// waypoint types are written, older releases don't understand everything newer ones do.
+///
+/// It can be parsed from strings like `2018.3`, `2020.3`, `2024.1` or `legacy`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub enum FgTarget {
+    /// The legacy version-1 route format, still read by some aircraft FMS add-ons.
+    Legacy,
+    V2018_3,
+    V2020_3,
+    /// The latest release, which supports every feature of this crate.
+    #[default]
+    V2024_1,
+}
+
+/// A feature of the .fgfp that some [`FgTarget`] profiles can't represent.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Feature {
+    /// The `flight-rules`, `flight-type` and `estimated-duration-minutes` header properties.
+    FlightInfo,
+    /// Airport waypoints with a `runway` type in the route.
+    RunwayWaypoints,
+    /// The `alt-restrict` of the waypoints.
+    AltRestrict,
+    /// The `speed-restrict` of the waypoints.
+    SpeedRestrict,
+    /// Waypoints with the `via` type.
+    Via,
+    /// Waypoints with the `hold` type.
+    Hold,
+    /// Waypoints with the `discontinuity` type.
+    Discontinuity,
+    /// Altitudes written as a `flight-level`, instead of in feet.
+    FlightLevel,
+}
+
+impl FgTarget {
+    /// The version of the route format used by the profile.
+    pub fn format_version(&self) -> u8 {
+        match self {
+            FgTarget::Legacy => 1,
+            _ => 2,
+        }
+    }
+
+    /// Checks if the profile can represent a feature.
+    pub fn supports(&self, feature: Feature) -> bool {
+        match feature {
+            Feature::FlightInfo | Feature::Discontinuity | Feature::FlightLevel => {
+                *self >= FgTarget::V2020_3
+            }
+            Feature::RunwayWaypoints
+            | Feature::AltRestrict
+            | Feature::SpeedRestrict
+            | Feature::Via
+            | Feature::Hold => *self >= FgTarget::V2018_3,
+        }
+    }
+}
+
+impl FromStr for FgTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "legacy" | "1" => Ok(FgTarget::Legacy),
+            "2018.3" => Ok(FgTarget::V2018_3),
+            "2020.3" => Ok(FgTarget::V2020_3),
+            "2024.1" => Ok(FgTarget::V2024_1),
+            _ => Err(format!(
+                "Unknown FlightGear target `{s}`, expected legacy, 2018.3, 2020.3 or 2024.1"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for FgTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FgTarget::Legacy => "legacy (version 1)",
+            FgTarget::V2018_3 => "2018.3",
+            FgTarget::V2020_3 => "2020.3",
+            FgTarget::V2024_1 => "2024.1",
+        })
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Feature::FlightInfo => "flight rules, type and duration",
+            Feature::RunwayWaypoints => "runway waypoints",
+            Feature::AltRestrict => "altitude restrictions",
+            Feature::SpeedRestrict => "speed restrictions",
+            Feature::Via => "airway (via) waypoints",
+            Feature::Hold => "holds",
+            Feature::Discontinuity => "discontinuities",
+            Feature::FlightLevel => "flight levels",
+        })
+    }
+}
+
+/// Prints a warning about a feature the target can't represent.
+pub fn warn_unsupported(target: FgTarget, feature: Feature) {
+    eprintln!(
+        "\x1B[01;33mWarning\x1B[00m: FlightGear {target} can't represent {feature}, they won't be written"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGETS: [FgTarget; 4] = [
+        FgTarget::Legacy,
+        FgTarget::V2018_3,
+        FgTarget::V2020_3,
+        FgTarget::V2024_1,
+    ];
+
+    #[test]
+    fn supports_the_features_of_each_release() {
+        // The features supported by legacy, 2018.3, 2020.3 and 2024.1.
+        let features = [
+            (Feature::FlightInfo, [false, false, true, true]),
+            (Feature::RunwayWaypoints, [false, true, true, true]),
+            (Feature::AltRestrict, [false, true, true, true]),
+            (Feature::SpeedRestrict, [false, true, true, true]),
+            (Feature::Via, [false, true, true, true]),
+            (Feature::Hold, [false, true, true, true]),
+            (Feature::Discontinuity, [false, false, true, true]),
+            (Feature::FlightLevel, [false, false, true, true]),
+        ];
+
+        for (feature, supported) in features {
+            for (target, supported) in TARGETS.iter().zip(supported) {
+                assert_eq!(target.supports(feature), supported, "{target} {feature}");
+            }
+        }
+    }
+
+    #[test]
+    fn uses_the_legacy_format_only_for_legacy() {
+        let versions: Vec<u8> = TARGETS.iter().map(|t| t.format_version()).collect();
+        assert_eq!(versions, [1, 2, 2, 2]);
+    }
+
+    #[test]
+    fn parses_the_releases() {
+        let parsed: Vec<FgTarget> = ["legacy", "1", " 2018.3", "2020.3", "2024.1", "LEGACY"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        assert_eq!(
+            parsed,
+            [
+                FgTarget::Legacy,
+                FgTarget::Legacy,
+                FgTarget::V2018_3,
+                FgTarget::V2020_3,
+                FgTarget::V2024_1,
+                FgTarget::Legacy
+            ]
+        );
+
+        assert!("2019.1".parse::<FgTarget>().is_err());
+        assert_eq!(FgTarget::default(), FgTarget::V2024_1);
+    }
+}
@@ -0,0 +1,101 @@
+//! Transition altitudes, above which altitudes are flight levels.
+//!
+//! The transition altitude depends on the country, and sometimes on the airport. The values here
+//! are the usual ones of each country, found by the prefix of the airport's ICAO code.
+
+/// The usual transition altitude in feet of the countries, by the prefix of their ICAO codes.
+/// Longer prefixes come first, so they're found before the shorter ones.
+const TRANSITION_ALTITUDES: &[(&str, f64)] = &[
+    // Alaska and Hawaii, with the rest of the United States.
+    ("PA", 18000.0),
+    ("PH", 18000.0),
+    ("NZ", 13000.0),
+    ("SA", 3000.0),
+    ("EG", 3000.0),
+    ("EH", 3000.0),
+    ("EB", 4500.0),
+    ("ED", 5000.0),
+    ("LF", 5000.0),
+    ("EI", 5000.0),
+    ("RJ", 14000.0),
+    ("VH", 9000.0),
+    ("WS", 11000.0),
+    ("K", 18000.0),
+    ("C", 18000.0),
+    ("Y", 10000.0),
+];
+
+/// Returns the usual transition altitude in feet for the airport with the ICAO code `icao`, if
+/// it's known.
+///
+/// # Example
+///
+/// ```
+/// # use kml_to_fgfp::transition::transition_altitude;
+/// assert_eq!(transition_altitude("SAEZ"), Some(3000.0));
+/// assert_eq!(transition_altitude("YSSY"), Some(10000.0));
+/// assert_eq!(transition_altitude("ZZZZ"), None);
+/// ```
+pub fn transition_altitude(icao: &str) -> Option<f64> {
+    let icao = icao.trim().to_uppercase();
+
+    TRANSITION_ALTITUDES
+        .iter()
+        .find(|(prefix, _)| icao.starts_with(prefix))
+        .map(|(_, altitude)| *altitude)
+}
+
+/// Writes an altitude in feet as a flight level like `FL350` when it's above the `transition`
+/// altitude, or in feet otherwise.
+///
+/// # Example
+///
+/// ```
+/// # use kml_to_fgfp::transition::format_altitude;
+/// assert_eq!(format_altitude(35000.0, Some(3000.0)), "FL350");
+/// assert_eq!(format_altitude(2500.0, Some(3000.0)), "2500 ft");
+/// assert_eq!(format_altitude(35000.0, None), "35000 ft");
+/// ```
+pub fn format_altitude(feet: f64, transition: Option<f64>) -> String {
+    match transition {
+        Some(transition) if feet > transition => format!("FL{:03.0}", feet / 100.0),
+        _ => format!("{feet:.0} ft"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_transition_altitude_by_the_prefix_of_the_country() {
+        let cases = [
+            ("KJFK", Some(18000.0)),
+            ("CYYZ", Some(18000.0)),
+            // Alaska and Hawaii, outside the K prefix.
+            ("PANC", Some(18000.0)),
+            ("PHNL", Some(18000.0)),
+            ("NZAA", Some(13000.0)),
+            ("YSSY", Some(10000.0)),
+            ("egll", Some(3000.0)),
+            ("EDDF", Some(5000.0)),
+            ("RJTT", Some(14000.0)),
+            // Uruguay isn't Argentina, even if they share the S.
+            ("SUMU", None),
+            ("LEMD", None),
+            ("", None),
+        ];
+
+        for (icao, altitude) in cases {
+            assert_eq!(transition_altitude(icao), altitude, "{icao}");
+        }
+    }
+
+    #[test]
+    fn writes_flight_levels_only_above_the_transition_altitude() {
+        assert_eq!(format_altitude(18000.0, Some(18000.0)), "18000 ft");
+        assert_eq!(format_altitude(18500.0, Some(18000.0)), "FL185");
+        assert_eq!(format_altitude(5000.0, Some(3000.0)), "FL050");
+        assert_eq!(format_altitude(2999.6, Some(3000.0)), "3000 ft");
+    }
+}
@@ -0,0 +1,115 @@
+//! Routes whose airports are only in the .kml file, checked like the given ones.
+
+use kml_to_fgfp::{
+    find_airports, geodesy::Coordinates, navdata::AirportDatabase, transform_route, EmitterConfig,
+    EventReader, RouteOptions,
+};
+
+const SAEZ_SUMU: &str = include_str!("fixtures/SAEZSUMU.kml");
+const SAEZ_SADP: &str = include_str!("fixtures/SAEZSADP.kml");
+
+const APT_DAT: &str = "I\n1100 Version\n\n\
+    1 20 0 0 SAEZ Ministro Pistarini\n\
+    1302 datum_lat -34.8222\n\
+    1302 datum_lon -58.5358\n\
+    100 45 1 0 0.25 0 2 1 11 -34.81340 -58.56280 0 0 3 0 0 0 29 -34.82857 -58.52217 0 0 3 0 0 0\n\
+    99\n";
+
+#[test]
+fn finds_the_airports_of_the_kml() {
+    let (departure, destination) =
+        find_airports(EventReader::new(SAEZ_SUMU.as_bytes()), &None, &None, false).unwrap();
+
+    let departure = departure.unwrap();
+    assert_eq!(departure.ident, "SAEZ");
+    assert_eq!(
+        departure.coordinates,
+        Some(Coordinates {
+            lon: -58.53,
+            lat: -34.82
+        })
+    );
+    assert_eq!(destination.unwrap().ident, "SUMU");
+}
+
+#[test]
+fn checks_the_airports_of_the_kml() {
+    let (departure, destination) =
+        find_airports(EventReader::new(SAEZ_SUMU.as_bytes()), &None, &None, false).unwrap();
+    let (mut departure, mut destination) = (departure.unwrap(), destination.unwrap());
+
+    // Like a runway chosen for the wind of a METAR.
+    departure.runway = Some(String::from("11"));
+
+    let airports = AirportDatabase::from_reader(APT_DAT.as_bytes()).unwrap();
+    airports.resolve(&mut departure).unwrap();
+    assert!(airports.resolve(&mut destination).is_err());
+
+    let mut output = Vec::new();
+    let mut writer = EmitterConfig::new().create_writer(&mut output);
+    let report = transform_route(
+        EventReader::new(SAEZ_SUMU.as_bytes()),
+        &mut writer,
+        &Some(departure),
+        &Some(destination),
+        &RouteOptions::default(),
+    )
+    .unwrap();
+
+    // The departure keeps what the database found, and the destination its Placemark.
+    let departure = report.departure.unwrap();
+    assert_eq!(departure.runway.as_deref(), Some("11"));
+    assert_eq!(departure.name.as_deref(), Some("Ministro Pistarini"));
+    assert_eq!(
+        departure.threshold,
+        Some(Coordinates {
+            lon: -58.5628,
+            lat: -34.8134
+        })
+    );
+    assert_eq!(
+        report.destination.unwrap().coordinates,
+        Some(Coordinates {
+            lon: -56.03,
+            lat: -34.83
+        })
+    );
+
+    // The EZE Placemark is the departure airport under its IATA code.
+    let idents: Vec<&str> = report.legs.iter().map(|leg| leg.to.as_str()).collect();
+    assert_eq!(idents, ["ARSOT", "DELTA", "SUMU"]);
+}
+
+#[test]
+fn drops_the_iata_codes_of_apt_dat() {
+    let (departure, destination) =
+        find_airports(EventReader::new(SAEZ_SADP.as_bytes()), &None, &None, false).unwrap();
+    let (mut departure, mut destination) = (departure.unwrap(), destination.unwrap());
+
+    // El Palomar's IATA code is only in apt.dat.
+    let apt_dat = APT_DAT.replace(
+        "99\n",
+        "1 59 0 0 SADP El Palomar\n\
+        1302 iata_code EPA\n\
+        100 30 1 0 0.25 0 2 1 16 -34.59900 -58.61800 0 0 3 0 0 0 34 -34.62000 -58.60700 0 0 3 0 0 0\n\
+        99\n",
+    );
+    let airports = AirportDatabase::from_reader(apt_dat.as_bytes()).unwrap();
+    airports.resolve(&mut departure).unwrap();
+    airports.resolve(&mut destination).unwrap();
+    assert_eq!(destination.iata.as_deref(), Some("EPA"));
+
+    let mut output = Vec::new();
+    let mut writer = EmitterConfig::new().create_writer(&mut output);
+    let report = transform_route(
+        EventReader::new(SAEZ_SADP.as_bytes()),
+        &mut writer,
+        &Some(departure),
+        &Some(destination),
+        &RouteOptions::default(),
+    )
+    .unwrap();
+
+    let idents: Vec<&str> = report.legs.iter().map(|leg| leg.to.as_str()).collect();
+    assert_eq!(idents, ["ALPHA", "BRAVO", "SADP"]);
+}
@@ -0,0 +1,110 @@
+//! Routes across the Pacific, whose legs cross the antimeridian.
+
+use kml_to_fgfp::{
+    transform_route, Densify, EmitterConfig, EventReader, Report, RouteOptions, Simplify,
+};
+
+const NZAA_PHNL: &str = include_str!("fixtures/NZAAPHNL.kml");
+const TRACK: &str = include_str!("fixtures/antimeridian_track.kml");
+
+/// Converts a .kml file, returning the report and the positions of the written waypoints.
+fn convert(kml: &str, options: &RouteOptions) -> (Report, Vec<(f64, f64)>) {
+    let mut output = Vec::new();
+    let mut writer = EmitterConfig::new().create_writer(&mut output);
+    let report = transform_route(
+        EventReader::new(kml.as_bytes()),
+        &mut writer,
+        &None,
+        &None,
+        options,
+    )
+    .unwrap();
+
+    let fgfp = String::from_utf8(output).unwrap();
+    let values = |element: &str| -> Vec<f64> {
+        let opening = format!("<{element} type=\"double\">");
+        fgfp.split(&opening)
+            .skip(1)
+            .map(|rest| rest.split('<').next().unwrap().parse().unwrap())
+            .collect()
+    };
+
+    let positions = values("lon").into_iter().zip(values("lat")).collect();
+    (report, positions)
+}
+
+/// Checks that a longitude is in the Pacific, between Auckland and Honolulu.
+fn is_in_the_pacific(lon: f64) -> bool {
+    !(-157.0..=174.0).contains(&lon)
+}
+
+#[test]
+fn measures_the_legs_across_the_antimeridian() {
+    let (report, positions) = convert(NZAA_PHNL, &RouteOptions::default());
+
+    let crossing: Vec<(&str, &str)> = report
+        .legs
+        .iter()
+        .filter(|leg| leg.crosses_antimeridian)
+        .map(|leg| (leg.from.as_str(), leg.to.as_str()))
+        .collect();
+    assert_eq!(crossing, [("KAGAN", "ADNAP")]);
+
+    let total = report.legs.last().unwrap().cumulative_nm;
+    assert!((total - 3827.5).abs() < 0.5, "{total}");
+    assert!(report
+        .legs
+        .iter()
+        .all(|leg| (20.0..30.0).contains(&leg.initial_course)));
+    assert!(positions.iter().all(|(lon, _)| is_in_the_pacific(*lon)));
+}
+
+#[test]
+fn densifies_legs_across_the_antimeridian() {
+    let options = RouteOptions {
+        densify: Some(Densify {
+            max_leg_nm: 200.0,
+            max_track_change: None,
+        }),
+        ..Default::default()
+    };
+    let (report, positions) = convert(NZAA_PHNL, &options);
+
+    // The legs from and to the airports aren't in the route, so they aren't densified.
+    let en_route = &report.legs[1..report.legs.len() - 1];
+    assert_eq!(report.generated.len(), 12);
+    assert!(en_route.iter().all(|leg| leg.distance_nm <= 200.0));
+
+    let crossing: Vec<(&str, &str)> = en_route
+        .iter()
+        .filter(|leg| leg.crosses_antimeridian)
+        .map(|leg| (leg.from.as_str(), leg.to.as_str()))
+        .collect();
+    assert_eq!(crossing, [("KAGAN", "GC001")]);
+
+    // The new waypoints are on the great circle, so the route isn't any longer.
+    let total = report.legs.last().unwrap().cumulative_nm;
+    assert!((total - 3827.5).abs() < 0.5, "{total}");
+    assert!(positions.iter().all(|(lon, _)| is_in_the_pacific(*lon)));
+}
+
+#[test]
+fn simplifies_paths_across_the_antimeridian() {
+    let options = RouteOptions {
+        read_paths: true,
+        simplify: Some(Simplify {
+            tolerance_nm: 1.0,
+            tolerance_ft: 100.0,
+        }),
+        ..Default::default()
+    };
+    let (report, positions) = convert(TRACK, &options);
+
+    // Only the ends and the corner past the antimeridian are left.
+    assert_eq!(report.simplified, 9);
+    let expected = [(172.0, -30.0), (-178.0, -26.0), (-170.0, -18.0)];
+    assert_eq!(positions.len(), expected.len(), "{positions:?}");
+    for ((lon, lat), (expected_lon, expected_lat)) in positions.iter().zip(expected) {
+        assert!((lon - expected_lon).abs() < 1e-6 && (lat - expected_lat).abs() < 1e-6);
+    }
+}